@@ -1,122 +0,0 @@
-; ModuleID = 'lib.c'
-source_filename = "lib.c"
-target datalayout = "e-m:o-i64:64-i128:128-n32:64-S128-Fn32"
-target triple = "arm64-apple-macosx15.0.0"
-
-@.str = private unnamed_addr constant [3 x i8] c"%d\00", align 1
-@.str.1 = private unnamed_addr constant [5 x i8] c"true\00", align 1
-@.str.2 = private unnamed_addr constant [6 x i8] c"false\00", align 1
-@.str.3 = private unnamed_addr constant [5 x i8] c"unit\00", align 1
-@.str.4 = private unnamed_addr constant [3 x i8] c"%s\00", align 1
-@.str.5 = private unnamed_addr constant [17 x i8] c"division by zero\00", align 1
-
-; Function Attrs: noinline nounwind optnone ssp uwtable(sync)
-define void @__print_int__(i32 noundef %0) #0 {
-  %2 = alloca i32, align 4
-  store i32 %0, ptr %2, align 4
-  %3 = load i32, ptr %2, align 4
-  %4 = call i32 (ptr, ...) @printf(ptr noundef @.str, i32 noundef %3)
-  ret void
-}
-
-declare i32 @printf(ptr noundef, ...) #1
-
-; Function Attrs: noinline nounwind optnone ssp uwtable(sync)
-define void @__print_bool__(i32 noundef %0) #0 {
-  %2 = alloca i32, align 4
-  store i32 %0, ptr %2, align 4
-  %3 = load i32, ptr %2, align 4
-  %4 = icmp ne i32 %3, 0
-  %5 = zext i1 %4 to i64
-  %6 = select i1 %4, ptr @.str.1, ptr @.str.2
-  %7 = call i32 (ptr, ...) @printf(ptr noundef %6)
-  ret void
-}
-
-; Function Attrs: noinline nounwind optnone ssp uwtable(sync)
-define void @__print_unit__() #0 {
-  %1 = call i32 (ptr, ...) @printf(ptr noundef @.str.3)
-  ret void
-}
-
-; Function Attrs: noinline nounwind optnone ssp uwtable(sync)
-define void @__print_string__(ptr noundef %0) #0 {
-  %2 = alloca ptr, align 8
-  store ptr %0, ptr %2, align 8
-  %3 = load ptr, ptr %2, align 8
-  %4 = call i32 (ptr, ...) @printf(ptr noundef @.str.4, ptr noundef %3)
-  ret void
-}
-
-; Function Attrs: noinline nounwind optnone ssp uwtable(sync)
-define i32 @__pow__(i32 noundef %0, i32 noundef %1) #0 {
-  %3 = alloca i32, align 4
-  %4 = alloca i32, align 4
-  %5 = alloca i32, align 4
-  store i32 %0, ptr %3, align 4
-  store i32 %1, ptr %4, align 4
-  store i32 1, ptr %5, align 4
-  br label %6
-
-6:                                                ; preds = %10, %2
-  %7 = load i32, ptr %4, align 4
-  %8 = add nsw i32 %7, -1
-  store i32 %8, ptr %4, align 4
-  %9 = icmp sgt i32 %7, 0
-  br i1 %9, label %10, label %14
-
-10:                                               ; preds = %6
-  %11 = load i32, ptr %3, align 4
-  %12 = load i32, ptr %5, align 4
-  %13 = mul nsw i32 %12, %11
-  store i32 %13, ptr %5, align 4
-  br label %6, !llvm.loop !6
-
-14:                                               ; preds = %6
-  %15 = load i32, ptr %5, align 4
-  ret i32 %15
-}
-
-; Function Attrs: noinline nounwind optnone ssp uwtable(sync)
-define i32 @__div__(i32 noundef %0, i32 noundef %1) #0 {
-  %3 = alloca i32, align 4
-  %4 = alloca i32, align 4
-  store i32 %0, ptr %3, align 4
-  store i32 %1, ptr %4, align 4
-  %5 = load i32, ptr %4, align 4
-  %6 = icmp eq i32 %5, 0
-  br i1 %6, label %7, label %9
-
-7:                                                ; preds = %2
-  %8 = call i32 @write(i32 noundef 2, ptr noundef @.str.5, i64 noundef 17)
-  call void @exit(i32 noundef 1) #3
-  unreachable
-
-9:                                                ; preds = %2
-  %10 = load i32, ptr %3, align 4
-  %11 = load i32, ptr %4, align 4
-  %12 = sdiv i32 %10, %11
-  ret i32 %12
-}
-
-declare i32 @write(i32 noundef, ptr noundef, i64 noundef) #1
-
-; Function Attrs: noreturn
-declare void @exit(i32 noundef) #2
-
-attributes #0 = { noinline nounwind optnone ssp uwtable(sync) "frame-pointer"="non-leaf" "no-trapping-math"="true" "probe-stack"="__chkstk_darwin" "stack-protector-buffer-size"="8" "target-cpu"="apple-m1" "target-features"="+aes,+altnzcv,+bti,+ccdp,+ccidx,+complxnum,+crc,+dit,+dotprod,+flagm,+fp-armv8,+fp16fml,+fptoint,+fullfp16,+jsconv,+lse,+neon,+pauth,+perfmon,+predres,+ras,+rcpc,+rdm,+sb,+sha2,+sha3,+specrestrict,+ssbs,+v8.1a,+v8.2a,+v8.3a,+v8.4a,+v8.5a,+v8a,+zcm,+zcz" }
-attributes #1 = { "frame-pointer"="non-leaf" "no-trapping-math"="true" "probe-stack"="__chkstk_darwin" "stack-protector-buffer-size"="8" "target-cpu"="apple-m1" "target-features"="+aes,+altnzcv,+bti,+ccdp,+ccidx,+complxnum,+crc,+dit,+dotprod,+flagm,+fp-armv8,+fp16fml,+fptoint,+fullfp16,+jsconv,+lse,+neon,+pauth,+perfmon,+predres,+ras,+rcpc,+rdm,+sb,+sha2,+sha3,+specrestrict,+ssbs,+v8.1a,+v8.2a,+v8.3a,+v8.4a,+v8.5a,+v8a,+zcm,+zcz" }
-attributes #2 = { noreturn "frame-pointer"="non-leaf" "no-trapping-math"="true" "probe-stack"="__chkstk_darwin" "stack-protector-buffer-size"="8" "target-cpu"="apple-m1" "target-features"="+aes,+altnzcv,+bti,+ccdp,+ccidx,+complxnum,+crc,+dit,+dotprod,+flagm,+fp-armv8,+fp16fml,+fptoint,+fullfp16,+jsconv,+lse,+neon,+pauth,+perfmon,+predres,+ras,+rcpc,+rdm,+sb,+sha2,+sha3,+specrestrict,+ssbs,+v8.1a,+v8.2a,+v8.3a,+v8.4a,+v8.5a,+v8a,+zcm,+zcz" }
-attributes #3 = { noreturn }
-
-!llvm.module.flags = !{!0, !1, !2, !3, !4}
-!llvm.ident = !{!5}
-
-!0 = !{i32 2, !"SDK Version", [2 x i32] [i32 15, i32 5]}
-!1 = !{i32 1, !"wchar_size", i32 4}
-!2 = !{i32 8, !"PIC Level", i32 2}
-!3 = !{i32 7, !"uwtable", i32 1}
-!4 = !{i32 7, !"frame-pointer", i32 1}
-!5 = !{!"Apple clang version 17.0.0 (clang-1700.0.13.5)"}
-!6 = distinct !{!6, !7}
-!7 = !{!"llvm.loop.mustprogress"}
@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+use crate::bytecode::instr::{Bytecode, Function, Instr, Position};
+use crate::semantic::captures::captured_vars;
+use crate::semantic::tail_calls::tail_calls;
+use crate::syntax::ast::*;
+
+// state of the function being compiled
+struct FunState<'a> {
+    code: Vec<Instr>,
+    positions: Vec<Position>,
+    params: usize,
+    locals: Vec<&'a str>, // variables in scope by slot, the innermost last
+    max_locals: usize,
+    tail_calls: HashSet<Span>,
+    captures: Vec<&'a Id>, // variables captured by a nested function
+    self_id: Option<&'a str>, // name of a nested function in its body
+}
+
+impl<'a> FunState<'a> {
+    fn new(params: Vec<&'a str>, tail_calls: HashSet<Span>) -> Self {
+        FunState {
+            code: vec![],
+            positions: vec![],
+            params: params.len(),
+            max_locals: params.len(),
+            locals: params,
+            tail_calls,
+            captures: vec![],
+            self_id: None,
+        }
+    }
+}
+
+pub struct BytecodeCompiler<'a> {
+    file: &'a str,
+    src: &'a str,
+    line_starts: Vec<usize>,
+    strings: Vec<String>,
+    string_ids: HashMap<&'a str, u32>,
+    globals: HashMap<&'a str, u32>,
+    fun_ids: HashMap<&'a str, u32>,
+    captures: HashMap<Span, Vec<&'a Id>>,
+    nested: Vec<Function>, // nested functions, which come after the initializer
+    fun: FunState<'a>,
+}
+
+impl<'a> BytecodeCompiler<'a> {
+
+    pub fn new(file: &'a str, src: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        BytecodeCompiler {
+            file,
+            src,
+            line_starts,
+            strings: vec![],
+            string_ids: HashMap::new(),
+            globals: HashMap::new(),
+            fun_ids: HashMap::new(),
+            captures: HashMap::new(),
+            nested: vec![],
+            fun: FunState::new(vec![], HashSet::new()),
+        }
+    }
+
+    pub fn compile(mut self, prog: &'a Program) -> Bytecode {
+        self.captures = captured_vars(prog);
+        let mut globals = vec![];
+        for decl in &prog.decls {
+            match &decl.value {
+                Decl::Var { id, .. } if id.value != "_" => {
+                    self.globals.insert(&id.value, globals.len() as u32);
+                    globals.push(id.value.clone());
+                }
+                Decl::Fun { id, .. } => {
+                    self.fun_ids.insert(&id.value, self.fun_ids.len() as u32);
+                }
+                _ => {}
+            }
+        }
+
+        let mut functions = vec![];
+        for decl in &prog.decls {
+            if let Decl::Fun { id, params, expr, .. } = &decl.value {
+                let params = params.iter().map(|param| param.value.as_str()).collect();
+                self.fun = FunState::new(params, tail_calls(&id.value, expr));
+                self.compile_expr(expr);
+                self.emit(Instr::Return, &expr.span);
+                functions.push(self.finish_function(&id.value, &decl.span));
+            }
+        }
+
+        // the globals are initialized by a function that is not visible to the program
+        self.fun = FunState::new(vec![], HashSet::new());
+        let mut init_span = 0..0;
+        for decl in &prog.decls {
+            if let Decl::Var { id, expr, .. } = &decl.value {
+                self.compile_expr(expr);
+                match self.globals.get(id.value.as_str()) {
+                    Some(global) => self.emit(Instr::StoreGlobal(*global), &decl.span),
+                    None => self.emit(Instr::Pop, &decl.span),
+                }
+                init_span = decl.span.clone();
+            }
+        }
+        self.emit(Instr::Unit, &init_span);
+        self.emit(Instr::Return, &init_span);
+        functions.push(self.finish_function("<init>", &(0..0)));
+        let init = functions.len() as u32 - 1;
+        functions.append(&mut self.nested);
+
+        Bytecode {
+            file: self.file.to_string(),
+            src: self.src.to_string(),
+            strings: self.strings,
+            globals,
+            init,
+            main: *self.fun_ids.get("main").expect("undefined function main"),
+            functions,
+        }
+    }
+
+    fn finish_function(&mut self, name: &str, span: &Span) -> Function {
+        let fun = std::mem::replace(&mut self.fun, FunState::new(vec![], HashSet::new()));
+        Function {
+            name: name.to_string(),
+            params: fun.params as u32,
+            captures: fun.captures.len() as u32,
+            locals: fun.max_locals as u32,
+            pos: self.position(span),
+            code: fun.code,
+            positions: fun.positions,
+        }
+    }
+
+    // compiles an expression that pushes its value on the stack
+    fn compile_expr(&mut self, expr: &'a Spanned<Expr>) {
+        let span = &expr.span;
+        match &expr.value {
+            Expr::Chain { lhs, rhs } => {
+                let scope = self.fun.locals.len();
+                self.compile_expr(lhs);
+                self.emit(Instr::Pop, span);
+                self.compile_expr(rhs);
+                // the variable declared by a let is only visible in the rhs, so its slot can be reused
+                self.fun.locals.truncate(scope);
+            }
+            Expr::Let { id, expr: init, .. } => {
+                self.compile_expr(init);
+                if id.value == "_" {
+                    self.emit(Instr::Pop, span);
+                } else {
+                    let slot = self.fun.locals.len();
+                    self.fun.locals.push(&id.value);
+                    self.fun.max_locals = self.fun.max_locals.max(slot + 1);
+                    self.emit(Instr::Store(slot as u32), span);
+                }
+                self.emit(Instr::Unit, span);
+            }
+            Expr::LetFun { id, params, expr: body, .. } => {
+                let captures = self.captures.remove(&id.span).unwrap_or_default();
+                for var in &captures {
+                    let instr = self.resolve(var);
+                    self.emit(instr, span);
+                }
+                let params = params.iter().map(|param| param.value.as_str()).collect();
+                let outer = std::mem::replace(&mut self.fun, FunState::new(params, tail_calls(&id.value, body)));
+                self.fun.captures = captures;
+                self.fun.self_id = Some(&id.value);
+                self.compile_expr(body);
+                self.emit(Instr::Return, &body.span);
+                let nested = self.finish_function(&id.value, span);
+                self.fun = outer;
+
+                // the initializer comes right after the top-level functions
+                let fun = (self.fun_ids.len() + 1 + self.nested.len()) as u32;
+                self.nested.push(nested);
+                self.emit(Instr::Closure(fun), span);
+                if id.value == "_" {
+                    self.emit(Instr::Pop, span);
+                } else {
+                    let slot = self.fun.locals.len();
+                    self.fun.locals.push(&id.value);
+                    self.fun.max_locals = self.fun.max_locals.max(slot + 1);
+                    self.emit(Instr::Store(slot as u32), span);
+                }
+                self.emit(Instr::Unit, span);
+            }
+            Expr::Set { lhs, expr: val } => {
+                match &lhs.value {
+                    Lhs::Var { id } => {
+                        self.compile_expr(val);
+                        let instr = match self.resolve(&id.value) {
+                            Instr::Load(slot) => Instr::Store(slot),
+                            Instr::LoadGlobal(global) => Instr::StoreGlobal(global),
+                            _ => panic!("cannot assign to function {}", id.value),
+                        };
+                        self.emit(instr, span);
+                    }
+                    Lhs::Index { lhs: arr, index } => {
+                        self.compile_lhs(arr);
+                        self.compile_expr(index);
+                        self.compile_expr(val);
+                        self.emit(Instr::SetIndex, &lhs.span);
+                    }
+                }
+                self.emit(Instr::Unit, span);
+            }
+            Expr::BinOp { lhs, op: Op::And, rhs } => {
+                self.compile_expr(lhs);
+                let to_false = self.emit_jump(Instr::JumpIfFalse(0), span);
+                self.compile_expr(rhs);
+                let to_end = self.emit_jump(Instr::Jump(0), span);
+                self.patch_jump(to_false);
+                self.emit(Instr::Bool(false), span);
+                self.patch_jump(to_end);
+            }
+            Expr::BinOp { lhs, op: Op::Or, rhs } => {
+                self.compile_expr(lhs);
+                let to_rhs = self.emit_jump(Instr::JumpIfFalse(0), span);
+                self.emit(Instr::Bool(true), span);
+                let to_end = self.emit_jump(Instr::Jump(0), span);
+                self.patch_jump(to_rhs);
+                self.compile_expr(rhs);
+                self.patch_jump(to_end);
+            }
+            Expr::BinOp { lhs, op, rhs } => {
+                self.compile_expr(lhs);
+                self.compile_expr(rhs);
+                let instr = match op {
+                    Op::Add => Instr::Add,
+                    Op::Sub => Instr::Sub,
+                    Op::Mul => Instr::Mul,
+                    Op::Div => Instr::Div,
+                    Op::Mod => Instr::Mod,
+                    Op::Pow => Instr::Pow,
+                    Op::Eq => Instr::Eq,
+                    Op::Neq => Instr::Neq,
+                    Op::Lt => Instr::Lt,
+                    Op::Leq => Instr::Leq,
+                    Op::Gt => Instr::Gt,
+                    Op::Geq => Instr::Geq,
+                    Op::And | Op::Or => unreachable!(),
+                };
+                self.emit(instr, span);
+            }
+            Expr::Not { expr } => {
+                self.compile_expr(expr);
+                self.emit(Instr::Not, span);
+            }
+            Expr::While { cond, expr: body } => {
+                let start = self.fun.code.len();
+                self.compile_expr(cond);
+                let to_end = self.emit_jump(Instr::JumpIfFalse(0), span);
+                self.compile_expr(body);
+                self.emit(Instr::Pop, span);
+                self.emit(Instr::Jump(start as u32), span);
+                self.patch_jump(to_end);
+                self.emit(Instr::Unit, span);
+            }
+            Expr::IfElse { cond, then, els } => {
+                self.compile_expr(cond);
+                let to_else = self.emit_jump(Instr::JumpIfFalse(0), span);
+                self.compile_expr(then);
+                let to_end = self.emit_jump(Instr::Jump(0), span);
+                self.patch_jump(to_else);
+                self.compile_expr(els);
+                self.patch_jump(to_end);
+            }
+            Expr::FunCall { id, args, .. } => {
+                let builtin = match id.value.as_str() {
+                    "print" => Some(Instr::Print),
+                    "length" => Some(Instr::Length),
+                    "exit" => Some(Instr::Exit),
+                    _ => None,
+                };
+                if let Some(instr) = builtin {
+                    self.compile_expr(args.first().unwrap());
+                    self.emit(instr, span);
+                    return;
+                }
+                let callee = self.resolve(&id.value);
+                self.emit(callee, &id.span);
+                for arg in args {
+                    self.compile_expr(arg);
+                }
+                let argc = args.len() as u32;
+                if self.fun.tail_calls.contains(span) {
+                    self.emit(Instr::TailCall(argc), span);
+                } else {
+                    self.emit(Instr::Call(argc), span);
+                }
+            }
+            Expr::NewArray { size, init, .. } => {
+                self.compile_expr(size);
+                self.compile_expr(init);
+                self.emit(Instr::NewArray, span);
+            }
+            Expr::ArrayIndex { lhs, index } => {
+                self.compile_lhs(lhs);
+                self.compile_expr(index);
+                self.emit(Instr::Index, span);
+            }
+            Expr::Id(id) => {
+                let instr = self.resolve(&id.value);
+                self.emit(instr, span);
+            }
+            Expr::Int(n) => self.emit(Instr::Int(*n), span),
+            Expr::Bool(b) => self.emit(Instr::Bool(*b), span),
+            Expr::String(str) => {
+                let next = self.strings.len() as u32;
+                let index = *self.string_ids.entry(str).or_insert(next);
+                if index == next {
+                    self.strings.push(str.clone());
+                }
+                self.emit(Instr::Str(index), span);
+            }
+            Expr::Unit => self.emit(Instr::Unit, span),
+        }
+    }
+
+    // pushes the array or element referred to by the lhs
+    fn compile_lhs(&mut self, lhs: &'a Spanned<Lhs>) {
+        match &lhs.value {
+            Lhs::Var { id } => {
+                let instr = self.resolve(&id.value);
+                self.emit(instr, &lhs.span);
+            }
+            Lhs::Index { lhs: arr, index } => {
+                self.compile_lhs(arr);
+                self.compile_expr(index);
+                self.emit(Instr::Index, &lhs.span);
+            }
+        }
+    }
+
+    // local variables shadow the captured ones, which shadow globals, which shadow functions
+    fn resolve(&self, id: &str) -> Instr {
+        if let Some(slot) = self.fun.locals.iter().rposition(|name| *name == id) {
+            return Instr::Load(slot as u32);
+        }
+        if let Some(index) = self.fun.captures.iter().position(|name| *name == id) {
+            return Instr::Capture(index as u32);
+        }
+        if self.fun.self_id == Some(id) {
+            return Instr::Env;
+        }
+        if let Some(global) = self.globals.get(id) {
+            return Instr::LoadGlobal(*global);
+        }
+        match self.fun_ids.get(id) {
+            Some(fun) => Instr::Fun(*fun),
+            None => panic!("undefined variable {}", id),
+        }
+    }
+
+    fn emit(&mut self, instr: Instr, span: &Span) {
+        let pos = self.position(span);
+        self.fun.code.push(instr);
+        self.fun.positions.push(pos);
+    }
+
+    // emits a jump whose target is patched later, returning its index
+    fn emit_jump(&mut self, instr: Instr, span: &Span) -> usize {
+        self.emit(instr, span);
+        self.fun.code.len() - 1
+    }
+
+    // makes the jump go to the next instruction
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.fun.code.len() as u32;
+        match &mut self.fun.code[at] {
+            Instr::Jump(to) | Instr::JumpIfFalse(to) => *to = target,
+            instr => panic!("instruction {} is not a jump", instr.name()),
+        }
+    }
+
+    fn position(&self, span: &Span) -> Position {
+        (self.line_and_col(span.start), self.line_and_col(span.end))
+    }
+
+    // same line and column as get_position_in_src, without scanning the source for every instruction
+    fn line_and_col(&self, index: usize) -> (u32, u32) {
+        let line = self.line_starts.partition_point(|start| *start <= index);
+        let line_start = self.line_starts[line - 1];
+        let col = self.src[line_start..index].chars().count() + 1;
+        (line as u32, col as u32)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,345 @@
+use crate::bytecode::instr::{Bytecode, Function, Instr, Position};
+
+// .agb files start with the magic bytes and the version of the format,
+// followed by the sections of the program, with every integer encoded as a leb128 varint
+const MAGIC: &[u8; 4] = b"AGB\0";
+const VERSION: u64 = 3;
+
+pub fn encode(bytecode: &Bytecode) -> Vec<u8> {
+    let mut enc = Encoder { bytes: MAGIC.to_vec() };
+    enc.uint(VERSION);
+    enc.string(&bytecode.file);
+    enc.string(&bytecode.src);
+    enc.uint(bytecode.strings.len() as u64);
+    for str in &bytecode.strings {
+        enc.string(str);
+    }
+    enc.uint(bytecode.globals.len() as u64);
+    for global in &bytecode.globals {
+        enc.string(global);
+    }
+    enc.uint(bytecode.functions.len() as u64);
+    for fun in &bytecode.functions {
+        enc.function(fun);
+    }
+    enc.uint(bytecode.init as u64);
+    enc.uint(bytecode.main as u64);
+    enc.bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Bytecode, String> {
+    if !bytes.starts_with(MAGIC) {
+        return Err("not an aguda bytecode file".to_string());
+    }
+    let mut dec = Decoder { bytes, pos: MAGIC.len() };
+    let version = dec.uint()?;
+    if version != VERSION {
+        return Err(format!("unsupported bytecode version {}, expected {}", version, VERSION));
+    }
+    let file = dec.string()?;
+    let src = dec.string()?;
+    let strings = dec.list(Decoder::string)?;
+    let globals = dec.list(Decoder::string)?;
+    let functions = dec.list(Decoder::function)?;
+    let bytecode = Bytecode {
+        file,
+        src,
+        strings,
+        globals,
+        functions,
+        init: dec.u32()?,
+        main: dec.u32()?,
+    };
+    if dec.pos != bytes.len() {
+        return Err("unexpected bytes after the end of the program".to_string());
+    }
+    validate(&bytecode)?;
+    Ok(bytecode)
+}
+
+// checks the indexes of the instructions and the height of the stack, so that the vm never reads out of bounds
+fn validate(bytecode: &Bytecode) -> Result<(), String> {
+    let functions = bytecode.functions.len() as u32;
+    if bytecode.init >= functions || bytecode.main >= functions {
+        return Err("invalid entry function".to_string());
+    }
+    for fun in &bytecode.functions {
+        if fun.params > fun.locals || fun.code.len() != fun.positions.len() {
+            return Err(format!("invalid function {}", fun.name));
+        }
+        let valid = |instr: &Instr| match *instr {
+            Instr::Str(s) => (s as usize) < bytecode.strings.len(),
+            // functions with captures are only called through their closures
+            Instr::Fun(f) => f < functions && bytecode.functions[f as usize].captures == 0,
+            Instr::Closure(f) => f < functions,
+            Instr::Capture(k) => k < fun.captures,
+            Instr::Load(slot) | Instr::Store(slot) => slot < fun.locals,
+            Instr::LoadGlobal(g) | Instr::StoreGlobal(g) => (g as usize) < bytecode.globals.len(),
+            Instr::Jump(to) | Instr::JumpIfFalse(to) => (to as usize) < fun.code.len(),
+            _ => true,
+        };
+        if let Some(pc) = fun.code.iter().position(|instr| !valid(instr)) {
+            return Err(format!("invalid operand of instruction {} of function {}", pc, fun.name));
+        }
+        if !matches!(fun.code.last(), Some(Instr::Return | Instr::Jump(_) | Instr::TailCall(_) | Instr::Exit)) {
+            return Err(format!("function {} does not end with a return", fun.name));
+        }
+        validate_stack(fun, &bytecode.functions)?;
+    }
+    Ok(())
+}
+
+// checks that every instruction finds the values it pops on the stack, with the same number of values
+// on every path that reaches it, so that the vm never pops the locals of the frame
+fn validate_stack(fun: &Function, functions: &[Function]) -> Result<(), String> {
+    let mut heights = vec![None; fun.code.len()];
+    let mut pending = vec![(0, 0usize)];
+    while let Some((pc, height)) = pending.pop() {
+        match heights[pc] {
+            Some(h) if h == height => continue,
+            Some(_) => return Err(format!("inconsistent stack at instruction {} of function {}", pc, fun.name)),
+            None => heights[pc] = Some(height),
+        }
+        let instr = fun.code[pc];
+        let (pops, pushes) = stack_effect(&instr, functions);
+        let height = height
+            .checked_sub(pops)
+            .ok_or_else(|| format!("stack underflow at instruction {} of function {}", pc, fun.name))?
+            + pushes;
+        // the last instruction is never followed by another
+        match instr {
+            Instr::Jump(to) => pending.push((to as usize, height)),
+            Instr::JumpIfFalse(to) => pending.extend([(to as usize, height), (pc + 1, height)]),
+            Instr::Return | Instr::TailCall(_) | Instr::Exit => {}
+            _ => pending.push((pc + 1, height)),
+        }
+    }
+    Ok(())
+}
+
+// number of values popped and pushed by the instruction
+fn stack_effect(instr: &Instr, functions: &[Function]) -> (usize, usize) {
+    match *instr {
+        Instr::Int(_) | Instr::Bool(_) | Instr::Unit | Instr::Str(_) | Instr::Fun(_) => (0, 1),
+        Instr::Capture(_) | Instr::Env | Instr::Load(_) | Instr::LoadGlobal(_) => (0, 1),
+        Instr::Closure(f) => (functions[f as usize].captures as usize, 1),
+        Instr::Store(_) | Instr::StoreGlobal(_) | Instr::Pop | Instr::JumpIfFalse(_) => (1, 0),
+        Instr::Add | Instr::Sub | Instr::Mul | Instr::Div | Instr::Mod | Instr::Pow => (2, 1),
+        Instr::Eq | Instr::Neq | Instr::Lt | Instr::Leq | Instr::Gt | Instr::Geq => (2, 1),
+        Instr::Not | Instr::Length | Instr::Print => (1, 1),
+        Instr::Jump(_) => (0, 0),
+        // the called function is below the arguments
+        Instr::Call(argc) => (argc as usize + 1, 1),
+        Instr::TailCall(argc) => (argc as usize + 1, 0),
+        Instr::Return | Instr::Exit => (1, 0),
+        Instr::NewArray | Instr::Index => (2, 1),
+        Instr::SetIndex => (3, 0),
+    }
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn uint(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    // zigzag encoding, so that small negative numbers are also short
+    fn int(&mut self, n: i64) {
+        self.uint(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn string(&mut self, str: &str) {
+        self.uint(str.len() as u64);
+        self.bytes.extend_from_slice(str.as_bytes());
+    }
+
+    fn function(&mut self, fun: &Function) {
+        self.string(&fun.name);
+        self.uint(fun.params as u64);
+        self.uint(fun.captures as u64);
+        self.uint(fun.locals as u64);
+        self.position(&fun.pos);
+        self.uint(fun.code.len() as u64);
+        for (instr, pos) in fun.code.iter().zip(&fun.positions) {
+            self.instr(instr);
+            self.position(pos);
+        }
+    }
+
+    fn position(&mut self, ((line, col), (end_line, end_col)): &Position) {
+        for n in [line, col, end_line, end_col] {
+            self.uint(*n as u64);
+        }
+    }
+
+    fn instr(&mut self, instr: &Instr) {
+        let (opcode, operand) = match *instr {
+            Instr::Int(n) => {
+                self.bytes.push(0);
+                self.int(n);
+                return;
+            }
+            Instr::Bool(b) => (1, Some(b as u32)),
+            Instr::Unit => (2, None),
+            Instr::Str(s) => (3, Some(s)),
+            Instr::Fun(f) => (4, Some(f)),
+            Instr::Load(slot) => (5, Some(slot)),
+            Instr::Store(slot) => (6, Some(slot)),
+            Instr::LoadGlobal(g) => (7, Some(g)),
+            Instr::StoreGlobal(g) => (8, Some(g)),
+            Instr::Pop => (9, None),
+            Instr::Add => (10, None),
+            Instr::Sub => (11, None),
+            Instr::Mul => (12, None),
+            Instr::Div => (13, None),
+            Instr::Mod => (14, None),
+            Instr::Pow => (15, None),
+            Instr::Eq => (16, None),
+            Instr::Neq => (17, None),
+            Instr::Lt => (18, None),
+            Instr::Leq => (19, None),
+            Instr::Gt => (20, None),
+            Instr::Geq => (21, None),
+            Instr::Not => (22, None),
+            Instr::Jump(to) => (23, Some(to)),
+            Instr::JumpIfFalse(to) => (24, Some(to)),
+            Instr::Call(argc) => (25, Some(argc)),
+            Instr::TailCall(argc) => (26, Some(argc)),
+            Instr::Return => (27, None),
+            Instr::NewArray => (28, None),
+            Instr::Index => (29, None),
+            Instr::SetIndex => (30, None),
+            Instr::Length => (31, None),
+            Instr::Print => (32, None),
+            Instr::Exit => (33, None),
+            Instr::Closure(f) => (34, Some(f)),
+            Instr::Capture(k) => (35, Some(k)),
+            Instr::Env => (36, None),
+        };
+        self.bytes.push(opcode);
+        if let Some(operand) = operand {
+            self.uint(operand as u64);
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.pos).ok_or("unexpected end of file")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn uint(&mut self) -> Result<u64, String> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err("invalid integer".to_string())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        u32::try_from(self.uint()?).map_err(|_| "integer out of range".to_string())
+    }
+
+    fn int(&mut self) -> Result<i64, String> {
+        let n = self.uint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.uint()? as usize;
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bytes.len()).ok_or("unexpected end of file")?;
+        let str = String::from_utf8(self.bytes[self.pos..end].to_vec()).map_err(|_| "invalid utf-8 string")?;
+        self.pos = end;
+        Ok(str)
+    }
+
+    fn list<T>(&mut self, item: fn(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let len = self.uint()?;
+        // every item takes at least one byte, which bounds the length of corrupted files
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err("unexpected end of file".to_string());
+        }
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    fn function(&mut self) -> Result<Function, String> {
+        let name = self.string()?;
+        let params = self.u32()?;
+        let captures = self.u32()?;
+        let locals = self.u32()?;
+        let pos = self.position()?;
+        let (code, positions) = self.list(|dec| Ok((dec.instr()?, dec.position()?)))?.into_iter().unzip();
+        Ok(Function { name, params, captures, locals, pos, code, positions })
+    }
+
+    fn position(&mut self) -> Result<Position, String> {
+        Ok(((self.u32()?, self.u32()?), (self.u32()?, self.u32()?)))
+    }
+
+    fn instr(&mut self) -> Result<Instr, String> {
+        let opcode = self.byte()?;
+        let instr = match opcode {
+            0 => Instr::Int(self.int()?),
+            1 => Instr::Bool(self.u32()? != 0),
+            2 => Instr::Unit,
+            3 => Instr::Str(self.u32()?),
+            4 => Instr::Fun(self.u32()?),
+            5 => Instr::Load(self.u32()?),
+            6 => Instr::Store(self.u32()?),
+            7 => Instr::LoadGlobal(self.u32()?),
+            8 => Instr::StoreGlobal(self.u32()?),
+            9 => Instr::Pop,
+            10 => Instr::Add,
+            11 => Instr::Sub,
+            12 => Instr::Mul,
+            13 => Instr::Div,
+            14 => Instr::Mod,
+            15 => Instr::Pow,
+            16 => Instr::Eq,
+            17 => Instr::Neq,
+            18 => Instr::Lt,
+            19 => Instr::Leq,
+            20 => Instr::Gt,
+            21 => Instr::Geq,
+            22 => Instr::Not,
+            23 => Instr::Jump(self.u32()?),
+            24 => Instr::JumpIfFalse(self.u32()?),
+            25 => Instr::Call(self.u32()?),
+            26 => Instr::TailCall(self.u32()?),
+            27 => Instr::Return,
+            28 => Instr::NewArray,
+            29 => Instr::Index,
+            30 => Instr::SetIndex,
+            31 => Instr::Length,
+            32 => Instr::Print,
+            33 => Instr::Exit,
+            34 => Instr::Closure(self.u32()?),
+            35 => Instr::Capture(self.u32()?),
+            36 => Instr::Env,
+            _ => return Err(format!("invalid opcode {}", opcode)),
+        };
+        Ok(instr)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+// instructions of the stack vm, every expression pushes exactly one value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    Int(i64),
+    Bool(bool),
+    Unit,
+    Str(u32), // index in the string constants
+    Fun(u32), // index of a function
+    Closure(u32), // index of a nested function, whose captured values are pushed before
+    Capture(u32), // captured value of the current function
+    Env, // closure of the current function, for recursive calls of nested functions
+    Load(u32), // local variable slot
+    Store(u32),
+    LoadGlobal(u32),
+    StoreGlobal(u32),
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Eq,
+    Neq,
+    Lt,
+    Leq,
+    Gt,
+    Geq,
+    Not,
+    Jump(u32), // index of the target instruction
+    JumpIfFalse(u32),
+    Call(u32), // number of arguments, pushed after the called function
+    TailCall(u32), // replaces the frame of the current call
+    Return,
+    NewArray,
+    Index,
+    SetIndex,
+    Length,
+    Print,
+    Exit,
+}
+
+impl Instr {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instr::Int(_) => "int",
+            Instr::Bool(_) => "bool",
+            Instr::Unit => "unit",
+            Instr::Str(_) => "str",
+            Instr::Fun(_) => "fun",
+            Instr::Closure(_) => "closure",
+            Instr::Capture(_) => "capture",
+            Instr::Env => "env",
+            Instr::Load(_) => "load",
+            Instr::Store(_) => "store",
+            Instr::LoadGlobal(_) => "load_global",
+            Instr::StoreGlobal(_) => "store_global",
+            Instr::Pop => "pop",
+            Instr::Add => "add",
+            Instr::Sub => "sub",
+            Instr::Mul => "mul",
+            Instr::Div => "div",
+            Instr::Mod => "mod",
+            Instr::Pow => "pow",
+            Instr::Eq => "eq",
+            Instr::Neq => "neq",
+            Instr::Lt => "lt",
+            Instr::Leq => "leq",
+            Instr::Gt => "gt",
+            Instr::Geq => "geq",
+            Instr::Not => "not",
+            Instr::Jump(_) => "jump",
+            Instr::JumpIfFalse(_) => "jump_if_false",
+            Instr::Call(_) => "call",
+            Instr::TailCall(_) => "tail_call",
+            Instr::Return => "return",
+            Instr::NewArray => "new_array",
+            Instr::Index => "index",
+            Instr::SetIndex => "set_index",
+            Instr::Length => "length",
+            Instr::Print => "print",
+            Instr::Exit => "exit",
+        }
+    }
+}
+
+// lines and columns of the start and the end of an expression, with the end exclusive
+pub type Position = ((u32, u32), (u32, u32));
+
+pub struct Function {
+    pub name: String,
+    pub params: u32,
+    pub captures: u32, // number of values captured by a nested function
+    pub locals: u32, // slots of the parameters and the let variables
+    pub pos: Position, // position of the declaration
+    pub code: Vec<Instr>,
+    pub positions: Vec<Position>, // position of the expression of each instruction
+}
+
+// program compiled to bytecode, with its source to report runtime errors
+pub struct Bytecode {
+    pub file: String,
+    pub src: String,
+    pub strings: Vec<String>,
+    pub globals: Vec<String>, // names of the global variables
+    pub functions: Vec<Function>,
+    pub init: u32, // function that initializes the globals in declaration order
+    pub main: u32,
+}
+
+impl Bytecode {
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "; {}", self.file).unwrap();
+        for (i, global) in self.globals.iter().enumerate() {
+            writeln!(out, "global {} {}", i, global).unwrap();
+        }
+        for (i, fun) in self.functions.iter().enumerate() {
+            let captures = if fun.captures > 0 { format!(", captures {}", fun.captures) } else { String::new() };
+            writeln!(
+                out,
+                "\nfun {} {} (params {}, locals {}{}) at {}:{}",
+                i, fun.name, fun.params, fun.locals, captures, fun.pos.0.0, fun.pos.0.1
+            ).unwrap();
+            for (pc, (instr, ((line, col), _))) in fun.code.iter().zip(&fun.positions).enumerate() {
+                let operand = match instr {
+                    Instr::Int(n) => n.to_string(),
+                    Instr::Bool(b) => b.to_string(),
+                    Instr::Str(s) => format!("{} {:?}", s, self.strings[*s as usize]),
+                    Instr::Fun(f) | Instr::Closure(f) => format!("{} {}", f, self.functions[*f as usize].name),
+                    Instr::LoadGlobal(g) | Instr::StoreGlobal(g) => format!("{} {}", g, self.globals[*g as usize]),
+                    Instr::Load(n) | Instr::Store(n) | Instr::Jump(n) | Instr::JumpIfFalse(n)
+                    | Instr::Call(n) | Instr::TailCall(n) | Instr::Capture(n) => n.to_string(),
+                    _ => String::new(),
+                };
+                let line = format!("  {:>4}  {:<8} {} {}", pc, format!("{}:{}", line, col), instr.name(), operand);
+                writeln!(out, "{}", line.trim_end()).unwrap();
+            }
+        }
+        out
+    }
+}
\ No newline at end of file
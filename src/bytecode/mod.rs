@@ -0,0 +1,4 @@
+pub mod compiler;
+pub mod format;
+pub mod instr;
+pub mod vm;
\ No newline at end of file
@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use crate::bytecode::instr::{Bytecode, Function, Instr, Position};
+use crate::diagnostics::errors::{Location, RuntimeError};
+use crate::interpreter::interpreter::eval_int_op;
+use crate::interpreter::value::{Closure, Value};
+use crate::syntax::ast::Op;
+use crate::CompileOptions;
+
+// nested calls allowed when the program has no maximum recursion depth, as the frames of the vm
+// are on the heap and would otherwise only stop when memory runs out
+const DEFAULT_MAX_DEPTH: u64 = 1 << 22;
+
+// reasons for the execution to stop before the program ends
+enum Stop {
+    Error(RuntimeError),
+    Exit(i32),
+}
+
+impl From<RuntimeError> for Stop {
+    fn from(e: RuntimeError) -> Self {
+        Stop::Error(e)
+    }
+}
+
+// call being executed, whose parameters and locals start at the base of the stack
+#[derive(Clone, Copy)]
+struct Frame<'a> {
+    fun: &'a Function,
+    pc: usize,
+    base: usize,
+}
+
+pub struct Vm<'a> {
+    bytecode: &'a Bytecode,
+    options: &'a CompileOptions,
+    strings: Vec<Value>,
+    globals: Vec<Value>,
+    stack: Vec<Value>,
+    frames: Vec<Frame<'a>>, // callers of the current call
+    depth: u64,
+    out: Vec<u8>,
+}
+
+impl<'a> Vm<'a> {
+
+    pub fn new(bytecode: &'a Bytecode, options: &'a CompileOptions) -> Self {
+        Vm {
+            bytecode,
+            options,
+            strings: bytecode.strings.iter().map(|str| Value::String(Rc::from(str.as_str()))).collect(),
+            globals: vec![Value::Unit; bytecode.globals.len()],
+            stack: vec![],
+            frames: vec![],
+            depth: 0,
+            out: vec![],
+        }
+    }
+
+    // initializes the globals and calls main, which returns the exit status
+    pub fn run(mut self) -> Result<(Vec<u8>, i32), RuntimeError> {
+        let status = self.execute(self.bytecode.init, vec![])
+            .and_then(|_| self.execute(self.bytecode.main, vec![Value::Unit]));
+        let status = match status {
+            Ok(Value::Int(status)) => status as i32,
+            Ok(_) => 0,
+            Err(Stop::Exit(status)) => status,
+            Err(Stop::Error(e)) => return Err(e),
+        };
+        Ok((self.out, status))
+    }
+
+    // calls the function and runs until it returns
+    fn execute(&mut self, fun: u32, args: Vec<Value>) -> Result<Value, Stop> {
+        self.stack.push(Value::Fun(fun as usize));
+        let argc = args.len();
+        self.stack.extend(args);
+        let mut frame = self.enter(argc)?;
+        loop {
+            let instr = frame.fun.code[frame.pc];
+            frame.pc += 1;
+            match instr {
+                Instr::Int(n) => self.stack.push(Value::Int(n)),
+                Instr::Bool(b) => self.stack.push(Value::Bool(b)),
+                Instr::Unit => self.stack.push(Value::Unit),
+                Instr::Str(s) => self.stack.push(self.strings[s as usize].clone()),
+                Instr::Fun(f) => self.stack.push(Value::Fun(f as usize)),
+                Instr::Closure(f) => {
+                    let captures = self.bytecode.functions[f as usize].captures as usize;
+                    let env = self.stack.split_off(self.stack.len() - captures);
+                    self.stack.push(Value::Closure(Rc::new(Closure { fun: f as usize, env })));
+                }
+                // the called closure is below the parameters
+                Instr::Capture(k) => {
+                    let Value::Closure(closure) = &self.stack[frame.base - 1] else {
+                        return Err(invalid(format!("function {} is not called through its closure", frame.fun.name)));
+                    };
+                    self.stack.push(closure.env[k as usize].clone());
+                }
+                Instr::Env => self.stack.push(self.stack[frame.base - 1].clone()),
+                Instr::Load(slot) => self.stack.push(self.stack[frame.base + slot as usize].clone()),
+                Instr::Store(slot) => self.stack[frame.base + slot as usize] = self.pop(),
+                Instr::LoadGlobal(g) => self.stack.push(self.globals[g as usize].clone()),
+                Instr::StoreGlobal(g) => self.globals[g as usize] = self.pop(),
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::Add => self.int_op(&Op::Add, &frame)?,
+                Instr::Sub => self.int_op(&Op::Sub, &frame)?,
+                Instr::Mul => self.int_op(&Op::Mul, &frame)?,
+                Instr::Div => self.int_op(&Op::Div, &frame)?,
+                Instr::Mod => self.int_op(&Op::Mod, &frame)?,
+                Instr::Pow => self.int_op(&Op::Pow, &frame)?,
+                Instr::Lt => self.int_op(&Op::Lt, &frame)?,
+                Instr::Leq => self.int_op(&Op::Leq, &frame)?,
+                Instr::Gt => self.int_op(&Op::Gt, &frame)?,
+                Instr::Geq => self.int_op(&Op::Geq, &frame)?,
+                Instr::Eq | Instr::Neq => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    self.stack.push(Value::Bool(l.equals(&r) == (instr == Instr::Eq)));
+                }
+                Instr::Not => {
+                    let b = self.pop_bool()?;
+                    self.stack.push(Value::Bool(!b));
+                }
+                Instr::Jump(to) => frame.pc = to as usize,
+                Instr::JumpIfFalse(to) => {
+                    if !self.pop_bool()? {
+                        frame.pc = to as usize;
+                    }
+                }
+                Instr::Call(argc) => {
+                    self.frames.push(frame);
+                    frame = self.enter(argc as usize)?;
+                }
+                Instr::TailCall(argc) => {
+                    // the called function and its arguments replace the frame, including the current function
+                    let start = self.stack.len() - argc as usize - 1;
+                    self.stack.drain(frame.base - 1..start);
+                    frame.base = self.stack.len() - argc as usize;
+                    frame.fun = self.function(frame.base - 1, argc as usize)?;
+                    frame.pc = 0;
+                    self.stack.resize(frame.base + frame.fun.locals as usize, Value::Unit);
+                }
+                Instr::Return => {
+                    let val = self.pop();
+                    self.stack.truncate(frame.base - 1);
+                    self.depth -= 1;
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            frame = caller;
+                            self.stack.push(val);
+                        }
+                        None => return Ok(val),
+                    }
+                }
+                Instr::NewArray => {
+                    let init = self.pop();
+                    let size = self.pop_int()?;
+                    self.stack.push(Value::new_array(size, init, position(&frame))?);
+                }
+                Instr::Index => {
+                    let index = self.pop_int()?;
+                    let arr = self.pop_array()?;
+                    let index = self.check_index(&arr, index, &frame)?;
+                    let elem = arr.borrow()[index].clone();
+                    self.stack.push(elem);
+                }
+                Instr::SetIndex => {
+                    let val = self.pop();
+                    let index = self.pop_int()?;
+                    let arr = self.pop_array()?;
+                    let index = self.check_index(&arr, index, &frame)?;
+                    arr.borrow_mut()[index] = val;
+                }
+                Instr::Length => {
+                    let length = self.pop_array()?.borrow().len() as i64;
+                    self.stack.push(Value::Int(length));
+                }
+                Instr::Print => {
+                    let val = self.pop();
+                    write!(self.out, "{}", val).unwrap();
+                    self.stack.push(Value::Unit);
+                }
+                Instr::Exit => return Err(Stop::Exit(self.pop_int()? as i32)),
+            }
+        }
+    }
+
+    // starts the call to the function pushed before the arguments
+    fn enter(&mut self, argc: usize) -> Result<Frame<'a>, Stop> {
+        let base = self.stack.len() - argc;
+        let fun = self.function(base - 1, argc)?;
+        self.depth += 1;
+        let max_depth = self.options.max_recursion_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        if max_depth > 0 && self.depth > max_depth {
+            return Err(RuntimeError::stack_overflow(location(fun.pos), fun.name.clone()).into());
+        }
+        self.stack.resize(base + fun.locals as usize, Value::Unit);
+        Ok(Frame { fun, pc: 0, base })
+    }
+
+    // function called with the arguments above it on the stack
+    fn function(&self, index: usize, argc: usize) -> Result<&'a Function, Stop> {
+        let fun = match self.stack[index] {
+            Value::Fun(fun) => &self.bytecode.functions[fun],
+            Value::Closure(ref closure) => &self.bytecode.functions[closure.fun],
+            _ => return Err(invalid("call of a value that is not a function".to_string())),
+        };
+        if fun.params as usize != argc {
+            return Err(invalid(format!("function {} called with {} arguments", fun.name, argc)));
+        }
+        Ok(fun)
+    }
+
+    // the loader checked the height of the stack at every instruction
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    // the types of the values are not checked by the loader, so they are checked when used
+    fn pop_int(&mut self) -> Result<i64, Stop> {
+        match self.pop() {
+            Value::Int(n) => Ok(n),
+            _ => Err(invalid("expected an integer".to_string())),
+        }
+    }
+
+    fn pop_bool(&mut self) -> Result<bool, Stop> {
+        match self.pop() {
+            Value::Bool(b) => Ok(b),
+            _ => Err(invalid("expected a boolean".to_string())),
+        }
+    }
+
+    fn pop_array(&mut self) -> Result<Rc<RefCell<Vec<Value>>>, Stop> {
+        match self.pop() {
+            Value::Array(arr) => Ok(arr),
+            _ => Err(invalid("expected an array".to_string())),
+        }
+    }
+
+    fn int_op(&mut self, op: &Op, frame: &Frame) -> Result<(), Stop> {
+        let r = self.pop_int()?;
+        let l = self.pop_int()?;
+        let val = eval_int_op(l, op, r, self.options.overflow, || position(frame))?;
+        self.stack.push(val);
+        Ok(())
+    }
+
+    // indexes are always checked, since there is no memory outside of the array to access
+    fn check_index(&self, arr: &RefCell<Vec<Value>>, index: i64, frame: &Frame) -> Result<usize, Stop> {
+        let length = arr.borrow().len() as i64;
+        if index < 0 || index >= length {
+            return Err(RuntimeError::index_out_of_bounds(position(frame), index, length).into());
+        }
+        Ok(index as usize)
+    }
+}
+
+// error of a program whose bytecode was not generated by the compiler
+fn invalid(message: String) -> Stop {
+    Stop::Error(RuntimeError::unknown(format!("invalid bytecode: {}", message)))
+}
+
+// position of the instruction being executed
+fn position(frame: &Frame) -> Location {
+    location(frame.fun.positions[frame.pc - 1])
+}
+
+fn location(((line, col), (end_line, end_col)): Position) -> Location {
+    Location {
+        start: (line as usize, col as usize),
+        end: (end_line as usize, end_col as usize),
+    }
+}
\ No newline at end of file
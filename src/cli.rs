@@ -1,11 +1,13 @@
 use clap::Parser;
+use crate::passes::PASS_NAMES;
+use crate::{Backend, CompileOptions, Emit, Gc, OptLevel, Overflow};
 
 #[derive(Parser, Debug)]
 #[command(name = "aguda-rs")]
 #[command(version = "0.1.0")]
 #[command(about = "AGUDA Compiler")]
 pub struct Cli {
-    /// Path to the source .agu file
+    /// Path to the source .agu file, or a .agb file generated with --emit bytecode
     #[arg(short, long, default_value = "main.agu")]
     pub file: String,
 
@@ -29,11 +31,96 @@ pub struct Cli {
     #[arg(long, default_value_t = false)]
     pub suppress_hints: bool,
 
-    /// Show the AST without running the program
+    /// Optimization level, which selects the AST passes and the LLVM pipeline
+    #[arg(short = 'O', long, value_enum, default_value_t = OptLevel::O0)]
+    pub opt: OptLevel,
+
+    /// Custom LLVM pass pipeline, in the format of opt's -passes (overrides the optimization level)
+    #[arg(long)]
+    pub passes: Option<String>,
+
+    /// Print the program after the AST pass with the given name
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(PASS_NAMES))]
+    pub print_after: Option<String>,
+
+    /// Disable array bounds checking
     #[arg(long, default_value_t = false)]
-    pub ast: bool,
+    pub unchecked_bounds: bool,
+
+    /// Behavior of integer arithmetic on overflow
+    #[arg(long, value_enum, default_value_t = Overflow::Wrap)]
+    pub overflow: Overflow,
+
+    /// Memory management strategy for arrays
+    #[arg(long, value_enum, default_value_t = Gc::None)]
+    pub gc: Gc,
+
+    /// Code generator used to build the program
+    #[arg(long, value_enum, default_value_t = Backend::Llvm)]
+    pub backend: Backend,
+
+    /// Maximum number of nested function calls before stopping with a stack overflow (0 for no limit),
+    /// instead of stopping when the stack runs out
+    #[arg(long)]
+    pub max_recursion_depth: Option<u64>,
+
+    /// Target triple to generate code for, such as aarch64-unknown-linux-gnu (defaults to the host)
+    #[arg(long)]
+    pub target: Option<String>,
+
+    /// Print the targets supported by LLVM and exit
+    #[arg(long, default_value_t = false)]
+    pub print_targets: bool,
+
+    /// Kind of output to generate, executables are run after being built
+    #[arg(long, value_enum, default_value_t = Emit::Exe)]
+    pub emit: Emit,
+
+    /// Path of the generated output (defaults to stdout, or the source name for executables)
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Run the program in the JIT execution engine instead of building an executable
+    #[arg(long, default_value_t = false, conflicts_with_all = ["emit", "target", "backend"])]
+    pub jit: bool,
+
+    /// Run the program in the tree-walking interpreter, without generating code
+    #[arg(long, default_value_t = false, conflicts_with_all = ["emit", "target", "backend", "jit"])]
+    pub interpret: bool,
+
+    /// Run the program in the bytecode VM instead of building an executable
+    #[arg(long, default_value_t = false, conflicts_with_all = ["emit", "target", "backend", "jit", "interpret"])]
+    pub vm: bool,
+
+    /// Print the bytecode instructions of the program instead of running it
+    #[arg(long, default_value_t = false, conflicts_with_all = ["emit", "target", "backend", "jit", "interpret", "vm"])]
+    pub disassemble: bool,
+
+    /// Generate DWARF debug information
+    #[arg(short = 'g', long = "debug", default_value_t = false)]
+    pub debug: bool,
+}
 
-    /// LLVM optimization level (0-3)
-    #[arg(short, long, default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=3))]
-    pub opt: u32,
+impl From<&Cli> for CompileOptions {
+    fn from(args: &Cli) -> Self {
+        CompileOptions {
+            opt: args.opt,
+            passes: args.passes.clone(),
+            print_after: args.print_after.clone(),
+            bounds_checks: !args.unchecked_bounds,
+            overflow: args.overflow,
+            gc: args.gc,
+            backend: args.backend,
+            max_recursion_depth: args.max_recursion_depth,
+            target: args.target.clone(),
+            // the jit runs the generated llvm bitcode, and the vm the generated bytecode
+            emit: match args.emit {
+                _ if args.jit => Emit::Bitcode,
+                _ if args.vm || args.disassemble => Emit::Bytecode,
+                emit => emit,
+            },
+            output: args.output.clone(),
+            debug: args.debug,
+        }
+    }
 }
\ No newline at end of file
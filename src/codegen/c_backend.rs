@@ -0,0 +1,869 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::codegen::closure_slots;
+use crate::diagnostics::errors::{BackendError, Location};
+use crate::semantic::captures::captured_vars;
+use crate::semantic::symbol_table::SymbolTable;
+use crate::semantic::tail_calls::tail_calls;
+use crate::syntax::ast::*;
+use crate::utils::TempDir;
+use crate::{CompileOptions, Gc, OptLevel, Overflow, DEFAULT_STACK_SIZE, MAX_ARRAY_LENGTH, STACK_MARGIN};
+
+// printing, arithmetic, runtime checks and the collector, configured with macros
+const RUNTIME: &str = include_str!("runtime.c");
+
+// translates the checked program into portable c99, built with any c compiler
+pub struct CCodeGen {
+    symbols: SymbolTable<(String, Type)>, // c expression of each variable and its type
+    src: String,
+    file: String,
+    options: CompileOptions,
+    typedefs: String, // function pointer types
+    fun_types: HashMap<String, String>, // typedef of each function type, by its text
+    bounces: String, // functions making the pending tail calls, one for each function type
+    bounce_funs: HashMap<String, String>, // bounce of each function type, by its text
+    tail_args: usize, // largest number of arguments of a tail call
+    globals: String,
+    global_roots: usize, // global arrays, at the bottom of the shadow stack
+    prototypes: String,
+    closures: String, // closures of the top-level functions used as values
+    functions: String,
+    top_level: HashSet<String>, // c names of the top-level functions
+    static_closures: HashMap<String, String>, // closure of each top-level function used as a value
+    captures: HashMap<Span, Vec<Id>>, // variables captured by the nested functions
+    next_id: usize, // suffix of the unique c names
+    fun: FunState,
+}
+
+// function being generated
+#[derive(Default)]
+struct FunState {
+    name: String, // c name
+    body: String,
+    indent: usize,
+    locals: Vec<(String, String)>, // c type and name, declared at the start of the function
+    roots: usize, // shadow stack slots of the variables holding arrays
+    params: Vec<Option<String>>, // variables of the params, assigned by tail calls of the function to itself
+    tail_calls: HashSet<Span>, // calls in tail position
+    tail_loop: bool, // whether a tail call jumps back to the start
+    depth_counted: bool, // whether the function counts towards the recursion depth
+    env: Option<String>, // closure of a nested function
+}
+
+impl CCodeGen {
+
+    pub fn new(file: &str, src: &str, options: &CompileOptions) -> Self {
+        CCodeGen {
+            symbols: SymbolTable::new(HashMap::new()),
+            src: src.to_string(),
+            file: file.to_string(),
+            options: options.clone(),
+            typedefs: String::new(),
+            fun_types: HashMap::new(),
+            bounces: String::new(),
+            bounce_funs: HashMap::new(),
+            tail_args: 0,
+            globals: String::new(),
+            global_roots: 0,
+            prototypes: String::new(),
+            closures: String::new(),
+            functions: String::new(),
+            top_level: HashSet::new(),
+            static_closures: HashMap::new(),
+            captures: HashMap::new(),
+            next_id: 0,
+            fun: FunState::default(),
+        }
+    }
+
+    // returns the c source of the program
+    pub fn gen_program(&mut self, prog: &Program) -> String {
+        self.captures = captured_vars(prog)
+            .into_iter()
+            .map(|(span, vars)| (span, vars.into_iter().cloned().collect()))
+            .collect();
+
+        // declare all functions first to allow for mutually recursive function calls
+        for decl in &prog.decls {
+            if let Decl::Fun { id, params, ty, .. } = &decl.value {
+                let name = format!("fun_{}", mangle(&id.value));
+                let param_names = params.iter().map(|_| self.unique("arg")).collect::<Vec<_>>();
+                let signature = self.fun_signature(&name, &ty.value, &param_names);
+                writeln!(self.prototypes, "{};", signature).unwrap();
+                self.top_level.insert(name.clone());
+                self.symbols.declare(&id.value, &(name, Type::Fun(ty.value.clone())));
+            }
+        }
+
+        // declare globals, initialized in declaration order
+        self.fun = FunState { name: "aguda_init_globals".to_string(), ..FunState::default() };
+        for decl in &prog.decls {
+            if let Decl::Var { id, ty, expr } = &decl.value {
+                self.gen_global(id, &ty.value, expr);
+            }
+        }
+        self.gen_fun_cleanup();
+        let init = std::mem::take(&mut self.fun);
+        self.gen_fun_definition("static void aguda_init_globals(void)", init);
+
+        // generate functions
+        let mut main_ret = Type::Unit;
+        for decl in &prog.decls {
+            if let Decl::Fun { id, params, ty, expr } = &decl.value {
+                self.gen_fun(&id.value, params, &ty.value, expr, &decl.span);
+                if id.value == "main" {
+                    main_ret = *ty.value.ret.clone();
+                }
+            }
+        }
+
+        self.gen_c_main(&main_ret)
+    }
+
+    fn gen_global(&mut self, id: &Spanned<Id>, ty: &Type, expr: &Spanned<Expr>) {
+        self.symbols.enter_scope();
+        let (val, _) = self.gen_expr(expr);
+        self.symbols.exit_scope();
+        if id.value == "_" {
+            // evaluated only for its side effects
+            return;
+        }
+        let var = if self.is_root(ty) {
+            self.global_roots += 1;
+            format!("AGUDA_ROOT(0, {})", self.global_roots - 1)
+        } else {
+            let var = self.unique(&format!("{}_", mangle(&id.value)));
+            let c_ty = self.c_type(ty);
+            writeln!(self.globals, "static {} = 0;", declaration(&c_ty, &var)).unwrap();
+            var
+        };
+        self.emit(&format!("{} = {};", var, val));
+        self.symbols.declare(&id.value, &(var, ty.clone()));
+    }
+
+    fn gen_fun(&mut self, id: &Id, params: &[Spanned<Id>], fun_ty: &FunType, body: &Spanned<Expr>, span: &Span) {
+        let (name, _) = self.symbols.lookup(id).unwrap_or_else(|| panic!("undefined function {}", id));
+        self.fun = FunState {
+            name: name.clone(),
+            tail_calls: tail_calls(id, body),
+            ..FunState::default()
+        };
+        self.symbols.enter_scope();
+        self.gen_fun_body(&name, id, params, fun_ty, body, span);
+    }
+
+    // nested functions read the captured variables from their closure, and call themselves directly,
+    // returns the name of the generated function
+    fn gen_nested_fun(&mut self, id: &Id, params: &[Spanned<Id>], fun_ty: &FunType, body: &Spanned<Expr>, span: &Span, captures: &[(Id, Type)]) -> String {
+        let name = &self.unique(&format!("fun_{}_", mangle(id)));
+        self.fun = FunState {
+            name: name.to_string(),
+            tail_calls: tail_calls(id, body),
+            ..FunState::default()
+        };
+        self.symbols.enter_scope();
+
+        // the closure is rooted, since the caller may have released it before a tail call
+        let closure_ty = Type::Fun(fun_ty.clone());
+        let env = if self.is_root(&closure_ty) {
+            let var = self.new_var("", &closure_ty);
+            self.emit(&format!("{} = env;", var));
+            var
+        } else {
+            "env".to_string()
+        };
+        self.symbols.declare(id, &(name.to_string(), closure_ty));
+        let types = captures.iter().map(|(_, ty)| ty.clone()).collect::<Vec<_>>();
+        let (slots, _) = closure_slots(&types);
+        for ((var, ty), slot) in captures.iter().zip(slots) {
+            let c_ty = self.c_type(ty);
+            self.symbols.declare(var, &(format!("AGUDA_SLOT({}, {}, {})", env, c_ty, slot), ty.clone()));
+        }
+        self.fun.env = Some(env);
+        self.gen_fun_body(name, id, params, fun_ty, body, span);
+        name.to_string()
+    }
+
+    // generates the function entered by gen_fun or gen_nested_fun, and exits its scope
+    fn gen_fun_body(&mut self, name: &str, id: &Id, params: &[Spanned<Id>], fun_ty: &FunType, body: &Spanned<Expr>, span: &Span) {
+        if self.options.max_recursion_depth != Some(0) {
+            let loc = self.location(span);
+            self.emit(&format!("aguda_enter({}, {});", c_string(id), loc));
+            self.fun.depth_counted = self.options.max_recursion_depth.is_some();
+        }
+
+        // array params are moved to the shadow stack, wildcard params are unused
+        let mut param_names = Vec::with_capacity(params.len());
+        for (param, param_ty) in params.iter().zip(&fun_ty.params) {
+            let arg = self.unique("arg");
+            if param.value == "_" {
+                self.fun.params.push(None);
+            } else if self.is_root(param_ty) {
+                let var = self.new_var("", param_ty);
+                self.emit(&format!("{} = {};", var, arg));
+                self.symbols.declare(&param.value, &(var.clone(), param_ty.clone()));
+                self.fun.params.push(Some(var));
+            } else {
+                self.symbols.declare(&param.value, &(arg.clone(), param_ty.clone()));
+                self.fun.params.push(Some(arg.clone()));
+            }
+            param_names.push(arg);
+        }
+        let prologue_len = self.fun.body.len();
+
+        // generate function body
+        let (ret_val, _) = self.gen_expr(body);
+        self.symbols.exit_scope();
+        let ret_val = if self.is_root(&fun_ty.ret) {
+            // the returned array is read before the shadow stack slots are released
+            let var = self.unique("ret");
+            let c_ty = self.c_type(&fun_ty.ret);
+            self.fun.locals.push((c_ty, var.clone()));
+            self.emit(&format!("{} = {};", var, ret_val));
+            var
+        } else {
+            ret_val
+        };
+        self.gen_fun_cleanup();
+        self.emit(&format!("return {};", ret_val));
+
+        // tail calls of the function to itself jump back here with the new arguments
+        let mut fun = std::mem::take(&mut self.fun);
+        if fun.tail_loop {
+            fun.body.insert_str(prologue_len, "start:\n");
+        }
+        let signature = self.fun_signature(name, fun_ty, &param_names);
+        self.gen_fun_definition(&signature, fun);
+    }
+
+    // declares the locals and reserves the shadow stack slots before the body of the function
+    fn gen_fun_definition(&mut self, signature: &str, fun: FunState) {
+        let mut out = String::new();
+        writeln!(out, "{} {{", signature).unwrap();
+        for (c_ty, name) in &fun.locals {
+            writeln!(out, "    {} = 0;", declaration(c_ty, name)).unwrap();
+        }
+        if self.options.gc == Gc::MarkSweep {
+            writeln!(out, "    int64_t gc_base = aguda_gc_push({});", fun.roots).unwrap();
+        }
+        out.push_str(&fun.body);
+        out.push_str("}\n\n");
+        self.functions.push_str(&out);
+    }
+
+    // releases the shadow stack slots and the call depth of the function before it returns
+    fn gen_fun_cleanup(&mut self) {
+        if self.options.gc == Gc::MarkSweep {
+            self.emit("aguda_gc_pop(gc_base);");
+        }
+        if self.fun.depth_counted {
+            self.emit("aguda_leave();");
+        }
+    }
+
+    // the c main runs the global initializers and returns the exit code of the program
+    fn gen_c_main(&mut self, main_ret: &Type) -> String {
+        let mut out = String::new();
+        writeln!(out, "// generated by aguda-rs from {}", self.file.replace(['\n', '\r'], " ")).unwrap();
+        if self.options.gc == Gc::MarkSweep {
+            out.push_str("#define AGUDA_GC_MARKSWEEP\n");
+        }
+        if self.options.overflow == Overflow::Trap {
+            out.push_str("#define AGUDA_OVERFLOW_TRAP\n");
+        }
+        match self.options.max_recursion_depth {
+            Some(max_depth) => writeln!(out, "#define AGUDA_MAX_DEPTH {}", max_depth).unwrap(),
+            None => {
+                out.push_str("#define AGUDA_STACK_CHECK\n");
+                writeln!(out, "#define AGUDA_DEFAULT_STACK_SIZE {}", DEFAULT_STACK_SIZE).unwrap();
+                writeln!(out, "#define AGUDA_STACK_MARGIN {}", STACK_MARGIN).unwrap();
+            }
+        }
+        writeln!(out, "#define AGUDA_MAX_ARRAY_LENGTH INT64_C({})", MAX_ARRAY_LENGTH).unwrap();
+        writeln!(out, "#define AGUDA_TAIL_ARGS {}", self.tail_args.max(1)).unwrap();
+        out.push('\n');
+        out.push_str(RUNTIME);
+        for section in [&self.typedefs, &self.bounces, &self.globals, &self.prototypes, &self.closures] {
+            if !section.is_empty() {
+                out.push('\n');
+                out.push_str(section);
+            }
+        }
+        out.push('\n');
+        out.push_str(&self.functions);
+
+        out.push_str("int main(void) {\n");
+        if self.options.max_recursion_depth.is_none() {
+            out.push_str("    aguda_init_stack_limit();\n");
+        }
+        if self.options.gc == Gc::MarkSweep {
+            writeln!(out, "    aguda_gc_push({});", self.global_roots).unwrap();
+        }
+        out.push_str("    aguda_init_globals();\n");
+        match main_ret {
+            Type::Int => out.push_str("    return (int) fun_main(NULL, 0);\n"),
+            _ => out.push_str("    fun_main(NULL, 0);\n    return 0;\n"),
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    // returns the c expression of the value and its type
+    fn gen_expr(&mut self, expr: &Spanned<Expr>) -> (String, Type) {
+        match &expr.value {
+            Expr::Chain { lhs, rhs } => {
+                self.gen_expr(lhs);
+                let rhs = self.gen_expr(rhs);
+                if matches!(lhs.value, Expr::Let { .. } | Expr::LetFun { .. }) {
+                    self.symbols.exit_scope();
+                }
+                rhs
+            }
+            Expr::Let { id, ty, expr } => {
+                let (val, _) = self.gen_expr(expr);
+                self.symbols.enter_scope();
+                // scope is exited in chain expression
+
+                // only declare a variable if not a wildcard
+                if id.value != "_" {
+                    let var = self.new_var(&format!("{}_", mangle(&id.value)), &ty.value);
+                    self.emit(&format!("{} = {};", var, val));
+                    self.symbols.declare(&id.value, &(var, ty.value.clone()));
+                }
+                unit()
+            }
+            Expr::LetFun { id, params, ty, expr: body } => {
+                let captures = self.captures.remove(&id.span).unwrap_or_default()
+                    .into_iter()
+                    .map(|var| {
+                        let (val, ty) = self.symbols.lookup(&var).unwrap_or_else(|| panic!("undefined variable {}", var));
+                        (var, self.fun_value(&val, &ty), ty)
+                    })
+                    .collect::<Vec<_>>();
+                let outer = std::mem::take(&mut self.fun);
+                let types = captures.iter().map(|(var, _, ty)| (var.clone(), ty.clone())).collect::<Vec<_>>();
+                let name = self.gen_nested_fun(&id.value, params, &ty.value, body, &expr.span, &types);
+                self.fun = outer;
+
+                // the closure holds the values of the captured variables
+                let fun_ty = Type::Fun(ty.value.clone());
+                let (slots, fun_slot) = closure_slots(&types.into_iter().map(|(_, ty)| ty).collect::<Vec<_>>());
+                let alloc = format!("aguda_new_closure({}, {}, {})", fun_slot, slots.len() + 1, self.location(&expr.span));
+                let (closure, _) = self.gen_call_temp(&alloc, fun_ty.clone());
+                for ((_, val, ty), slot) in captures.iter().zip(slots) {
+                    let c_ty = self.c_type(ty);
+                    self.emit(&format!("AGUDA_SLOT({}, {}, {}) = {};", closure, c_ty, slot, val));
+                }
+                let fun_pointer_ty = self.fun_pointer_type(&ty.value);
+                self.emit(&format!("AGUDA_SLOT({}, {}, {}) = {};", closure, fun_pointer_ty, fun_slot, name));
+                self.symbols.enter_scope();
+                // scope is exited in chain expression
+                if id.value != "_" {
+                    let var = self.new_var(&format!("{}_", mangle(&id.value)), &fun_ty);
+                    self.emit(&format!("{} = {};", var, closure));
+                    self.symbols.declare(&id.value, &(var, fun_ty));
+                }
+                unit()
+            }
+            Expr::Set { lhs, expr } => {
+                let (lhs, _) = self.gen_lhs(lhs);
+                let (val, _) = self.gen_expr(expr);
+                self.emit(&format!("{} = {};", lhs, val));
+                unit()
+            }
+            Expr::BinOp { lhs, op, rhs } => {
+                match op {
+                    // short circuit operators
+                    Op::And => self.gen_short_circuit_op(lhs, rhs, true),
+                    Op::Or => self.gen_short_circuit_op(lhs, rhs, false),
+                    _ => {
+                        let (l, lhs_ty) = self.gen_expr(lhs);
+                        let (r, _) = self.gen_expr(rhs);
+                        let loc = self.location(&expr.span);
+                        let (val, ty) = match op {
+                            Op::Add => (format!("aguda_add({}, {}, {})", l, r, loc), Type::Int),
+                            Op::Sub => (format!("aguda_sub({}, {}, {})", l, r, loc), Type::Int),
+                            Op::Mul => (format!("aguda_mul({}, {}, {})", l, r, loc), Type::Int),
+                            Op::Div => (format!("aguda_div({}, {}, {})", l, r, loc), Type::Int),
+                            Op::Mod => (format!("aguda_mod({}, {}, {})", l, r, loc), Type::Int),
+                            Op::Pow => (format!("aguda_pow({}, {}, {})", l, r, loc), Type::Int),
+                            // strings are compared by content, like in the interpreter
+                            Op::Eq | Op::Neq if lhs_ty == Type::String => {
+                                (format!("strcmp({}, {}) {} 0", l, r, op.to_text()), Type::Bool)
+                            }
+                            // function values and arrays are compared by reference
+                            _ => (format!("{} {} {}", l, op.to_text(), r), Type::Bool),
+                        };
+                        self.gen_temp(&val, ty)
+                    }
+                }
+            }
+            Expr::Not { expr } => {
+                let (val, _) = self.gen_expr(expr);
+                self.gen_temp(&format!("!{}", val), Type::Bool)
+            }
+            Expr::While { cond, expr } => {
+                self.emit("for (;;) {");
+                self.fun.indent += 1;
+                let (cond, _) = self.gen_expr(cond);
+                self.emit(&format!("if (!{}) {{", cond));
+                self.emit("    break;");
+                self.emit("}");
+                self.gen_expr(expr);
+                self.fun.indent -= 1;
+                self.emit("}");
+                unit()
+            }
+            Expr::IfElse { cond, then, els } => {
+                let (cond, _) = self.gen_expr(cond);
+                self.emit(&format!("if ({}) {{", cond));
+                self.fun.indent += 1;
+                let (then_val, ty) = self.gen_expr(then);
+                // unit branches have no result
+                let res = match ty {
+                    Type::Unit => None,
+                    _ => Some(self.new_var("t", &ty)),
+                };
+                if let Some(res) = &res {
+                    self.emit(&format!("{} = {};", res, then_val));
+                }
+                self.fun.indent -= 1;
+                self.emit("} else {");
+                self.fun.indent += 1;
+                let (else_val, _) = self.gen_expr(els);
+                if let Some(res) = &res {
+                    self.emit(&format!("{} = {};", res, else_val));
+                }
+                self.fun.indent -= 1;
+                self.emit("}");
+                (res.unwrap_or_else(|| "0".to_string()), ty)
+            }
+            Expr::FunCall { id, args, .. } => {
+                match id.value.as_str() {
+                    "print" => {
+                        let (val, ty) = self.gen_expr(args.first().unwrap());
+                        self.gen_print(&val, &ty);
+                        return unit();
+                    }
+                    "length" => {
+                        let (arr, _) = self.gen_expr(args.first().unwrap());
+                        return self.gen_temp(&format!("{}->length", arr), Type::Int);
+                    }
+                    "exit" => {
+                        let (code, _) = self.gen_expr(args.first().unwrap());
+                        self.emit(&format!("exit((int) {});", code));
+                        return unit();
+                    }
+                    _ => {}
+                }
+                let (fun, ty) = self.symbols.lookup(&id.value)
+                    .unwrap_or_else(|| panic!("undefined function {}", id.value));
+                let Type::Fun(fun_ty) = ty else {
+                    panic!("type {:?} is not callable", ty)
+                };
+                let mut args = args.iter().map(|arg| self.gen_expr(arg).0).collect::<Vec<_>>();
+                let (callee, env) = self.gen_callee(&fun, &fun_ty);
+                args.insert(0, env);
+                if self.fun.tail_calls.contains(&expr.span) {
+                    return self.gen_tail_call(&callee, &fun_ty, &args);
+                }
+                // the called function may have returned to make a tail call
+                let call = format!("{}({}({}))", trampoline(&fun_ty.ret), callee, args.join(", "));
+                match *fun_ty.ret {
+                    Type::Unit => {
+                        self.emit(&format!("{};", call));
+                        unit()
+                    }
+                    ret => self.gen_call_temp(&call, ret),
+                }
+            }
+            Expr::Id(id) => {
+                // copied, since the variable may be assigned before the value is used
+                let (var, ty) = self.symbols.lookup(&id.value)
+                    .unwrap_or_else(|| panic!("undefined variable {}", id.value));
+                let val = self.fun_value(&var, &ty);
+                self.gen_temp(&val, ty)
+            }
+            Expr::Int(n) if *n == i64::MIN => ("INT64_MIN".to_string(), Type::Int),
+            Expr::Int(n) => (format!("INT64_C({})", n), Type::Int),
+            Expr::Bool(b) => (b.to_string(), Type::Bool),
+            Expr::Unit => unit(),
+            Expr::String(str) => (c_string(str), Type::String),
+            Expr::NewArray { ty, size, init } => {
+                let loc = self.location(&expr.span);
+                let (size, _) = self.gen_expr(size);
+                let (init, _) = self.gen_expr(init);
+                let elem_ty = self.c_type(&ty.value);
+                let has_refs = ty.value.is_reference();
+                let arr_ty = Type::Array(Box::new(ty.value.clone()));
+                let alloc = format!("aguda_new_array({}, sizeof({}), {}, {})", size, elem_ty, has_refs, loc);
+                let (arr, arr_ty) = self.gen_call_temp(&alloc, arr_ty);
+
+                // initialize every element with the same value
+                let i = self.gen_index_var();
+                self.emit(&format!("for ({i} = 0; {i} < {}->length; {i}++) {{", arr));
+                self.emit(&format!("    AGUDA_ELEMS({}, {})[{}] = {};", arr, elem_ty, i, init));
+                self.emit("}");
+                (arr, arr_ty)
+            }
+            Expr::ArrayIndex { lhs, index } => {
+                let (elem, ty) = self.gen_index(lhs, index, &expr.span);
+                self.gen_temp(&elem, ty)
+            }
+        }
+    }
+
+    // returns the called c function and the closure passed to it, which top-level functions ignore,
+    // while nested functions call themselves directly with their own closure
+    fn gen_callee(&mut self, fun: &str, fun_ty: &FunType) -> (String, String) {
+        if fun == self.fun.name {
+            return (fun.to_string(), self.fun.env.clone().unwrap_or_else(|| "NULL".to_string()));
+        }
+        if self.top_level.contains(fun) {
+            return (fun.to_string(), "NULL".to_string());
+        }
+        // the closure is copied, since it is read twice
+        let (closure, _) = self.gen_temp(fun, Type::Fun(fun_ty.clone()));
+        let fun_pointer_ty = self.fun_pointer_type(fun_ty);
+        (format!("AGUDA_CALLEE({}, {})", closure, fun_pointer_ty), closure)
+    }
+
+    // functions used as values are closures, where the top-level ones have a static closure
+    fn fun_value(&mut self, var: &str, ty: &Type) -> String {
+        if !matches!(ty, Type::Fun(_)) {
+            return var.to_string();
+        }
+        if var == self.fun.name {
+            if let Some(env) = &self.fun.env {
+                return env.clone();
+            }
+        }
+        if !self.top_level.contains(var) {
+            return var.to_string();
+        }
+        if let Some(closure) = self.static_closures.get(var) {
+            return closure.clone();
+        }
+        let name = self.unique("closure");
+        writeln!(self.closures, "static aguda_static_closure {} = AGUDA_STATIC_CLOSURE({});", name, var).unwrap();
+        let closure = format!("((aguda_array *) &{}.refs)", name);
+        self.static_closures.insert(var.to_string(), closure.clone());
+        closure
+    }
+
+    // a call in tail position jumps back to the start of the function when it calls itself,
+    // otherwise it stores the call, made by the trampoline of the caller once the function returns
+    fn gen_tail_call(&mut self, fun: &str, fun_ty: &FunType, args: &[String]) -> (String, Type) {
+        if fun == self.fun.name {
+            // the arguments are temporaries or literals, so the params can be assigned in order,
+            // after the closure, which is unchanged
+            for (param, arg) in self.fun.params.clone().iter().zip(&args[1..]) {
+                if let Some(param) = param {
+                    self.emit(&format!("{} = {};", param, arg));
+                }
+            }
+            self.emit("goto start;");
+            self.fun.tail_loop = true;
+        } else {
+            let bounce = self.bounce_fun(fun_ty);
+            self.emit(&format!("aguda_tail_fun = (void (*)(void)) {};", fun));
+            self.emit(&format!("aguda_tail_env = {};", args[0]));
+            for (k, (arg, ty)) in args[1..].iter().zip(&fun_ty.params).enumerate() {
+                self.emit(&format!("aguda_tail_args[{}].{} = {};", k, value_field(ty), arg));
+            }
+            self.emit(&format!("aguda_tail_call = (void (*)(void)) {};", bounce));
+            self.gen_fun_cleanup();
+            self.emit("return 0;");
+        }
+        // code after the tail call is unreachable
+        ("0".to_string(), *fun_ty.ret.clone())
+    }
+
+    // returns the lvalue of the lhs and the type of the value stored there
+    fn gen_lhs(&mut self, lhs: &Spanned<Lhs>) -> (String, Type) {
+        match &lhs.value {
+            Lhs::Var { id } => self.symbols.lookup(&id.value)
+                .unwrap_or_else(|| panic!("undefined variable {}", id.value)),
+            Lhs::Index { lhs: arr, index } => self.gen_index(arr, index, &lhs.span),
+        }
+    }
+
+    // returns the lvalue of the indexed element and its type
+    fn gen_index(&mut self, lhs: &Spanned<Lhs>, index: &Spanned<Expr>, span: &Span) -> (String, Type) {
+        let (arr, arr_ty) = self.gen_lhs(lhs);
+        let Type::Array(elem_ty) = &arr_ty else {
+            panic!("type {:?} is not indexable", arr_ty)
+        };
+        let elem_ty = *elem_ty.clone();
+        // the indexed array stays alive even if the index expression replaces it
+        let (arr, _) = self.gen_temp(&arr, arr_ty);
+        let (index, _) = self.gen_expr(index);
+        if self.options.bounds_checks {
+            let loc = self.location(span);
+            self.emit(&format!("aguda_check_index({}, {}, {});", arr, index, loc));
+        }
+        let c_elem_ty = self.c_type(&elem_ty);
+        (format!("AGUDA_ELEMS({}, {})[{}]", arr, c_elem_ty, index), elem_ty)
+    }
+
+    fn gen_short_circuit_op(&mut self, lhs: &Spanned<Expr>, rhs: &Spanned<Expr>, and_op: bool) -> (String, Type) {
+        let (lhs, _) = self.gen_expr(lhs);
+        let (res, ty) = self.gen_temp(&lhs, Type::Bool);
+
+        // and => evaluate rhs when lhs is true
+        // or => evaluate rhs only when lhs is false
+        self.emit(&format!("if ({}{}) {{", if and_op { "" } else { "!" }, res));
+        self.fun.indent += 1;
+        let (rhs, _) = self.gen_expr(rhs);
+        self.emit(&format!("{} = {};", res, rhs));
+        self.fun.indent -= 1;
+        self.emit("}");
+        (res, ty)
+    }
+
+    fn gen_print(&mut self, val: &str, ty: &Type) {
+        match ty {
+            Type::Int => self.emit(&format!("aguda_print_int({});", val)),
+            Type::Bool => self.emit(&format!("aguda_print_bool({});", val)),
+            Type::String => self.emit(&format!("aguda_print_string({});", val)),
+            Type::Unit => self.emit("aguda_print_unit();"),
+            Type::Array(elem_ty) => {
+                // prints as [e1,e2,...,en]
+                let i = self.gen_index_var();
+                let c_elem_ty = self.c_type(elem_ty);
+                self.emit("aguda_print_string(\"[\");");
+                self.emit(&format!("for ({i} = 0; {i} < {}->length; {i}++) {{", val));
+                self.fun.indent += 1;
+                self.emit(&format!("if ({} > 0) {{", i));
+                self.emit("    aguda_print_string(\",\");");
+                self.emit("}");
+                self.gen_print(&format!("AGUDA_ELEMS({}, {})[{}]", val, c_elem_ty, i), elem_ty);
+                self.fun.indent -= 1;
+                self.emit("}");
+                self.emit("aguda_print_string(\"]\");");
+            }
+            // function values print like in the interpreter
+            Type::Fun(_) => self.emit("aguda_print_string(\"<function>\");"),
+            _ => panic!("unsupported type for print: {:?}", ty),
+        }
+    }
+
+    // stores the value in a new temporary
+    fn gen_temp(&mut self, val: &str, ty: Type) -> (String, Type) {
+        let var = self.new_var("t", &ty);
+        self.emit(&format!("{} = {};", var, val));
+        (var, ty)
+    }
+
+    // calls may grow the shadow stack, moving its slots, so arrays are only stored there after the call
+    fn gen_call_temp(&mut self, call: &str, ty: Type) -> (String, Type) {
+        if !self.is_root(&ty) {
+            return self.gen_temp(call, ty);
+        }
+        let var = self.unique("r");
+        self.fun.locals.push(("aguda_array *".to_string(), var.clone()));
+        self.emit(&format!("{} = {};", var, call));
+        self.gen_temp(&var, ty)
+    }
+
+    // counter of a loop over the elements of an array
+    fn gen_index_var(&mut self) -> String {
+        let var = self.unique("i");
+        self.fun.locals.push(("int64_t".to_string(), var.clone()));
+        var
+    }
+
+    // variable of the current function, arrays live in the shadow stack so the collector can find them
+    fn new_var(&mut self, name: &str, ty: &Type) -> String {
+        if self.is_root(ty) {
+            self.fun.roots += 1;
+            return format!("AGUDA_ROOT(gc_base, {})", self.fun.roots - 1);
+        }
+        let var = self.unique(name);
+        let c_ty = self.c_type(ty);
+        self.fun.locals.push((c_ty, var.clone()));
+        var
+    }
+
+    fn is_root(&self, ty: &Type) -> bool {
+        self.options.gc == Gc::MarkSweep && ty.is_reference()
+    }
+
+    fn unique(&mut self, name: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", name, self.next_id)
+    }
+
+    fn emit(&mut self, line: &str) {
+        let indent = "    ".repeat(self.fun.indent + 1);
+        writeln!(self.fun.body, "{}{}", indent, line).unwrap();
+    }
+
+    // source location of a span, as a c string in the format file:line:col-line:col
+    fn location(&self, span: &Span) -> String {
+        let Location { start, end } = Location::from_span(&self.src, span);
+        c_string(&format!("{}:{}:{}-{}:{}", self.file, start.0, start.1, end.0, end.1))
+    }
+
+    fn fun_signature(&mut self, name: &str, fun_ty: &FunType, params: &[String]) -> String {
+        let ret = self.c_type(&fun_ty.ret);
+        // every function takes its closure first
+        let params = std::iter::once("aguda_array *env".to_string())
+            .chain(fun_ty.params.iter().zip(params).map(|(ty, param)| {
+                let c_ty = self.c_type(ty);
+                declaration(&c_ty, param)
+            }))
+            .collect::<Vec<_>>();
+        format!("static {}({})", declaration(&ret, name), params.join(", "))
+    }
+
+    fn c_type(&mut self, ty: &Type) -> String {
+        match ty {
+            Type::Int => "int64_t".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::String => "const char *".to_string(),
+            Type::Unit => "aguda_unit".to_string(),
+            // function values are closures
+            Type::Array(_) | Type::Fun(_) => "aguda_array *".to_string(),
+            // the type checker only gives this type to expressions with errors, which stop the compilation
+            Type::Any => unreachable!("type {:?} of a program that failed to type check", ty),
+        }
+    }
+
+    // functions called through closures are function pointers, named by a typedef
+    fn fun_pointer_type(&mut self, fun_ty: &FunType) -> String {
+        let key = fun_ty.to_text();
+        if let Some(name) = self.fun_types.get(&key) {
+            return name.clone();
+        }
+        let ret = self.c_type(&fun_ty.ret);
+        let params = std::iter::once("aguda_array *".to_string())
+            .chain(fun_ty.params.iter().map(|ty| self.c_type(ty)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let name = format!("aguda_fun{}", self.fun_types.len());
+        let pointer = format!("(*{})", name);
+        writeln!(self.typedefs, "typedef {}({});", declaration(&ret, &pointer), params).unwrap();
+        self.fun_types.insert(key, name.clone());
+        name
+    }
+
+    // function making the pending tail call to a function of the type, with the stored closure and arguments
+    fn bounce_fun(&mut self, fun_ty: &FunType) -> String {
+        let key = fun_ty.to_text();
+        if let Some(name) = self.bounce_funs.get(&key) {
+            return name.clone();
+        }
+        let fun_pointer_ty = self.fun_pointer_type(fun_ty);
+        let ret = self.c_type(&fun_ty.ret);
+        let name = format!("aguda_bounce{}", self.bounce_funs.len());
+        let args = std::iter::once("aguda_tail_env".to_string())
+            .chain(fun_ty.params.iter().enumerate().map(|(k, ty)| format!("aguda_tail_args[{}].{}", k, value_field(ty))))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(self.bounces, "static {}(void) {{", declaration(&ret, &name)).unwrap();
+        writeln!(self.bounces, "    return (({}) aguda_tail_fun)({});", fun_pointer_ty, args).unwrap();
+        writeln!(self.bounces, "}}\n").unwrap();
+        self.tail_args = self.tail_args.max(fun_ty.params.len());
+        self.bounce_funs.insert(key, name.clone());
+        name
+    }
+}
+
+// trampoline returning the result of a call of the type
+fn trampoline(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int => "aguda_trampoline_int",
+        Type::Bool => "aguda_trampoline_bool",
+        Type::String => "aguda_trampoline_string",
+        Type::Unit => "aguda_trampoline_unit",
+        _ => "aguda_trampoline_ref",
+    }
+}
+
+// field of aguda_value holding a value of the type
+fn value_field(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int => "i",
+        Type::Bool => "b",
+        Type::String => "s",
+        Type::Unit => "u",
+        _ => "a",
+    }
+}
+
+fn unit() -> (String, Type) {
+    ("0".to_string(), Type::Unit)
+}
+
+// declaration of a c variable, without a space after pointer types
+fn declaration(c_ty: &str, name: &str) -> String {
+    if c_ty.ends_with('*') {
+        format!("{}{}", c_ty, name)
+    } else {
+        format!("{} {}", c_ty, name)
+    }
+}
+
+// identifiers may contain primes, and the ones introduced by the optimization passes dots,
+// so they are escaped along with underscores
+fn mangle(id: &str) -> String {
+    id.replace('_', "__").replace('\'', "_q").replace('.', "_d")
+}
+
+fn c_string(str: &str) -> String {
+    let mut out = String::from("\"");
+    for byte in str.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            // avoids trigraphs
+            b'?' => out.push_str("\\?"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => write!(out, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// compiles the c source into a native executable with the c compiler given by $CC, or cc,
+// which may include its own flags
+pub fn build_executable(source: &str, options: &CompileOptions, exe_path: &Path) -> Result<(), BackendError> {
+    let dir = TempDir::new("aguda-c").map_err(|e| BackendError::c_compilation(e.to_string()))?;
+    let c_path = dir.path().join("program.c");
+    fs::write(&c_path, source).map_err(|e| BackendError::c_compilation(e.to_string()))?;
+
+    let cc = env::var("CC").ok().filter(|cc| !cc.trim().is_empty()).unwrap_or_else(|| "cc".to_string());
+    let mut cc = cc.split_whitespace();
+    let mut command = Command::new(cc.next().unwrap());
+    command.args(cc);
+    let opt = match options.opt {
+        OptLevel::O0 => "-O0",
+        OptLevel::O1 => "-O1",
+        OptLevel::O2 => "-O2",
+        OptLevel::O3 => "-O3",
+        OptLevel::Os => "-Os",
+        OptLevel::Oz => "-Oz",
+    };
+    command.arg(opt);
+    if options.debug {
+        command.arg("-g");
+    }
+    let output = command
+        .arg(&c_path)
+        .arg("-o")
+        .arg(exe_path)
+        .output()
+        .map_err(|e| BackendError::c_compilation(e.to_string()))?;
+    if !output.status.success() {
+        return Err(BackendError::c_compilation(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(())
+}
\ No newline at end of file
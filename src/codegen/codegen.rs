@@ -1,374 +1,1166 @@
-use std::collections::HashMap;
 use inkwell::AddressSpace;
+use inkwell::basic_block::BasicBlock;
 use inkwell::{context::Context, builder::Builder, module::Module, values::*, types::BasicTypeEnum, IntPredicate};
 use std::ops::Deref;
-use std::path::Path;
 use inkwell::builder::BuilderError;
-use inkwell::memory_buffer::MemoryBuffer;
-use inkwell::types::{BasicType, IntType, StructType};
-use crate::semantic::symbol_table::SymbolTable;
-use crate::syntax::ast::*;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::module::Linkage;
+use inkwell::targets::TargetMachine;
+use crate::codegen::debug::DebugInfo;
+use crate::codegen::closure_slots;
+use crate::codegen::runtime::Runtime;
+use inkwell::types::{BasicType, FunctionType, IntType, PointerType, StructType};
+use crate::ir::instr::*;
+use crate::syntax::ast::{FunType, Id, Op, Span, Type};
+use crate::diagnostics::errors::Location;
+use crate::{CompileOptions, Gc, OptLevel, Overflow, DEFAULT_STACK_SIZE, MAX_ARRAY_LENGTH, STACK_MARGIN};
+
+// llvm tailcc, which guarantees that calls marked as tail in tail position reuse the stack frame
+const TAIL_CALL_CONV: u32 = 18;
 
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    symbols: SymbolTable<(PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    src: String,
+    options: CompileOptions,
+    debug: Option<DebugInfo<'ctx>>,
+    size_type: IntType<'ctx>, // size_t of the target
+    functions: Vec<FunctionValue<'ctx>>,
+    globals: Vec<GlobalValue<'ctx>>,
+    static_closures: Vec<Option<PointerValue<'ctx>>>, // closures of the top-level functions used as values
+    main: FunId,
+    env: Option<PointerValue<'ctx>>, // closure of the current function, none for main and the initializer
+    locals: Vec<PointerValue<'ctx>>, // stack slots of the current function
+    temps: Vec<Option<BasicValueEnum<'ctx>>>, // values of the temporaries of the current function
+    entry_block: Option<BasicBlock<'ctx>>, // stack slots of the current function
+    gc_roots: Vec<(PointerValue<'ctx>, Type)>, // stack slots holding arrays in the current function
+    exit_points: Vec<InstructionValue<'ctx>>, // returns and tail calls of the current function
+    depth_counted: bool, // whether the current function counts towards the recursion depth
 }
 
 impl<'ctx> CodeGen<'ctx> {
 
-    pub fn new(module_name: &str, context: &'ctx Context) -> Self {
-        if module_name == "lib.agu" {
-            panic!("invalid module name"); // avoid collision with lib.ll
-        }
+    pub fn new(
+        module_name: &str,
+        src: &str,
+        options: &CompileOptions,
+        machine: &TargetMachine,
+        context: &'ctx Context,
+    ) -> Self {
         let module = context.create_module(module_name);
-        // link with lib.ll
-        let aguda_buf = MemoryBuffer::create_from_file(Path::new("lib.ll")).expect("could not read lib.ll");
-        let aguda_mod = context.create_module_from_ir(aguda_buf).expect("failed to parse lib.ll");
-        module.link_in_module(aguda_mod).expect("failed to link aguda module");
+        module.set_triple(&machine.get_triple());
+        module.set_data_layout(&machine.get_target_data().get_data_layout());
         let builder = context.create_builder();
-        CodeGen {
+        let mut codegen = CodeGen {
             context,
             module,
             builder,
-            symbols: SymbolTable::new(HashMap::new()),
+            src: src.to_string(),
+            options: options.clone(),
+            debug: None,
+            size_type: context.ptr_sized_int_type(&machine.get_target_data(), None),
+            functions: vec![],
+            globals: vec![],
+            static_closures: vec![],
+            main: 0,
+            env: None,
+            locals: vec![],
+            temps: vec![],
+            entry_block: None,
+            gc_roots: vec![],
+            exit_points: vec![],
+            depth_counted: false,
+        };
+        if options.debug {
+            let int_bits = codegen.int_type().get_bit_width() as u64;
+            let ptr_bits = machine.get_target_data().get_pointer_byte_size(None) as u64 * 8;
+            let is_optimized = !matches!(options.opt, OptLevel::O0);
+            codegen.debug = Some(DebugInfo::new(context, &codegen.module, module_name, src, is_optimized, int_bits, ptr_bits));
         }
+        // runtime library functions
+        Runtime::new(context, &codegen.module, codegen.int_type(), codegen.size_type).gen_runtime(options);
+        codegen
     }
 
-    pub fn gen_ll(&self, path: Box<Path>) {
-        self.module.print_to_file(path.with_extension("ll")).expect("failed to generate .ll file");
+    pub fn module(&self) -> &Module<'ctx> {
+        &self.module
     }
 
     pub fn gen_program(&mut self, prog: &Program) {
         // declare all functions first to allow for mutually recursive function calls
-        let functions = &prog.decls.iter().filter_map(|decl| {
-            if let Decl::Fun { id, ty, .. } = &decl.value {
-                Some((&decl.value, self.gen_fun_signature(&id.value, &ty.value)))
-            } else {
-                None
-            }
-        }).collect::<Vec<_>>();
+        self.main = prog.main;
+        self.functions = prog.functions
+            .iter()
+            .enumerate()
+            .map(|(id, fun)| self.gen_fun_signature(fun, id == prog.init, id == prog.main))
+            .collect();
+        self.static_closures = vec![None; prog.functions.len()];
 
-        // declare globals
-        for decl in prog.decls.iter().filter(|d| matches!(d.value, Decl::Var { .. })) {
-            self.gen_decl(&decl.value);
+        // globals start as zero, their initial values are set by the initializer
+        self.globals = prog.globals.iter().map(|global| {
+            let llvm_ty = self.llvm_type(&global.ty);
+            let pointer = self.module.add_global(llvm_ty, None, &symbol(&global.name));
+            pointer.set_initializer(&llvm_ty.const_zero());
+            pointer
+        }).collect();
+        if self.options.gc == Gc::MarkSweep {
+            self.gen_gc_global_roots(prog);
         }
 
-        // generate functions
-        for (decl, fun) in functions {
-            if let Decl::Fun { id, params, ty, expr } = decl {
-                let params = params.iter().map(|param| param.value.clone()).collect::<Vec<_>>();
-                self.gen_fun(&id.value, fun, &params, &ty.value, &expr.value);
-            }
+        for id in 0..prog.functions.len() {
+            self.gen_fun(prog, id);
+        }
+
+        if let Some(debug) = &self.debug {
+            debug.finalize();
         }
     }
 
-    fn gen_decl(&mut self, decl: &Decl) {
-        if let Decl::Var { id, ty, expr } = decl {
-            // constant for top-level declarations
-            let constant = match& expr.value {
-                // literals
-                Expr::Unit | Expr::String(_) | Expr::Bool(_) | Expr::Int(_) => {
-                    expr.value.clone()
-                }
-                // negative integer literal
-                Expr::BinOp { lhs, op, rhs } if matches!(op, Op::Sub) && matches!(lhs.value, Expr::Int(0)) => {
-                    if let Expr::Int(rhs) = &rhs.value {
-                        Expr::Int(-rhs)
-                    } else {
-                        unimplemented!("expression for top-level declaration")
-                    }
-                }
-                _ => unimplemented!("expression for top-level declaration"),
-            };
-            let llvm_ty = self.llvm_type(&ty.value);
-            self.symbols.enter_scope();
-            let val = self.gen_expr(&constant);
-            self.symbols.exit_scope();
-            let global = self.module.add_global(llvm_ty, None, &id.value);
-            self.symbols.declare(&id.value, &(global.as_pointer_value(), llvm_ty));
-            global.set_initializer(&val);
-        }
-    }
-
-    fn gen_fun_signature(
-        &mut self,
-        id: &Id,
-        fun_ty: &FunType,
-    ) -> FunctionValue<'ctx> {
-        let params_ty = fun_ty
-            .params
-            .iter()
-            .map(|t| self.llvm_type(t).into())
-            .collect::<Vec<_>>();
+    fn gen_fun_signature(&mut self, fun: &Function, is_init: bool, is_main: bool) -> FunctionValue<'ctx> {
+        if is_init {
+            let init = self.context.void_type().fn_type(&[], false);
+            return self.module.add_function(&fun.name, init, Some(Linkage::Internal));
+        }
+        let fn_type = self.fun_type(&fun.ty, is_main);
+        // main is called from c
+        if is_main {
+            return self.module.add_function(&fun.name, fn_type, None);
+        }
+        let llvm_fun = self.module.add_function(&symbol(&fun.name), fn_type, None);
+        llvm_fun.set_call_conventions(TAIL_CALL_CONV);
+        llvm_fun
+    }
 
-        let fn_type = match fun_ty.ret.deref() {
-            Type::Unit =>
-                if id == "main" {
-                    self.int_type().fn_type(&params_ty, false)
-                } else {
-                    self.context.void_type().fn_type(&params_ty, false)
-                },
-            _ => self.llvm_type(&fun_ty.ret).fn_type(&params_ty, false),
+    fn gen_fun(&mut self, prog: &Program, id: FunId) {
+        let fun = &prog.functions[id];
+        let llvm_fun = self.functions[id];
+        self.gen_fun_prologue(llvm_fun);
+        self.enter_debug_scope(llvm_fun, &fun.name, &fun.ty, &fun.span);
+        self.env = match id {
+            _ if id == prog.init || id == prog.main => None,
+            _ => Some(llvm_fun.get_nth_param(0).unwrap().into_pointer_value()),
         };
-        self.module.add_function(&id, fn_type, None)
-    }
-
-    fn gen_fun(
-        &mut self,
-        id: &Id,
-        fun: &FunctionValue<'ctx>,
-        params: &[Id],
-        fun_ty: &FunType,
-        body: &Expr,
-    ) {
-        // function body
-        let entry = self.context.append_basic_block(fun.clone(), "entry");
-        self.builder.position_at_end(entry);
-        self.symbols.enter_scope();
-
-        // allocate space for params
-        for (index, param) in fun.get_param_iter().enumerate() {
-            let name = &params[index];
-            if name == "_" {
-                // skip wildcard parameters
-                continue;
-            }
-            let llvm_ty = self.llvm_type(&fun_ty.params[index]);
-            let pointer = self.builder.build_alloca(llvm_ty, name).unwrap();
-            self.builder.build_store(pointer, param).unwrap();
-            self.symbols.declare(name, &(pointer, llvm_ty));
-        }
-
-        // generate function body
-        let ret_val = self.gen_expr(body);
-        self.symbols.exit_scope();
-
-        // generate return
-        if matches!(fun_ty.ret.deref(), Type::Unit) {
-            if id == "main" {
-                self.builder.build_return(Some(&self.int_type().const_zero())).unwrap();
-            } else {
-                self.builder.build_return(None).unwrap();
+
+        if id == prog.init {
+            // string constants are created by the builder, so they need a function
+            for (id, global) in prog.globals.iter().enumerate() {
+                if let Some(init) = &global.init {
+                    let val = self.gen_operand(init);
+                    self.globals[id].set_initializer(&val);
+                }
             }
         } else {
-            self.builder.build_return(Some(&ret_val)).unwrap();
+            // run the global initializers before main
+            if id == prog.main {
+                if self.options.max_recursion_depth.is_none() {
+                    self.gen_stack_limit();
+                }
+                self.builder.build_call(self.functions[prog.init], &[], "").unwrap();
+            }
+            match self.options.max_recursion_depth {
+                None => self.gen_stack_check(&fun.name, &fun.span),
+                Some(0) => {}
+                Some(max_depth) => self.gen_depth_check(&fun.name, &fun.span, max_depth),
+            }
         }
-    }
 
-    fn gen_expr(&mut self, expr: &Expr) -> BasicValueEnum<'ctx> {
-        match expr {
-            Expr::Chain { lhs, rhs } => {
-                self.gen_expr(&lhs.value);
-                let rhs = self.gen_expr(&rhs.value);
-                if matches!(lhs.value, Expr::Let { .. }) {
-                    self.symbols.exit_scope();
+        // allocate space for params and variables
+        let entry = self.entry_block.unwrap();
+        self.locals = fun.locals.iter().map(|local| {
+            let pointer = self.gen_entry_alloca(&local.ty, &local.name);
+            if let Some(debug) = &self.debug {
+                match local.param {
+                    Some(index) => debug.declare_param(pointer, &local.name, index as u32 + 1, &local.ty, &local.span, entry),
+                    None => debug.declare_var(pointer, &local.name, &local.ty, &local.span, entry),
                 }
-                rhs
-            }
-            Expr::Let { id, ty, expr } => {
-                let val = self.gen_expr(&expr.value);
-                self.symbols.enter_scope();
-                // scope is exited in chain expression
-
-                // only allocate space if not a wildcard
-                if id.value != "_" {
-                    let llvm_ty = self.llvm_type(&ty.value);
-                    let pointer = self.builder.build_alloca(llvm_ty, &id.value).unwrap();
-                    self.builder.build_store(pointer, val).unwrap();
-                    self.symbols.declare(&id.value, &(pointer, llvm_ty));
-                };
-                self.unit_type().const_zero().into()
-            }
-            Expr::Set { lhs, expr } => {
-                let pointer = self.gen_lhs(&lhs.value);
-                let val = self.gen_expr(&expr.value);
-                self.builder.build_store(pointer, val).unwrap();
-                self.unit_type().const_zero().into()
-            }
-            Expr::BinOp { lhs, op, rhs } => {
-                match op {
-                    // short circuit operators
-                    Op::And => self.build_short_circuit_op(&lhs.value, &rhs.value, true),
-                    Op::Or => self.build_short_circuit_op(&lhs.value, &rhs.value, false),
-                    _ => {
-                        let l = self.gen_expr(&lhs.value).into_int_value();
-                        let r = self.gen_expr(&rhs.value).into_int_value();
-                        let res = match op {
-                            Op::Add => self.builder.build_int_add(l, r, "add"),
-                            Op::Sub => self.builder.build_int_sub(l, r, "sub"),
-                            Op::Mul => self.builder.build_int_mul(l, r, "mul"),
-                            Op::Mod => self.builder.build_int_signed_rem(l, r, "mod"),
-                            Op::Eq => self.builder.build_int_compare(IntPredicate::EQ, l, r, "eq"),
-                            Op::Neq => self.builder.build_int_compare(IntPredicate::NE, l, r, "neq"),
-                            Op::Lt => self.builder.build_int_compare(IntPredicate::SLT, l, r, "lt"),
-                            Op::Leq => self.builder.build_int_compare(IntPredicate::SLE, l, r, "leq"),
-                            Op::Gt => self.builder.build_int_compare(IntPredicate::SGT, l, r, "gt"),
-                            Op::Geq => self.builder.build_int_compare(IntPredicate::SGE, l, r, "geq"),
-                            Op::Div => self.call_binop_fun(l, r, "div"),
-                            Op::Pow => self.call_binop_fun(l, r, "pow"),
-                            Op::And | Op::Or => unreachable!()
-                        };
-                        res.unwrap().into()
+            }
+            pointer
+        }).collect();
+        self.temps = vec![None; fun.temps.len()];
+
+        // unreachable blocks are not generated, and the others come after their dominators,
+        // so the temporaries are generated before they are used
+        let order = fun.reverse_postorder();
+        let mut blocks = vec![None; fun.blocks.len()];
+        for &block in &order {
+            blocks[block] = Some(self.context.append_basic_block(llvm_fun, fun.blocks[block].label));
+        }
+        self.builder.build_unconditional_branch(blocks[0].unwrap()).unwrap();
+
+        // the instructions of a block may be split across llvm blocks by the runtime checks,
+        // so the phis take their values from the last one
+        let mut block_ends = vec![None; fun.blocks.len()];
+        let mut phis = vec![];
+        for &block in &order {
+            self.builder.position_at_end(blocks[block].unwrap());
+            for instr in &fun.blocks[block].instrs {
+                self.set_debug_location(&instr.span);
+                match &instr.value {
+                    Instr::Phi { dst, incoming } => {
+                        let phi = self.builder.build_phi(self.llvm_type(&fun.temps[*dst]), "phi").unwrap();
+                        self.temps[*dst] = Some(phi.as_basic_value());
+                        phis.push((phi, incoming));
                     }
+                    instr_value => self.gen_instr(prog, id, instr_value, &instr.span),
                 }
             }
-            Expr::Not { expr } => {
-                // short-circuit for chained not operations
-                let (not_count, inner_expr) = self.unwrap_not(&expr.value);
-                let val = self.gen_expr(inner_expr).into_int_value();
-                if not_count % 2 == 0 {
-                    // even number of nots (cancel out)
-                    val.into()
-                } else {
-                    // odd number of nots (negate)
-                    self.builder.build_not(val, "not").unwrap().into()
+            block_ends[block] = self.builder.get_insert_block();
+            let term = fun.blocks[block].term.as_ref().unwrap();
+            self.set_debug_location(&term.span);
+            self.gen_terminator(prog, id, &term.value, &blocks);
+        }
+        for (phi, incoming) in phis {
+            for (value, pred) in incoming {
+                if let Some(pred_end) = block_ends[*pred] {
+                    phi.add_incoming(&[(&self.gen_operand(value), pred_end)]);
                 }
             }
-            Expr::While { cond, expr } => {
-                let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
-                let cond_block = self.context.append_basic_block(parent, "cond");
-                let body_block = self.context.append_basic_block(parent, "body");
-                let after_block = self.context.append_basic_block(parent, "after");
+        }
+
+        self.gen_fun_epilogue();
+        self.exit_debug_scope();
+    }
+
+    // the entry block holds the stack slots of the function and branches to its body
+    fn gen_fun_prologue(&mut self, fun: FunctionValue<'ctx>) {
+        let entry = self.context.append_basic_block(fun, "entry");
+        let body = self.context.append_basic_block(fun, "body");
+        self.builder.position_at_end(body);
+        self.entry_block = Some(entry);
+        self.gc_roots.clear();
+    }
+
+    fn gen_fun_epilogue(&mut self) {
+        let entry = self.entry_block.take().unwrap();
+        let body = entry.get_next_basic_block().unwrap();
+        let roots = std::mem::take(&mut self.gc_roots);
+        let exit_points = std::mem::take(&mut self.exit_points);
+        let depth_counted = std::mem::take(&mut self.depth_counted);
+        let entry_builder = self.context.create_builder();
+        entry_builder.position_at_end(entry);
+
+        let frame = match self.options.gc {
+            Gc::MarkSweep if !roots.is_empty() => Some(self.gen_gc_frame(&entry_builder, &roots)),
+            _ => None,
+        };
+        entry_builder.build_unconditional_branch(body).unwrap();
+
+        // the roots are released before returning, and before the callee of a tail call reuses the stack frame
+        for exit_point in exit_points {
+            self.builder.position_before(&exit_point);
+            self.gen_gc_release_roots(&roots, frame);
+            if depth_counted {
+                self.gen_depth_decrement();
+            }
+        }
+    }
+
+    // sets the lowest address the stack may reach from the size limit of the stack, the first time main is called
+    fn gen_stack_limit(&mut self) {
+        let size_ty = self.size_type;
+        let i32_ty = self.context.i32_type();
+        let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let limit_ptr = self.stack_limit();
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let set_block = self.context.append_basic_block(parent, "set_stack_limit");
+        let cont_block = self.context.append_basic_block(parent, "stack_limit_set");
+        let limit = self.builder.build_load(size_ty, limit_ptr, "stack_limit").unwrap().into_int_value();
+        let unset = self.builder.build_int_compare(IntPredicate::EQ, limit, size_ty.const_zero(), "unset").unwrap();
+        self.builder.build_conditional_branch(unset, set_block, cont_block).unwrap();
+
+        // set block, where the size is { soft limit, hard limit } as returned by getrlimit(RLIMIT_STACK)
+        self.builder.position_at_end(set_block);
+        let entry_builder = self.context.create_builder();
+        entry_builder.position_at_end(self.entry_block.unwrap());
+        let rlimit = entry_builder.build_alloca(size_ty.array_type(2), "rlimit").unwrap();
+        let getrlimit = self.get_extern_fun("getrlimit", i32_ty.fn_type(&[i32_ty.into(), ptr_ty.into()], false));
+        let rlimit_ptr = self.builder.build_pointer_cast(rlimit, ptr_ty, "rlimit_ptr").unwrap();
+        let res = self.builder.build_call(getrlimit, &[i32_ty.const_int(3, false).into(), rlimit_ptr.into()], "res").unwrap();
+        let failed = self.builder.build_int_compare(
+            IntPredicate::NE, res.try_as_basic_value().left().unwrap().into_int_value(), i32_ty.const_zero(), "failed"
+        ).unwrap();
+        let soft_limit = self.builder.build_pointer_cast(rlimit, size_ty.ptr_type(AddressSpace::default()), "soft_limit").unwrap();
+        let size = self.builder.build_load(size_ty, soft_limit, "stack_size").unwrap().into_int_value();
+        let unlimited = self.builder.build_int_compare(IntPredicate::EQ, size, size_ty.const_all_ones(), "unlimited").unwrap();
+        let use_default = self.builder.build_or(failed, unlimited, "use_default").unwrap();
+        let size = self.builder.build_select(use_default, size_ty.const_int(DEFAULT_STACK_SIZE, false), size, "stack_size")
+            .unwrap()
+            .into_int_value();
+
+        // the limit is clamped at zero, as the stack cannot grow past it anyway
+        let base = self.gen_frame_address();
+        let fits = self.builder.build_int_compare(IntPredicate::ULT, size, base, "fits").unwrap();
+        let bottom = self.builder.build_int_sub(base, size, "bottom").unwrap();
+        let bottom = self.builder.build_select(fits, bottom, size_ty.const_zero(), "bottom").unwrap().into_int_value();
+        let limit = self.builder.build_int_add(bottom, size_ty.const_int(STACK_MARGIN, false), "stack_limit").unwrap();
+        self.builder.build_store(limit_ptr, limit).unwrap();
+        self.builder.build_unconditional_branch(cont_block).unwrap();
+
+        // cont block
+        self.builder.position_at_end(cont_block);
+    }
+
+    // stops the program when the frame of the function is below the stack limit
+    fn gen_stack_check(&mut self, id: &Id, span: &Span) {
+        let limit = self.builder.build_load(self.size_type, self.stack_limit(), "stack_limit").unwrap().into_int_value();
+        let frame = self.gen_frame_address();
+        let overflow = self.builder.build_int_compare(IntPredicate::ULT, frame, limit, "overflow").unwrap();
+        self.gen_stack_overflow(overflow, id, span);
+    }
+
+    // increments the number of nested calls, stopping the program when it exceeds the maximum depth
+    fn gen_depth_check(&mut self, id: &Id, span: &Span, max_depth: u64) {
+        let depth_ptr = self.call_depth();
+        let depth = self.builder.build_load(self.int_type(), depth_ptr, "depth").unwrap().into_int_value();
+        let depth = self.builder.build_int_add(depth, self.int_type().const_int(1, false), "depth").unwrap();
+        self.builder.build_store(depth_ptr, depth).unwrap();
+        self.depth_counted = true;
+
+        let max_depth = self.int_type().const_int(max_depth, false);
+        let too_deep = self.builder.build_int_compare(IntPredicate::UGT, depth, max_depth, "too_deep").unwrap();
+        self.gen_stack_overflow(too_deep, id, span);
+    }
+
+    // stops the program with a stack overflow in the function if the condition holds
+    fn gen_stack_overflow(&mut self, cond: IntValue<'ctx>, id: &Id, span: &Span) {
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let error_block = self.context.append_basic_block(parent, "stack_overflow");
+        let ok_block = self.context.append_basic_block(parent, "stack_ok");
+        self.builder.build_conditional_branch(cond, error_block, ok_block).unwrap();
+
+        // error block
+        self.builder.position_at_end(error_block);
+        let fmt = format!("{}: stack overflow in function `{}`\n", self.location(span), id.replace('%', "%%"));
+        self.gen_runtime_error(&fmt, &[]);
+
+        // ok block
+        self.builder.position_at_end(ok_block);
+    }
+
+    // address of the frame of the current function, as a size
+    fn gen_frame_address(&self) -> IntValue<'ctx> {
+        let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let frame_address = Intrinsic::find("llvm.frameaddress")
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, &[ptr_ty.into()]))
+            .expect("undefined intrinsic llvm.frameaddress");
+        let frame = self.builder
+            .build_call(frame_address, &[self.context.i32_type().const_zero().into()], "frame")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        self.builder.build_ptr_to_int(frame, self.size_type, "frame").unwrap()
+    }
+
+    // global lowest address of the stack that the frames of the functions may start at
+    fn stack_limit(&self) -> PointerValue<'ctx> {
+        let global = self.module.get_global("__stack_limit__").unwrap_or_else(|| {
+            let global = self.module.add_global(self.size_type, None, "__stack_limit__");
+            global.set_linkage(Linkage::Internal);
+            global.set_initializer(&self.size_type.const_zero());
+            global
+        });
+        global.as_pointer_value()
+    }
+
+    fn gen_depth_decrement(&mut self) {
+        let depth_ptr = self.call_depth();
+        let depth = self.builder.build_load(self.int_type(), depth_ptr, "depth").unwrap().into_int_value();
+        let depth = self.builder.build_int_sub(depth, self.int_type().const_int(1, false), "depth").unwrap();
+        self.builder.build_store(depth_ptr, depth).unwrap();
+    }
 
-                // while block
-                self.builder.build_unconditional_branch(cond_block).unwrap();
+    // global counter of the calls that have not returned yet
+    fn call_depth(&self) -> PointerValue<'ctx> {
+        let global = self.module.get_global("__call_depth__").unwrap_or_else(|| {
+            let global = self.module.add_global(self.int_type(), None, "__call_depth__");
+            global.set_linkage(Linkage::Internal);
+            global.set_initializer(&self.int_type().const_zero());
+            global
+        });
+        global.as_pointer_value()
+    }
 
-                // cond block
-                self.builder.position_at_end(cond_block);
-                let cond_val = self.gen_expr(&cond.value).into_int_value();
-                let zero = self.bool_type().const_zero();
-                let cmp = self.builder.build_int_compare(IntPredicate::NE, cond_val, zero, "while_cond").unwrap();
-                self.builder.build_conditional_branch(cmp, body_block, after_block).unwrap();
+    // pushes a frame { prev, count, [slots] } onto the shadow stack, returning the pointer to prev
+    fn gen_gc_frame(&self, entry_builder: &Builder<'ctx>, roots: &[(PointerValue<'ctx>, Type)]) -> PointerValue<'ctx> {
+        let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let i64_ty = self.context.i64_type();
+        let frame_ty = self.context.struct_type(
+            &[ptr_ty.into(), i64_ty.into(), ptr_ty.array_type(roots.len() as u32).into()],
+            false,
+        );
+        let gc_roots = self.module.get_global("__gc_roots__").unwrap().as_pointer_value();
+        let frame = entry_builder.build_alloca(frame_ty, "gc_frame").unwrap();
+        let prev = entry_builder.build_load(ptr_ty, gc_roots, "prev").unwrap();
+        let prev_ptr = entry_builder.build_struct_gep(frame_ty, frame, 0, "prev_ptr").unwrap();
+        entry_builder.build_store(prev_ptr, prev).unwrap();
+        let count_ptr = entry_builder.build_struct_gep(frame_ty, frame, 1, "count_ptr").unwrap();
+        entry_builder.build_store(count_ptr, i64_ty.const_int(roots.len() as u64, false)).unwrap();
+        for (index, (slot, _)) in roots.iter().enumerate() {
+            let indexes = [i64_ty.const_zero(), self.context.i32_type().const_int(2, false), i64_ty.const_int(index as u64, false)];
+            let slot_ptr = unsafe { entry_builder.build_gep(frame_ty, frame, &indexes, "slot_ptr").unwrap() };
+            let slot = entry_builder.build_pointer_cast(*slot, ptr_ty, "slot").unwrap();
+            entry_builder.build_store(slot_ptr, slot).unwrap();
+        }
+        let frame_ptr = entry_builder.build_pointer_cast(frame, ptr_ty, "frame").unwrap();
+        entry_builder.build_store(gc_roots, frame_ptr).unwrap();
+        prev_ptr
+    }
 
-                // body block
-                self.builder.position_at_end(body_block);
-                self.gen_expr(&expr.value);
-                self.builder.build_unconditional_branch(cond_block).unwrap();
+    // releases the arrays held by the function before it returns
+    fn gen_gc_release_roots(&mut self, roots: &[(PointerValue<'ctx>, Type)], frame: Option<PointerValue<'ctx>>) {
+        match self.options.gc {
+            Gc::Rc => {
+                for (slot, ty) in roots {
+                    let val = self.builder.build_load(self.llvm_type(ty), *slot, "root").unwrap();
+                    self.gen_gc_call("__gc_dec__", val);
+                }
+            }
+            Gc::MarkSweep => {
+                // pop the frame
+                if let Some(prev_ptr) = frame {
+                    let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+                    let gc_roots = self.module.get_global("__gc_roots__").unwrap().as_pointer_value();
+                    let prev = self.builder.build_load(ptr_ty, prev_ptr, "prev").unwrap();
+                    self.builder.build_store(gc_roots, prev).unwrap();
+                }
+            }
+            Gc::None => {}
+        }
+    }
 
-                // after block
-                self.builder.position_at_end(after_block);
+    // allocates a stack slot once per call, arrays are roots of the collector and start as null
+    fn gen_entry_alloca(&mut self, ty: &Type, name: &str) -> PointerValue<'ctx> {
+        let entry_builder = self.context.create_builder();
+        entry_builder.position_at_end(self.entry_block.unwrap());
+        let llvm_ty = self.llvm_type(ty);
+        let pointer = entry_builder.build_alloca(llvm_ty, name).unwrap();
+        if self.options.gc != Gc::None && ty.is_reference() {
+            entry_builder.build_store(pointer, llvm_ty.const_zero()).unwrap();
+            self.gc_roots.push((pointer, ty.clone()));
+        }
+        pointer
+    }
 
-                // return value
-                self.unit_type().const_zero().into()
+    // stores the value, updating the reference counts of the arrays and closures involved
+    // owned values were already counted, like the ones returned by functions
+    fn gen_store(&mut self, pointer: PointerValue<'ctx>, val: BasicValueEnum<'ctx>, ty: &Type, owned: bool) {
+        if self.options.gc == Gc::Rc && ty.is_reference() {
+            if !owned {
+                self.gen_gc_call("__gc_inc__", val);
             }
-            Expr::IfElse { cond, then, els } => {
+            let old = self.builder.build_load(self.llvm_type(ty), pointer, "old").unwrap();
+            self.gen_gc_call("__gc_dec__", old);
+        }
+        self.builder.build_store(pointer, val).unwrap();
+    }
+
+    // keeps a temporary array or closure alive until the function returns
+    fn gen_gc_root(&mut self, val: BasicValueEnum<'ctx>, ty: &Type, owned: bool) {
+        if self.options.gc != Gc::None && ty.is_reference() {
+            let slot = self.gen_entry_alloca(ty, "tmp");
+            self.gen_store(slot, val, ty, owned);
+        }
+    }
+
+    // releases an owned array or closure that is not stored anywhere
+    fn gen_gc_release(&mut self, val: BasicValueEnum<'ctx>, ty: &Type) {
+        if self.options.gc == Gc::Rc && ty.is_reference() {
+            self.gen_gc_call("__gc_dec__", val);
+        }
+    }
+
+    fn gen_gc_call(&self, name: &str, val: BasicValueEnum<'ctx>) {
+        let fun = self.module.get_function(name).unwrap_or_else(|| panic!("undefined function {}", name));
+        let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let obj = self.builder.build_pointer_cast(val.into_pointer_value(), ptr_ty, "obj").unwrap();
+        self.builder.build_call(fun, &[obj.into()], "").unwrap();
+    }
+
+
+    // global arrays and closures are roots of the collector, in a static frame at the bottom of the shadow stack
+    fn gen_gc_global_roots(&mut self, prog: &Program) {
+        let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let globals = prog.globals
+            .iter()
+            .zip(&self.globals)
+            .filter(|(global, _)| global.ty.is_reference())
+            .map(|(_, pointer)| pointer.as_pointer_value().const_cast(ptr_ty))
+            .collect::<Vec<_>>();
+        if globals.is_empty() {
+            return;
+        }
+        let i64_ty = self.context.i64_type();
+        let frame = self.context.const_struct(&[
+            ptr_ty.const_null().into(),
+            i64_ty.const_int(globals.len() as u64, false).into(),
+            ptr_ty.const_array(&globals).into(),
+        ], false);
+        let global = self.module.add_global(frame.get_type(), None, "__gc_globals__");
+        global.set_linkage(Linkage::Internal);
+        global.set_initializer(&frame);
+        let gc_roots = self.module.get_global("__gc_roots__").unwrap();
+        gc_roots.set_initializer(&global.as_pointer_value().const_cast(ptr_ty));
+    }
+
+    fn gen_instr(&mut self, prog: &Program, id: FunId, instr: &Instr, span: &Span) {
+        let fun = &prog.functions[id];
+        let ty_of = |operand: &Operand| fun.operand_type(operand, prog);
+        let val = match instr {
+            Instr::Param { index, .. } => {
+                // the closure comes before the params, except in main
                 let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
-                let then_block = self.context.append_basic_block(parent, "then");
-                let else_block = self.context.append_basic_block(parent, "else");
-                let merge_block = self.context.append_basic_block(parent, "merge");
-
-                // condition
-                let cond_val = self.gen_expr(&cond.value).into_int_value();
-                let zero = cond_val.get_type().const_zero();
-                let cmp = self.builder.build_int_compare(IntPredicate::NE, cond_val, zero, "if_cond").unwrap();
-                self.builder.build_conditional_branch(cmp, then_block, else_block).unwrap();
-
-                // then
-                self.builder.position_at_end(then_block);
-                let then_val = self.gen_expr(&then.value);
-                let then_end = self.builder.get_insert_block().unwrap();
-                self.builder.build_unconditional_branch(merge_block).unwrap();
-
-                // else
-                self.builder.position_at_end(else_block);
-                let else_val = self.gen_expr(&els.value);
-                let else_end = self.builder.get_insert_block().unwrap();
-                self.builder.build_unconditional_branch(merge_block).unwrap();
-
-                // merge
-                self.builder.position_at_end(merge_block);
-
-                // return branch result
-                let phi = self.builder.build_phi(then_val.get_type(), "phi").unwrap();
-                phi.add_incoming(&[(&then_val, then_end), (&else_val, else_end)]);
-                phi.as_basic_value()
-            }
-            Expr::FunCall { id, args } => {
-                let fun_name = match id.value.as_str() {
-                    "print" => {
-                        // get the bit width of the first argument to know which print function to call
-                        let arg = self.gen_expr(&args.first().unwrap().value).get_type();
-                        match arg {
-                            BasicTypeEnum::IntType(ty) => {
-                                match ty.get_bit_width() {
-                                    1 => "__print_bool__",
-                                    32 => "__print_int__",
-                                    _ => panic!("unsupported bit width for print: {}", ty.get_bit_width()),
-                                }
-                            }
-                            BasicTypeEnum::PointerType(_) => "__print_string__",
-                            BasicTypeEnum::StructType(ty) if ty.get_field_types().is_empty() =>
-                                "__print_unit__",
-                            _ => panic!("unsupported type for print: {:?}", arg),
-                        }
+                let index = if self.env.is_some() { *index + 1 } else { *index };
+                Some(parent.get_nth_param(index as u32).unwrap())
+            }
+            Instr::Load { local, .. } => {
+                let local_ty = self.llvm_type(&fun.locals[*local].ty);
+                Some(self.builder.build_load(local_ty, self.locals[*local], &fun.locals[*local].name).unwrap())
+            }
+            Instr::Store { local, value, owned } => {
+                let val = self.gen_operand(value);
+                self.gen_store(self.locals[*local], val, &fun.locals[*local].ty, *owned);
+                None
+            }
+            Instr::LoadGlobal { global, .. } => {
+                let global_ty = self.llvm_type(&prog.globals[*global].ty);
+                let pointer = self.globals[*global].as_pointer_value();
+                Some(self.builder.build_load(global_ty, pointer, &prog.globals[*global].name).unwrap())
+            }
+            Instr::StoreGlobal { global, value } => {
+                let val = self.gen_operand(value);
+                self.gen_store(self.globals[*global].as_pointer_value(), val, &prog.globals[*global].ty, false);
+                None
+            }
+            // function values and arrays are compared by reference
+            Instr::BinOp { op: op @ (Op::Eq | Op::Neq), lhs, rhs, .. } if matches!(ty_of(lhs), Type::Fun(_) | Type::Array(_)) => {
+                let l = self.gen_operand(lhs).into_pointer_value();
+                let r = self.gen_operand(rhs).into_pointer_value();
+                let predicate = if matches!(op, Op::Eq) { IntPredicate::EQ } else { IntPredicate::NE };
+                let l = self.builder.build_ptr_to_int(l, self.context.i64_type(), "lhs_addr").unwrap();
+                let r = self.builder.build_ptr_to_int(r, self.context.i64_type(), "rhs_addr").unwrap();
+                Some(self.builder.build_int_compare(predicate, l, r, "ptr_cmp").unwrap().into())
+            }
+            // strings are compared by content, like in the interpreter
+            Instr::BinOp { op: op @ (Op::Eq | Op::Neq), lhs, rhs, .. } if matches!(ty_of(lhs), Type::String) => {
+                let i32_ty = self.context.i32_type();
+                let str_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+                let strcmp = self.get_extern_fun("strcmp", i32_ty.fn_type(&[str_ty.into(), str_ty.into()], false));
+                let l = self.gen_operand(lhs);
+                let r = self.gen_operand(rhs);
+                let cmp = self.builder.build_call(strcmp, &[l.into(), r.into()], "str_cmp").unwrap();
+                let cmp = cmp.try_as_basic_value().left().unwrap().into_int_value();
+                let predicate = if matches!(op, Op::Eq) { IntPredicate::EQ } else { IntPredicate::NE };
+                Some(self.builder.build_int_compare(predicate, cmp, i32_ty.const_zero(), "str_eq").unwrap().into())
+            }
+            Instr::BinOp { op, lhs, rhs, .. } => {
+                let l = self.gen_operand(lhs).into_int_value();
+                let r = self.gen_operand(rhs).into_int_value();
+                let res = match op {
+                    Op::Add | Op::Sub | Op::Mul | Op::Pow if self.options.overflow == Overflow::Trap => {
+                        Ok(self.gen_checked_op(l, r, op, span))
                     }
-                    _ => &id.value
+                    Op::Add => self.builder.build_int_add(l, r, "add"),
+                    Op::Sub => self.builder.build_int_sub(l, r, "sub"),
+                    Op::Mul => self.builder.build_int_mul(l, r, "mul"),
+                    Op::Div | Op::Mod => Ok(self.gen_checked_division(l, r, op, span)),
+                    Op::Eq => self.builder.build_int_compare(IntPredicate::EQ, l, r, "eq"),
+                    Op::Neq => self.builder.build_int_compare(IntPredicate::NE, l, r, "neq"),
+                    Op::Lt => self.builder.build_int_compare(IntPredicate::SLT, l, r, "lt"),
+                    Op::Leq => self.builder.build_int_compare(IntPredicate::SLE, l, r, "leq"),
+                    Op::Gt => self.builder.build_int_compare(IntPredicate::SGT, l, r, "gt"),
+                    Op::Geq => self.builder.build_int_compare(IntPredicate::SGE, l, r, "geq"),
+                    Op::Pow => self.call_binop_fun(l, r, "pow"),
+                    Op::And | Op::Or => panic!("operator {} is not lowered to branches", op.to_text()),
                 };
-                let fun = self.module.get_function(fun_name)
-                    .expect(format!("undefined function {}", fun_name).as_str());
-                let args = args
-                    .iter()
-                    .map(|arg| BasicMetadataValueEnum::from(self.gen_expr(&arg.value)))
-                    .collect::<Vec<_>>();
-                let call_site = self.builder.build_call(fun, &args, "call").unwrap();
-                call_site
+                Some(res.unwrap().into())
+            }
+            Instr::Not { value, .. } => {
+                let val = self.gen_operand(value).into_int_value();
+                Some(self.builder.build_not(val, "not").unwrap().into())
+            }
+            Instr::Call { callee, args, .. } => {
+                let call_site = self.gen_call(callee, args, &ty_of(callee));
+                let ret_val = call_site
                     .try_as_basic_value()
                     .left()
-                    .unwrap_or_else(|| self.unit_type().const_zero().into())
+                    .unwrap_or_else(|| self.unit_type().const_zero().into());
+                Some(ret_val)
+            }
+            Instr::NewArray { elem_ty, size, init, .. } => {
+                let size = self.gen_operand(size).into_int_value();
+                let init = self.gen_operand(init);
+                let arr = self.gen_array_alloc(elem_ty, size, span);
+
+                // initialize every element with the same value
+                self.build_array_loop(arr, elem_ty, |codegen, elem_ptr, _| {
+                    codegen.gen_store(elem_ptr, init, elem_ty, false);
+                });
+                Some(arr.into())
+            }
+            Instr::CheckIndex { array, index } => {
+                if self.options.bounds_checks {
+                    let arr = self.gen_operand(array).into_pointer_value();
+                    let index = self.gen_operand(index).into_int_value();
+                    self.gen_bounds_check(arr, &ty_of(array), index, span.clone());
+                }
+                None
+            }
+            Instr::Index { array, index, .. } => {
+                let (elem_ptr, elem_ty) = self.gen_index(array, index, &ty_of(array));
+                Some(self.builder.build_load(self.llvm_type(&elem_ty), elem_ptr, "elem").unwrap())
             }
-            Expr::Id(id) => {
-                let (pointer, ty) = self.symbols.lookup(&id.value)
-                    .expect(format!("undefined variable {}", id.value).as_str());
-                self.builder.build_load(ty, pointer, &id.value).unwrap()
+            Instr::SetIndex { array, index, value } => {
+                let (elem_ptr, elem_ty) = self.gen_index(array, index, &ty_of(array));
+                let val = self.gen_operand(value);
+                self.gen_store(elem_ptr, val, &elem_ty, false);
+                None
+            }
+            Instr::Length { array, .. } => {
+                let arr = self.gen_operand(array).into_pointer_value();
+                Some(self.gen_array_length(arr, &ty_of(array)).into())
+            }
+            Instr::Print { value } => {
+                let val = self.gen_operand(value);
+                self.gen_print(val, &ty_of(value));
+                None
+            }
+            Instr::Phi { .. } => panic!("phis are generated with their block"),
+            Instr::Root { value, owned } => {
+                let val = self.gen_operand(value);
+                self.gen_gc_root(val, &ty_of(value), *owned);
+                None
+            }
+            Instr::Retain { value } => {
+                let val = self.gen_operand(value);
+                if self.options.gc == Gc::Rc {
+                    self.gen_gc_call("__gc_inc__", val);
+                } else {
+                    self.gen_gc_root(val, &ty_of(value), false);
+                }
+                None
+            }
+            Instr::Release { value } => {
+                let val = self.gen_operand(value);
+                self.gen_gc_release(val, &ty_of(value));
+                None
             }
-            Expr::Int(n) => self.int_type().const_int(*n as u64, true).into(),
-            Expr::Bool(b) => self.bool_type().const_int(*b as u64, false).into(),
-            Expr::Unit => self.unit_type().const_zero().into(),
-            Expr::String(str) => self.builder.build_global_string_ptr(str, "str").unwrap().as_pointer_value().into(),
-            Expr::NewArray { .. } => todo!(),
-            Expr::ArrayIndex { .. } => todo!(),
+            Instr::Closure { fun: nested, captures, .. } => {
+                let captures = captures.iter().map(|value| (self.gen_operand(value), ty_of(value))).collect::<Vec<_>>();
+                Some(self.gen_closure(*nested, &prog.functions[*nested].captures, &captures, span).into())
+            }
+            Instr::Capture { index, .. } => {
+                let (struct_ty, fields) = self.closure_struct_type(&fun.captures);
+                let env = self.builder.build_pointer_cast(self.env.unwrap(), struct_ty.ptr_type(AddressSpace::default()), "env").unwrap();
+                let field_ptr = self.builder.build_struct_gep(struct_ty, env, fields[*index], "capture_ptr").unwrap();
+                Some(self.builder.build_load(self.llvm_type(&fun.captures[*index]), field_ptr, "capture").unwrap())
+            }
+            Instr::Env { .. } => Some(self.env.unwrap().into()),
+        };
+        if let (Some(dst), Some(val)) = (instr.dst(), val) {
+            self.temps[dst] = Some(val);
         }
     }
 
-    fn gen_lhs(&mut self, lhs: &Lhs) -> PointerValue<'ctx> {
-       match lhs {
-           Lhs::Var { id } => self.symbols.lookup(&id.value)
-               .expect(format!("undefined variable {}", id.value).as_str()).0,
-           Lhs::Index { .. } => unimplemented!("array index")
-       }
+    fn gen_terminator(&mut self, prog: &Program, id: FunId, term: &Terminator, blocks: &[Option<BasicBlock<'ctx>>]) {
+        let fun = &prog.functions[id];
+        match term {
+            Terminator::Jump(to) => {
+                self.builder.build_unconditional_branch(blocks[*to].unwrap()).unwrap();
+            }
+            Terminator::Branch { cond, then, els } => {
+                let cond = self.gen_operand(cond).into_int_value();
+                self.builder.build_conditional_branch(cond, blocks[*then].unwrap(), blocks[*els].unwrap()).unwrap();
+            }
+            Terminator::Return(value) => {
+                // main returns the exit code of the program
+                let i32_ty = self.context.i32_type();
+                let ret_val = match fun.ty.ret.deref() {
+                    Type::Unit if id == prog.main => Some(i32_ty.const_zero().into()),
+                    Type::Int if id == prog.main => {
+                        let code = self.gen_operand(value).into_int_value();
+                        Some(self.builder.build_int_truncate(code, i32_ty, "exit_code").unwrap().into())
+                    }
+                    Type::Unit => None,
+                    _ => Some(self.gen_operand(value)),
+                };
+                // the caller takes the reference to the returned array or closure
+                if let (Gc::Rc, true, Some(val)) = (self.options.gc, fun.ty.ret.is_reference(), ret_val) {
+                    self.gen_gc_call("__gc_inc__", val);
+                }
+                let ret = match ret_val {
+                    Some(val) => self.builder.build_return(Some(&val)).unwrap(),
+                    None => self.builder.build_return(None).unwrap(),
+                };
+                self.exit_points.push(ret);
+            }
+            Terminator::TailCall { callee, args } => {
+                // the roots of the caller are released before the call by the epilogue
+                let call_site = self.gen_call(callee, args, &fun.operand_type(callee, prog));
+                call_site.set_tail_call(true);
+                self.exit_points.push(self.builder.get_insert_block().unwrap().get_last_instruction().unwrap());
+                match call_site.try_as_basic_value().left() {
+                    Some(val) => self.builder.build_return(Some(&val)).unwrap(),
+                    None => self.builder.build_return(None).unwrap(),
+                };
+            }
+            Terminator::Exit(code) => {
+                let code = self.gen_operand(code).into_int_value();
+                let code = self.builder.build_int_truncate(code, self.context.i32_type(), "exit_code").unwrap();
+                self.gen_exit(code);
+            }
+        }
+    }
+
+    // direct call to a function, or indirect call through the function of a closure, which is passed to it,
+    // while top-level functions called directly take a null closure and nested ones calling themselves their own
+    fn gen_call(&mut self, callee: &Operand, args: &[Operand], callee_ty: &Type) -> CallSiteValue<'ctx> {
+        let mut args = args.iter().map(|arg| self.gen_operand(arg).into()).collect::<Vec<BasicMetadataValueEnum>>();
+        match callee {
+            Operand::Fun(id) => {
+                let fun = self.functions[*id];
+                let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+                let env = match self.env {
+                    Some(env) if fun == parent => env,
+                    _ => self.context.i8_type().ptr_type(AddressSpace::default()).const_null(),
+                };
+                if *id != self.main {
+                    args.insert(0, env.into());
+                }
+                let call_site = self.builder.build_call(fun, &args, "call").unwrap();
+                call_site.set_call_convention(fun.get_call_conventions());
+                call_site
+            }
+            _ => {
+                let Type::Fun(fun_ty) = callee_ty else {
+                    panic!("type {:?} is not callable", callee_ty)
+                };
+                // the function comes after the captured references
+                let closure = self.gen_operand(callee).into_pointer_value();
+                let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+                let refs_ty = self.context.struct_type(&[self.int_type().into(), ptr_ty.array_type(0).into()], false);
+                let refs_ptr = self.builder.build_pointer_cast(closure, refs_ty.ptr_type(AddressSpace::default()), "refs_ptr").unwrap();
+                let len_ptr = self.builder.build_struct_gep(refs_ty, refs_ptr, 0, "len_ptr").unwrap();
+                let refs = self.builder.build_load(self.int_type(), len_ptr, "refs").unwrap().into_int_value();
+                let indexes = [self.int_type().const_zero(), self.context.i32_type().const_int(1, false), refs];
+                let fun_ptr_ptr = unsafe { self.builder.build_gep(refs_ty, refs_ptr, &indexes, "fun_ptr_ptr").unwrap() };
+                let fun_ptr = self.builder.build_load(ptr_ty, fun_ptr_ptr, "fun_ptr").unwrap().into_pointer_value();
+                let fn_type = self.fun_type(fun_ty, false);
+                let fun_ptr = self.builder.build_pointer_cast(fun_ptr, fn_type.ptr_type(AddressSpace::default()), "fun").unwrap();
+                args.insert(0, closure.into());
+                let call_site = self.builder.build_indirect_call(fn_type, fun_ptr, &args, "call").unwrap();
+                call_site.set_call_convention(TAIL_CALL_CONV);
+                call_site
+            }
+        }
+    }
+
+    // allocates the closure of the nested function, storing the captured values in their slots
+    fn gen_closure(&mut self, id: FunId, capture_types: &[Type], captures: &[(BasicValueEnum<'ctx>, Type)], span: &Span) -> PointerValue<'ctx> {
+        let (struct_ty, fields) = self.closure_struct_type(capture_types);
+        let size = struct_ty.size_of().unwrap();
+        let raw = self.gen_alloc(size, true, span);
+        let closure = self.builder.build_pointer_cast(raw, struct_ty.ptr_type(AddressSpace::default()), "closure").unwrap();
+
+        let refs = capture_types.iter().filter(|ty| ty.is_reference()).count();
+        let refs_ptr = self.builder.build_struct_gep(struct_ty, closure, 0, "refs_ptr").unwrap();
+        self.builder.build_store(refs_ptr, self.int_type().const_int(refs as u64, false)).unwrap();
+        for ((val, ty), field) in captures.iter().zip(&fields) {
+            let field_ptr = self.builder.build_struct_gep(struct_ty, closure, *field, "capture_ptr").unwrap();
+            self.gen_store(field_ptr, *val, ty, false);
+        }
+        let fun_ptr = self.builder.build_struct_gep(struct_ty, closure, refs as u32 + 1, "fun_ptr").unwrap();
+        let fun = self.functions[id].as_global_value().as_pointer_value();
+        let fun = self.builder.build_pointer_cast(fun, self.context.i8_type().ptr_type(AddressSpace::default()), "fun").unwrap();
+        self.builder.build_store(fun_ptr, fun).unwrap();
+        raw
+    }
+
+    // constant closure of a top-level function, which is never freed since it is always marked and referenced
+    fn gen_static_closure(&mut self, id: FunId) -> PointerValue<'ctx> {
+        if let Some(closure) = self.static_closures[id] {
+            return closure;
+        }
+        let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let i64_ty = self.context.i64_type();
+        let fun_ptr = self.functions[id].as_global_value().as_pointer_value().const_cast(ptr_ty);
+        let closure = self.context.const_struct(&[i64_ty.const_zero().into(), fun_ptr.into()], false);
+        let value = match self.options.gc {
+            Gc::None => closure,
+            // header { next object, mark or reference count, has references, size }
+            _ => {
+                let header = self.context.const_struct(&[
+                    ptr_ty.const_null().into(),
+                    i64_ty.const_int(1 << 62, false).into(),
+                    i64_ty.const_zero().into(),
+                    i64_ty.const_zero().into(),
+                ], false);
+                self.context.const_struct(&[header.into(), closure.into()], false)
+            }
+        };
+        let global = self.module.add_global(value.get_type(), None, &format!("{}.closure", self.functions[id].get_name().to_str().unwrap()));
+        global.set_linkage(Linkage::Internal);
+        global.set_constant(self.options.gc == Gc::None);
+        global.set_initializer(&value);
+        let pointer = match self.options.gc {
+            Gc::None => global.as_pointer_value(),
+            _ => {
+                let indexes = [self.context.i32_type().const_zero(), self.context.i32_type().const_int(1, false)];
+                unsafe { global.as_pointer_value().const_in_bounds_gep(value.get_type(), &indexes) }
+            }
+        }.const_cast(ptr_ty);
+        self.static_closures[id] = Some(pointer);
+        pointer
     }
 
-    fn build_short_circuit_op(&mut self, lhs: &Expr, rhs: &Expr, and_op: bool) -> BasicValueEnum<'ctx> {
-        let fun = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+    // returns the address of the indexed element and its type
+    fn gen_index(&mut self, array: &Operand, index: &Operand, arr_ty: &Type) -> (PointerValue<'ctx>, Type) {
+        let Type::Array(elem_ty) = arr_ty else {
+            panic!("type {:?} is not indexable", arr_ty)
+        };
+        let arr = self.gen_operand(array).into_pointer_value();
+        let index = self.gen_operand(index).into_int_value();
+        (self.gen_elem_ptr(arr, elem_ty, index), *elem_ty.clone())
+    }
+
+    fn gen_operand(&mut self, operand: &Operand) -> BasicValueEnum<'ctx> {
+        match operand {
+            Operand::Temp(temp) => self.temps[*temp].unwrap_or_else(|| panic!("temporary t{} used before its definition", temp)),
+            Operand::Int(n) => self.int_type().const_int(*n as u64, true).into(),
+            Operand::Bool(b) => self.bool_type().const_int(*b as u64, false).into(),
+            Operand::Unit => self.unit_type().const_zero().into(),
+            Operand::String(str) => self.builder.build_global_string_ptr(str, "str").unwrap().as_pointer_value().into(),
+            // top-level functions are used as static closures
+            Operand::Fun(id) => self.gen_static_closure(*id).into(),
+            Operand::Undef(ty) => self.llvm_type(ty).const_zero(),
+        }
+    }
+
+    fn gen_bounds_check(&mut self, arr: PointerValue<'ctx>, arr_ty: &Type, index: IntValue<'ctx>, span: Span) {
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let error_block = self.context.append_basic_block(parent, "out_of_bounds");
+        let ok_block = self.context.append_basic_block(parent, "in_bounds");
+
+        // negative indexes are also caught by the unsigned comparison
+        let len = self.gen_array_length(arr, arr_ty);
+        let out_of_bounds = self.builder.build_int_compare(IntPredicate::UGE, index, len, "out_of_bounds").unwrap();
+        self.builder.build_conditional_branch(out_of_bounds, error_block, ok_block).unwrap();
+
+        // error block
+        self.builder.position_at_end(error_block);
+        let fmt = format!("{}: index %lld out of bounds for length %lld\n", self.location(&span));
+        self.gen_runtime_error(&fmt, &[index.into(), len.into()]);
+
+        // ok block
+        self.builder.position_at_end(ok_block);
+    }
+
+    // lowers the operation to a *.with.overflow call that stops the program on overflow
+    fn gen_checked_op(&mut self, l: IntValue<'ctx>, r: IntValue<'ctx>, op: &Op, span: &Span) -> IntValue<'ctx> {
+        let fun = match op {
+            Op::Add => self.get_intrinsic("llvm.sadd.with.overflow"),
+            Op::Sub => self.get_intrinsic("llvm.ssub.with.overflow"),
+            Op::Mul => self.get_intrinsic("llvm.smul.with.overflow"),
+            Op::Pow => self.module.get_function("__pow_with_overflow__").unwrap(),
+            _ => panic!("operator {} cannot overflow", op.to_text()),
+        };
+        let res = self.builder
+            .build_call(fun, &[l.into(), r.into()], "checked")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let val = self.builder.build_extract_value(res, 0, "val").unwrap().into_int_value();
+        let overflow = self.builder.build_extract_value(res, 1, "overflow").unwrap().into_int_value();
+
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let error_block = self.context.append_basic_block(parent, "overflow");
+        let ok_block = self.context.append_basic_block(parent, "no_overflow");
+        self.builder.build_conditional_branch(overflow, error_block, ok_block).unwrap();
+
+        // error block
+        self.builder.position_at_end(error_block);
+        let fmt = format!("{}: integer overflow in '{}'\n", self.location(span), op.to_text());
+        self.gen_runtime_error(&fmt, &[]);
+
+        // ok block
+        self.builder.position_at_end(ok_block);
+        val
+    }
 
-        // lhs
-        let lhs_val = self.gen_expr(lhs).into_int_value();
-        let lhs_block = self.builder.get_insert_block().unwrap();
+    // division and modulo, stopping the program when the divisor is zero
+    fn gen_checked_division(&mut self, l: IntValue<'ctx>, r: IntValue<'ctx>, op: &Op, span: &Span) -> IntValue<'ctx> {
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let error_block = self.context.append_basic_block(parent, "div_by_zero");
+        let ok_block = self.context.append_basic_block(parent, "div");
+        let is_zero = self.builder.build_int_compare(IntPredicate::EQ, r, self.int_type().const_zero(), "is_zero").unwrap();
+        self.builder.build_conditional_branch(is_zero, error_block, ok_block).unwrap();
 
-        // rhs and merge blocks
-        let rhs_block = self.context.append_basic_block(fun, "rhs");
-        let merge_block = self.context.append_basic_block(fun, "merge");
+        // error block
+        self.builder.position_at_end(error_block);
+        let fmt = format!("{}: division by zero in '{}'\n", self.location(span), op.to_text().replace('%', "%%"));
+        self.gen_runtime_error(&fmt, &[]);
 
-        // and => branch to rhs when lhs is true
-        // or => branch to rhs only when lhs is false
-        let cmp = if and_op { lhs_val } else { self.builder.build_not(lhs_val, "not").unwrap() };
-        self.builder.build_conditional_branch(cmp, rhs_block, merge_block).unwrap();
+        // ok block, where dividing the minimum integer by -1 wraps around instead of trapping,
+        // so the divisor -1 is replaced by 1 and the quotient negated
+        self.builder.position_at_end(ok_block);
+        let minus_one = self.int_type().const_all_ones();
+        let is_minus_one = self.builder.build_int_compare(IntPredicate::EQ, r, minus_one, "is_minus_one").unwrap();
+        let divisor = self.builder.build_select(is_minus_one, self.int_type().const_int(1, false), r, "divisor").unwrap().into_int_value();
+        match op {
+            Op::Div => {
+                let div = self.builder.build_int_signed_div(l, divisor, "div").unwrap();
+                let neg = self.builder.build_int_sub(self.int_type().const_zero(), l, "neg").unwrap();
+                self.builder.build_select(is_minus_one, neg, div, "div").unwrap().into_int_value()
+            }
+            Op::Mod => self.builder.build_int_signed_rem(l, divisor, "mod").unwrap(),
+            _ => panic!("operator {} is not a division", op.to_text()),
+        }
+    }
+
+    // prints the formatted message to stderr and exits the program
+    fn gen_runtime_error(&mut self, fmt: &str, args: &[BasicMetadataValueEnum<'ctx>]) {
+        let i32_ty = self.context.i32_type();
+        let str_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let dprintf = self.get_extern_fun("dprintf", i32_ty.fn_type(&[i32_ty.into(), str_ty.into()], true));
+
+        let fmt = self.builder.build_global_string_ptr(fmt, "err_fmt").unwrap().as_pointer_value();
+        let stderr = i32_ty.const_int(2, false);
+        let dprintf_args = [stderr.into(), fmt.into()].into_iter().chain(args.iter().copied()).collect::<Vec<_>>();
+        self.builder.build_call(dprintf, &dprintf_args, "err").unwrap();
+        self.gen_exit(i32_ty.const_int(1, false));
+    }
+
+    // exits the program with the code, flushing its output
+    fn gen_exit(&mut self, code: IntValue<'ctx>) {
+        let i32_ty = self.context.i32_type();
+        let exit = self.get_extern_fun("exit", self.context.void_type().fn_type(&[i32_ty.into()], false));
+        self.builder.build_call(exit, &[code.into()], "exit").unwrap();
+        self.builder.build_unreachable().unwrap();
+    }
 
-        // rhs
-        self.builder.position_at_end(rhs_block);
-        let rhs_val = self.gen_expr(rhs).into_int_value();
-        self.builder.build_unconditional_branch(merge_block).unwrap();
-        let rhs_end = self.builder.get_insert_block().unwrap();
+    fn enter_debug_scope(&mut self, fun: FunctionValue<'ctx>, name: &str, fun_ty: &FunType, span: &Span) {
+        if let Some(debug) = &mut self.debug {
+            debug.enter_fun(fun, name, fun_ty, span);
+        }
+        self.set_debug_location(span);
+    }
+
+    fn exit_debug_scope(&mut self) {
+        if let Some(debug) = &mut self.debug {
+            debug.exit_fun();
+            self.builder.unset_current_debug_location();
+        }
+    }
+
+    fn set_debug_location(&self, span: &Span) {
+        if let Some(location) = self.debug.as_ref().and_then(|debug| debug.location(span)) {
+            self.builder.set_current_debug_location(location);
+        }
+    }
 
-        // merge
-        self.builder.position_at_end(merge_block);
-        let phi = self.builder.build_phi(self.bool_type(), "logical_op").unwrap();
-        phi.add_incoming(&[(&lhs_val, lhs_block), (&rhs_val, rhs_end)]);
-        phi.as_basic_value().as_basic_value_enum()
+    // source location of a span, in the format file:line:col-line:col (escaped for printf)
+    fn location(&self, span: &Span) -> String {
+        let Location { start, end } = Location::from_span(&self.src, span);
+        let file = self.module.get_name().to_str().unwrap();
+        format!("{}:{}:{}-{}:{}", file, start.0, start.1, end.0, end.1).replace('%', "%%")
     }
 
+    fn gen_array_alloc(&mut self, elem_ty: &Type, size: IntValue<'ctx>, span: &Span) -> PointerValue<'ctx> {
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let error_block = self.context.append_basic_block(parent, "invalid_size");
+        let ok_block = self.context.append_basic_block(parent, "valid_size");
+
+        // negative sizes are also caught by the unsigned comparison, and the limit keeps the size in bytes in a size_t
+        let max_length = MAX_ARRAY_LENGTH.min(1 << (self.size_type.get_bit_width() - 4));
+        let max_length = self.int_type().const_int(max_length as u64, false);
+        let invalid = self.builder.build_int_compare(IntPredicate::UGT, size, max_length, "invalid_size").unwrap();
+        self.builder.build_conditional_branch(invalid, error_block, ok_block).unwrap();
+
+        // error block
+        self.builder.position_at_end(error_block);
+        let fmt = format!("{}: invalid array size %lld\n", self.location(span));
+        self.gen_runtime_error(&fmt, &[size.into()]);
+
+        // ok block
+        self.builder.position_at_end(ok_block);
+
+        // header with the length followed by the elements, only arrays of arrays reference other heap objects
+        let struct_ty = self.array_struct_type(elem_ty);
+        let header_size = struct_ty.size_of().unwrap();
+        let elem_size = self.llvm_type(elem_ty).size_of().unwrap();
+        let elems_size = self.builder.build_int_mul(size, elem_size, "elems_size").unwrap();
+        let total_size = self.builder.build_int_add(header_size, elems_size, "size").unwrap();
+        let raw = self.gen_alloc(total_size, elem_ty.is_reference(), span);
+        let arr = self.builder.build_pointer_cast(raw, struct_ty.ptr_type(AddressSpace::default()), "arr").unwrap();
+
+        // store length
+        let len_ptr = self.builder.build_struct_gep(struct_ty, arr, 0, "len_ptr").unwrap();
+        self.builder.build_store(len_ptr, size).unwrap();
+        arr
+    }
+
+    // allocates the heap object, stopping the program when there is no memory left
+    fn gen_alloc(&mut self, size: IntValue<'ctx>, has_refs: bool, span: &Span) -> PointerValue<'ctx> {
+        let raw = match self.options.gc {
+            Gc::None => {
+                let malloc_size = self.builder.build_int_truncate_or_bit_cast(size, self.size_type, "malloc_size").unwrap();
+                self.builder.build_call(self.get_malloc(), &[malloc_size.into()], "raw")
+            }
+            _ => {
+                let gc_alloc = self.module.get_function("__gc_alloc__").unwrap();
+                let has_refs = self.bool_type().const_int(has_refs as u64, false);
+                self.builder.build_call(gc_alloc, &[size.into(), has_refs.into()], "raw")
+            }
+        };
+        let raw = raw
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let error_block = self.context.append_basic_block(parent, "out_of_memory");
+        let ok_block = self.context.append_basic_block(parent, "allocated");
+        let is_null = self.builder.build_is_null(raw, "is_null").unwrap();
+        self.builder.build_conditional_branch(is_null, error_block, ok_block).unwrap();
+
+        // error block
+        self.builder.position_at_end(error_block);
+        let fmt = format!("{}: out of memory\n", self.location(span));
+        self.gen_runtime_error(&fmt, &[]);
+
+        // ok block
+        self.builder.position_at_end(ok_block);
+        raw
+    }
+
+    fn gen_array_length(&mut self, arr: PointerValue<'ctx>, arr_ty: &Type) -> IntValue<'ctx> {
+        let Type::Array(elem_ty) = arr_ty else {
+            panic!("type {:?} has no length", arr_ty)
+        };
+        let struct_ty = self.array_struct_type(elem_ty);
+        let len_ptr = self.builder.build_struct_gep(struct_ty, arr, 0, "len_ptr").unwrap();
+        self.builder.build_load(self.int_type(), len_ptr, "len").unwrap().into_int_value()
+    }
+
+    fn gen_elem_ptr(&mut self, arr: PointerValue<'ctx>, elem_ty: &Type, index: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let struct_ty = self.array_struct_type(elem_ty);
+        let zero = self.int_type().const_zero();
+        // struct field indexes must be i32
+        let elems_field = self.context.i32_type().const_int(1, false);
+        unsafe {
+            self.builder.build_gep(struct_ty, arr, &[zero, elems_field, index], "elem_ptr").unwrap()
+        }
+    }
+
+    // generates a loop over all elements of the array, calling body with each element pointer and index
+    fn build_array_loop<F>(&mut self, arr: PointerValue<'ctx>, elem_ty: &Type, mut body: F)
+    where
+        F: FnMut(&mut Self, PointerValue<'ctx>, IntValue<'ctx>),
+    {
+        let parent = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let cond_block = self.context.append_basic_block(parent, "arr_cond");
+        let body_block = self.context.append_basic_block(parent, "arr_body");
+        let after_block = self.context.append_basic_block(parent, "arr_after");
+
+        let len = self.gen_array_length(arr, &Type::Array(Box::new(elem_ty.clone())));
+        let entry_block = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(cond_block).unwrap();
+
+        // cond block
+        self.builder.position_at_end(cond_block);
+        let phi = self.builder.build_phi(self.int_type(), "i").unwrap();
+        let i = phi.as_basic_value().into_int_value();
+        let cmp = self.builder.build_int_compare(IntPredicate::SLT, i, len, "arr_cond").unwrap();
+        self.builder.build_conditional_branch(cmp, body_block, after_block).unwrap();
+
+        // body block
+        self.builder.position_at_end(body_block);
+        let elem_ptr = self.gen_elem_ptr(arr, elem_ty, i);
+        body(self, elem_ptr, i);
+        let next = self.builder.build_int_add(i, self.int_type().const_int(1, false), "next").unwrap();
+        let body_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(cond_block).unwrap();
+        phi.add_incoming(&[(&self.int_type().const_zero(), entry_block), (&next, body_end)]);
+
+        // after block
+        self.builder.position_at_end(after_block);
+    }
+
+    fn gen_print(&mut self, val: BasicValueEnum<'ctx>, ty: &Type) {
+        match ty {
+            Type::Int => self.call_print_fun("__print_int__", &[val.into()]),
+            Type::Bool => self.call_print_fun("__print_bool__", &[val.into()]),
+            Type::String => self.call_print_fun("__print_string__", &[val.into()]),
+            Type::Unit => self.call_print_fun("__print_unit__", &[]),
+            Type::Array(elem_ty) => {
+                // prints as [e1,e2,...,en]
+                let arr = val.into_pointer_value();
+                self.print_str("[");
+                self.build_array_loop(arr, elem_ty, |codegen, elem_ptr, i| {
+                    let parent = codegen.builder.get_insert_block().unwrap().get_parent().unwrap();
+                    let sep_block = codegen.context.append_basic_block(parent, "sep");
+                    let elem_block = codegen.context.append_basic_block(parent, "elem");
+                    let is_first = codegen.builder
+                        .build_int_compare(IntPredicate::EQ, i, codegen.int_type().const_zero(), "is_first")
+                        .unwrap();
+                    codegen.builder.build_conditional_branch(is_first, elem_block, sep_block).unwrap();
+
+                    // separator
+                    codegen.builder.position_at_end(sep_block);
+                    codegen.print_str(",");
+                    codegen.builder.build_unconditional_branch(elem_block).unwrap();
+
+                    // element
+                    codegen.builder.position_at_end(elem_block);
+                    let elem = codegen.builder.build_load(codegen.llvm_type(elem_ty), elem_ptr, "elem").unwrap();
+                    codegen.gen_print(elem, elem_ty);
+                });
+                self.print_str("]");
+            }
+            // function values print like in the interpreter
+            Type::Fun(_) => self.print_str("<function>"),
+            _ => panic!("unsupported type for print: {:?}", ty),
+        }
+    }
+
+    fn print_str(&mut self, str: &str) {
+        let ptr = self.builder.build_global_string_ptr(str, "str").unwrap().as_pointer_value();
+        self.call_print_fun("__print_string__", &[ptr.into()]);
+    }
+
+    fn call_print_fun(&self, name: &str, args: &[BasicMetadataValueEnum<'ctx>]) {
+        let fun = self.module.get_function(name).unwrap_or_else(|| panic!("undefined function {}", name));
+        self.builder.build_call(fun, args, "print").unwrap();
+    }
+
+    fn get_malloc(&self) -> FunctionValue<'ctx> {
+        let ty = self.context.i8_type()
+            .ptr_type(AddressSpace::default())
+            .fn_type(&[self.size_type.into()], false);
+        self.get_extern_fun("malloc", ty)
+    }
+
+    // returns the function with the given name, declaring it if needed
+    fn get_extern_fun(&self, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module
+            .get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, ty, None))
+    }
+
+    // declaration of an overloaded llvm intrinsic for the int type
+    fn get_intrinsic(&self, name: &str) -> FunctionValue<'ctx> {
+        Intrinsic::find(name)
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, &[self.int_type().into()]))
+            .unwrap_or_else(|| panic!("undefined intrinsic {}", name))
+    }
+
+
     fn call_binop_fun(
         &self,
         l: IntValue<'ctx>,
@@ -383,29 +1175,64 @@ impl<'ctx> CodeGen<'ctx> {
         Ok(ret)
     }
 
-    // counts number of not operators and returns the innermost expression
-    fn unwrap_not<'a>(&self, expr: &'a Expr) -> (usize, &'a Expr) {
-        let mut count = 0;
-        let mut current = expr;
-        while let Expr::Not { expr: inner } = current {
-            count += 1;
-            current = &inner.value;
-        }
-        (count+1, current)
-    }
-
     fn llvm_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
         match ty {
             Type::Int => self.int_type().into(),
             Type::Bool => self.bool_type().into(),
             Type::Unit => self.unit_type().into(),
             Type::String => self.context.i8_type().ptr_type(AddressSpace::default()).into(),
+            Type::Array(elem_ty) => self.array_type(elem_ty).into(),
+            // function values are pointers to closures
+            Type::Fun(_) => self.context.i8_type().ptr_type(AddressSpace::default()).into(),
             _ => unimplemented!("type {:?}", ty),
         }
     }
 
+    // unit functions return void, except main which always returns a c int exit code,
+    // and the other functions take their closure before the params
+    fn fun_type(&self, fun_ty: &FunType, is_main: bool) -> FunctionType<'ctx> {
+        let env_ty = self.context.i8_type().ptr_type(AddressSpace::default()).into();
+        let params_ty = (!is_main)
+            .then_some(env_ty)
+            .into_iter()
+            .chain(fun_ty.params.iter().map(|t| self.llvm_type(t).into()))
+            .collect::<Vec<_>>();
+
+        match fun_ty.ret.deref() {
+            _ if is_main => self.context.i32_type().fn_type(&params_ty, false),
+            Type::Unit => self.context.void_type().fn_type(&params_ty, false),
+            _ => self.llvm_type(&fun_ty.ret).fn_type(&params_ty, false),
+        }
+    }
+
+    // arrays are pointers to a heap allocated { length, [elements] } struct
+    fn array_type(&self, elem_ty: &Type) -> PointerType<'ctx> {
+        self.array_struct_type(elem_ty).ptr_type(AddressSpace::default())
+    }
+
+    // closures are { number of captured references, [captured references], function, [other captured values] },
+    // returns the struct and the field of each captured value
+    fn closure_struct_type(&self, captures: &[Type]) -> (StructType<'ctx>, Vec<u32>) {
+        let (slots, fun_slot) = closure_slots(captures);
+        let ptr_ty = self.context.i8_type().ptr_type(AddressSpace::default());
+        let mut fields = vec![ptr_ty.into(); captures.len() + 2];
+        fields[0] = self.int_type().into();
+        for (ty, slot) in captures.iter().zip(&slots) {
+            fields[slot + 1] = self.llvm_type(ty);
+        }
+        fields[fun_slot + 1] = ptr_ty.into();
+        let struct_ty = self.context.struct_type(&fields, false);
+        (struct_ty, slots.into_iter().map(|slot| slot as u32 + 1).collect())
+    }
+
+    fn array_struct_type(&self, elem_ty: &Type) -> StructType<'ctx> {
+        let elems_ty = self.llvm_type(elem_ty).array_type(0);
+        self.context.struct_type(&[self.int_type().into(), elems_ty.into()], false)
+    }
+
+    // the Int type is a 64-bit signed integer, like the literals parsed by the lexer
     fn int_type(&self) -> IntType<'ctx> {
-        self.context.i32_type()
+        self.context.i64_type()
     }
 
     fn bool_type(&self) -> IntType<'ctx> {
@@ -416,3 +1243,9 @@ impl<'ctx> CodeGen<'ctx> {
         self.context.struct_type(&[], false)
     }
 }
+
+// symbol of a function or global of the program, which never clashes with the runtime functions
+// or the c library, since a dot cannot appear in their names
+fn symbol(name: &str) -> String {
+    format!("aguda.{}", name)
+}
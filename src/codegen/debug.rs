@@ -0,0 +1,239 @@
+use std::path::Path;
+use inkwell::AddressSpace;
+use inkwell::basic_block::BasicBlock;
+use inkwell::context::Context;
+use inkwell::debug_info::*;
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::values::{FunctionValue, PointerValue};
+use crate::syntax::ast::{FunType, Span, Type};
+use crate::utils::get_position_in_src;
+
+// dwarf type encodings
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+
+// debug metadata for the source program, with line locations taken from the ast spans
+pub struct DebugInfo<'ctx> {
+    context: &'ctx Context,
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    scope: Option<DISubprogram<'ctx>>, // function being generated
+    src: String,
+    int_bits: u64,
+    ptr_bits: u64,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+
+    pub fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        file: &str,
+        src: &str,
+        is_optimized: bool,
+        int_bits: u64,
+        ptr_bits: u64,
+    ) -> Self {
+        let path = Path::new(file);
+        let filename = path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+        let directory = path
+            .parent()
+            .and_then(|dir| std::path::absolute(dir).ok())
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let debug_version = context.i32_type().const_int(debug_metadata_version() as u64, false);
+        module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, debug_version);
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            // no dwarf language code for aguda, c is the closest
+            DWARFSourceLanguage::C,
+            &filename,
+            &directory,
+            "aguda-rs",
+            is_optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        DebugInfo {
+            context,
+            builder,
+            unit,
+            scope: None,
+            src: src.to_string(),
+            int_bits,
+            ptr_bits,
+        }
+    }
+
+    // creates the subprogram of a function, which becomes the scope of the following locations
+    pub fn enter_fun(&mut self, fun: FunctionValue<'ctx>, name: &str, fun_ty: &FunType, span: &Span) {
+        let (line, _) = self.position(span);
+        let ret_ty = match fun_ty.ret.as_ref() {
+            Type::Unit => None,
+            ty => Some(self.di_type(ty)),
+        };
+        let params_ty = fun_ty.params.iter().map(|ty| self.di_type(ty)).collect::<Vec<_>>();
+        let subroutine_ty = self.builder.create_subroutine_type(self.file(), ret_ty, &params_ty, DIFlags::ZERO);
+        let subprogram = self.builder.create_function(
+            self.unit.as_debug_info_scope(),
+            name,
+            None,
+            self.file(),
+            line,
+            subroutine_ty,
+            false,
+            true,
+            line,
+            DIFlags::ZERO,
+            false,
+        );
+        fun.set_subprogram(subprogram);
+        self.scope = Some(subprogram);
+    }
+
+    pub fn exit_fun(&mut self) {
+        self.scope = None;
+    }
+
+    // location of the span in the current function
+    pub fn location(&self, span: &Span) -> Option<DILocation<'ctx>> {
+        let scope = self.scope?;
+        let (line, col) = self.position(span);
+        Some(self.builder.create_debug_location(self.context, line, col, scope.as_debug_info_scope(), None))
+    }
+
+    pub fn declare_param(&self, storage: PointerValue<'ctx>, name: &str, arg_no: u32, ty: &Type, span: &Span, block: BasicBlock<'ctx>) {
+        let (Some(scope), Some(loc)) = (self.scope, self.location(span)) else {
+            return;
+        };
+        let var = self.builder.create_parameter_variable(
+            scope.as_debug_info_scope(),
+            name,
+            arg_no,
+            self.file(),
+            loc.get_line(),
+            self.di_type(ty),
+            true,
+            DIFlags::ZERO,
+        );
+        self.builder.insert_declare_at_end(storage, Some(var), None, loc, block);
+    }
+
+    pub fn declare_var(&self, storage: PointerValue<'ctx>, name: &str, ty: &Type, span: &Span, block: BasicBlock<'ctx>) {
+        let (Some(scope), Some(loc)) = (self.scope, self.location(span)) else {
+            return;
+        };
+        let var = self.builder.create_auto_variable(
+            scope.as_debug_info_scope(),
+            name,
+            self.file(),
+            loc.get_line(),
+            self.di_type(ty),
+            true,
+            DIFlags::ZERO,
+            0,
+        );
+        self.builder.insert_declare_at_end(storage, Some(var), None, loc, block);
+    }
+
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+
+    fn di_type(&self, ty: &Type) -> DIType<'ctx> {
+        match ty {
+            Type::Int => self.basic_type("Int", self.int_bits, DW_ATE_SIGNED),
+            Type::Bool => self.basic_type("Bool", 8, DW_ATE_BOOLEAN),
+            Type::String => {
+                let char_ty = self.basic_type("char", 8, DW_ATE_SIGNED_CHAR);
+                self.pointer_type("String", char_ty)
+            }
+            Type::Unit | Type::Any => self.unit_type(),
+            Type::Array(elem_ty) => {
+                // pointer to { length, [elements] }
+                let subscript = 0..0;
+                let elems = self.builder.create_array_type(self.di_type(elem_ty), 0, 0, std::slice::from_ref(&subscript));
+                let length = self.member_type("length", self.di_type(&Type::Int), self.int_bits, 0);
+                let elements = self.member_type("elements", elems.as_type(), 0, self.int_bits);
+                let name = ty.to_text();
+                let struct_ty = self.builder.create_struct_type(
+                    self.unit.as_debug_info_scope(),
+                    &name,
+                    self.file(),
+                    0,
+                    self.int_bits,
+                    0,
+                    DIFlags::ZERO,
+                    None,
+                    &[length, elements],
+                    0,
+                    None,
+                    &name,
+                );
+                self.pointer_type(&name, struct_ty.as_type())
+            }
+            Type::Fun(fun_ty) => self.pointer_type(&fun_ty.to_text(), self.unit_type()),
+        }
+    }
+
+    fn basic_type(&self, name: &str, bits: u64, encoding: u32) -> DIType<'ctx> {
+        self.builder
+            .create_basic_type(name, bits, encoding, DIFlags::ZERO)
+            .unwrap_or_else(|e| panic!("invalid debug type {}: {}", name, e))
+            .as_type()
+    }
+
+    fn unit_type(&self) -> DIType<'ctx> {
+        self.builder.create_struct_type(
+            self.unit.as_debug_info_scope(),
+            "Unit",
+            self.file(),
+            0,
+            0,
+            0,
+            DIFlags::ZERO,
+            None,
+            &[],
+            0,
+            None,
+            "Unit",
+        ).as_type()
+    }
+
+    fn pointer_type(&self, name: &str, pointee: DIType<'ctx>) -> DIType<'ctx> {
+        self.builder
+            .create_pointer_type(name, pointee, self.ptr_bits, 0, AddressSpace::default())
+            .as_type()
+    }
+
+    fn member_type(&self, name: &str, ty: DIType<'ctx>, bits: u64, offset: u64) -> DIType<'ctx> {
+        self.builder.create_member_type(
+            self.unit.as_debug_info_scope(),
+            name,
+            self.file(),
+            0,
+            bits,
+            0,
+            offset,
+            DIFlags::ZERO,
+            ty,
+        ).as_type()
+    }
+
+    fn file(&self) -> DIFile<'ctx> {
+        self.unit.get_file()
+    }
+
+    fn position(&self, span: &Span) -> (u32, u32) {
+        let (line, col) = get_position_in_src(&self.src, span.start);
+        (line as u32, col as u32)
+    }
+}
\ No newline at end of file
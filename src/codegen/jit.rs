@@ -0,0 +1,189 @@
+use std::ffi::c_char;
+use std::ptr;
+use std::thread;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::context::Context;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::FunctionType;
+use inkwell::values::{BasicValueEnum, FunctionValue, GlobalValue};
+use inkwell::{AddressSpace, IntPredicate, OptimizationLevel};
+use inkwell::targets::{InitializationConfig, Target};
+use crate::diagnostics::errors::RuntimeError;
+use crate::{DEFAULT_STACK_SIZE, STACK_MARGIN};
+
+// entry point generated into the module, which runs main with the output streams and the stack limit
+const ENTRY: &str = "__jit_main__";
+
+// bytes reserved for the jmp_buf of setjmp and for a va_list, more than any host needs
+const JMP_BUF_SIZE: u32 = 512;
+const VA_LIST_SIZE: u32 = 64;
+
+type EntryFn = unsafe extern "C" fn(*mut libc::FILE, *mut libc::FILE, usize) -> i32;
+
+// runs the main function of the llvm bitcode with the jit execution engine, in a thread of the compiler
+// with the stack of a compiled program, returning what the program wrote to stdout and stderr and its exit status
+pub fn execute(bitcode: &[u8]) -> Result<(Vec<u8>, Vec<u8>, i32), RuntimeError> {
+    let bitcode = bitcode.to_vec();
+    thread::Builder::new()
+        .stack_size(DEFAULT_STACK_SIZE as usize)
+        .spawn(move || run(&bitcode))
+        .map_err(|e| RuntimeError::unknown(format!("failed to start the jit: {}", e)))?
+        .join()
+        .map_err(|_| RuntimeError::unknown("the jit panicked".to_string()))?
+}
+
+fn run(bitcode: &[u8]) -> Result<(Vec<u8>, Vec<u8>, i32), RuntimeError> {
+    let context = Context::create();
+    let buf = MemoryBuffer::create_from_memory_range_copy(bitcode, "program");
+    let module = context
+        .create_module_from_ir(buf)
+        .map_err(|e| RuntimeError::unknown(e.to_string()))?;
+    gen_entry(&context, &module);
+    Target::initialize_native(&InitializationConfig::default()).map_err(RuntimeError::unknown)?;
+    let engine = module
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .map_err(|e| RuntimeError::unknown(e.to_string()))?;
+    let entry = unsafe { engine.get_function::<EntryFn>(ENTRY) }
+        .map_err(|e| RuntimeError::unknown(e.to_string()))?;
+
+    // the program measures its stack from here, like compiled programs do from main
+    let frame = 0u8;
+    let stack_limit = (&frame as *const u8 as usize).saturating_sub(DEFAULT_STACK_SIZE as usize) + STACK_MARGIN as usize;
+    let stdout = MemStream::open()?;
+    let stderr = MemStream::open()?;
+    let status = unsafe { entry.call(stdout.file, stderr.file, stack_limit) };
+    Ok((stdout.close(), stderr.close(), status))
+}
+
+// generates the entry point of the jit, and redirects the calls of the program to the c library that write
+// to stdout or stderr, or exit the process, to the streams of the jit and to a jump back to the entry point
+fn gen_entry<'ctx>(context: &'ctx Context, module: &Module<'ctx>) {
+    let builder = context.create_builder();
+    let i8_ty = context.i8_type();
+    let i32_ty = context.i32_type();
+    let ptr_ty = i8_ty.ptr_type(AddressSpace::default());
+    let size_ty = context.custom_width_int_type(usize::BITS);
+
+    let add_global = |name: &str, value: BasicValueEnum<'ctx>| -> GlobalValue<'ctx> {
+        let global = module.add_global(value.get_type(), None, name);
+        global.set_linkage(Linkage::Internal);
+        global.set_initializer(&value);
+        global
+    };
+    let stdout = add_global("__jit_stdout__", ptr_ty.const_null().into());
+    let stderr = add_global("__jit_stderr__", ptr_ty.const_null().into());
+    let status = add_global("__jit_status__", i32_ty.const_zero().into());
+    let env = add_global("__jit_env__", i8_ty.array_type(JMP_BUF_SIZE).const_zero().into());
+    env.set_alignment(16);
+    let env = env.as_pointer_value().const_cast(ptr_ty);
+
+    let vfprintf = declare(module, "vfprintf", i32_ty.fn_type(&[ptr_ty.into(), ptr_ty.into(), ptr_ty.into()], false));
+    let setjmp = declare(module, "_setjmp", i32_ty.fn_type(&[ptr_ty.into()], false));
+    let longjmp = declare(module, "_longjmp", context.void_type().fn_type(&[ptr_ty.into(), i32_ty.into()], false));
+    let attribute = |name: &str| context.create_enum_attribute(Attribute::get_named_enum_kind_id(name), 0);
+    setjmp.add_attribute(AttributeLoc::Function, attribute("returns_twice"));
+    longjmp.add_attribute(AttributeLoc::Function, attribute("noreturn"));
+    let va_start = Intrinsic::find("llvm.va_start").and_then(|i| i.get_declaration(module, &[])).unwrap();
+    let va_end = Intrinsic::find("llvm.va_end").and_then(|i| i.get_declaration(module, &[])).unwrap();
+
+    // int __jit_printf__(const char *fmt, ...) and int __jit_dprintf__(int fd, const char *fmt, ...),
+    // which write to the stream of the jit for stdout or stderr
+    let gen_printf = |name: &str, has_fd: bool| {
+        let params = if has_fd { vec![i32_ty.into(), ptr_ty.into()] } else { vec![ptr_ty.into()] };
+        let fun = module.add_function(name, i32_ty.fn_type(&params, true), Some(Linkage::Internal));
+        builder.position_at_end(context.append_basic_block(fun, "entry"));
+        let stream = if has_fd {
+            let fd = fun.get_nth_param(0).unwrap().into_int_value();
+            let is_stdout = builder.build_int_compare(IntPredicate::EQ, fd, i32_ty.const_int(1, false), "is_stdout").unwrap();
+            let out = builder.build_load(ptr_ty, stdout.as_pointer_value(), "stdout").unwrap();
+            let err = builder.build_load(ptr_ty, stderr.as_pointer_value(), "stderr").unwrap();
+            builder.build_select(is_stdout, out, err, "stream").unwrap()
+        } else {
+            builder.build_load(ptr_ty, stdout.as_pointer_value(), "stdout").unwrap()
+        };
+        let fmt = fun.get_nth_param(has_fd as u32).unwrap();
+        let ap = builder.build_alloca(i8_ty.array_type(VA_LIST_SIZE), "ap").unwrap();
+        ap.as_instruction().unwrap().set_alignment(16).unwrap();
+        let ap = builder.build_pointer_cast(ap, ptr_ty, "ap").unwrap();
+        builder.build_call(va_start, &[ap.into()], "").unwrap();
+        let res = builder.build_call(vfprintf, &[stream.into(), fmt.into(), ap.into()], "res").unwrap();
+        builder.build_call(va_end, &[ap.into()], "").unwrap();
+        builder.build_return(Some(&res.try_as_basic_value().left().unwrap())).unwrap();
+        fun
+    };
+    let printf = gen_printf("__jit_printf__", false);
+    let dprintf = gen_printf("__jit_dprintf__", true);
+
+    // void __jit_exit__(int code), which jumps back to the entry point with the exit code
+    let exit = module.add_function("__jit_exit__", context.void_type().fn_type(&[i32_ty.into()], false), Some(Linkage::Internal));
+    builder.position_at_end(context.append_basic_block(exit, "entry"));
+    builder.build_store(status.as_pointer_value(), exit.get_nth_param(0).unwrap()).unwrap();
+    builder.build_call(longjmp, &[env.into(), i32_ty.const_int(1, false).into()], "").unwrap();
+    builder.build_unreachable().unwrap();
+
+    for (name, redirected) in [("printf", printf), ("dprintf", dprintf), ("exit", exit)] {
+        if let Some(fun) = module.get_function(name) {
+            fun.replace_all_uses_with(redirected);
+        }
+    }
+
+    // int __jit_main__(FILE *stdout, FILE *stderr, size_t stack_limit), which returns the exit code of the program
+    let entry_ty = i32_ty.fn_type(&[ptr_ty.into(), ptr_ty.into(), size_ty.into()], false);
+    let entry = module.add_function(ENTRY, entry_ty, None);
+    builder.position_at_end(context.append_basic_block(entry, "entry"));
+    builder.build_store(stdout.as_pointer_value(), entry.get_nth_param(0).unwrap()).unwrap();
+    builder.build_store(stderr.as_pointer_value(), entry.get_nth_param(1).unwrap()).unwrap();
+    // there is no stack limit when the recursion depth is counted or unlimited
+    if let Some(stack_limit) = module.get_global("__stack_limit__") {
+        builder.build_store(stack_limit.as_pointer_value(), entry.get_nth_param(2).unwrap()).unwrap();
+    }
+    let run_block = context.append_basic_block(entry, "run");
+    let exited_block = context.append_basic_block(entry, "exited");
+    let jumped = builder.build_call(setjmp, &[env.into()], "jumped").unwrap().try_as_basic_value().left().unwrap();
+    let returned = builder.build_int_compare(IntPredicate::EQ, jumped.into_int_value(), i32_ty.const_zero(), "returned").unwrap();
+    builder.build_conditional_branch(returned, run_block, exited_block).unwrap();
+
+    // run block
+    builder.position_at_end(run_block);
+    let main = module.get_function("main").expect("undefined function main");
+    let code = builder.build_call(main, &[], "code").unwrap().try_as_basic_value().left().unwrap();
+    builder.build_return(Some(&code)).unwrap();
+
+    // exited block
+    builder.position_at_end(exited_block);
+    let code = builder.build_load(i32_ty, status.as_pointer_value(), "code").unwrap();
+    builder.build_return(Some(&code)).unwrap();
+}
+
+fn declare<'ctx>(module: &Module<'ctx>, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+    module.get_function(name).unwrap_or_else(|| module.add_function(name, ty, None))
+}
+
+// stream written to memory, which collects the output of the program
+struct MemStream {
+    file: *mut libc::FILE,
+    buf: Box<(*mut c_char, usize)>,
+}
+
+impl MemStream {
+    fn open() -> Result<Self, RuntimeError> {
+        let mut buf = Box::new((ptr::null_mut(), 0));
+        let file = unsafe { libc::open_memstream(&mut buf.0, &mut buf.1) };
+        if file.is_null() {
+            return Err(RuntimeError::unknown("failed to open the output of the jit".to_string()));
+        }
+        Ok(MemStream { file, buf })
+    }
+
+    // closes the stream, returning what was written to it
+    fn close(self) -> Vec<u8> {
+        unsafe {
+            libc::fclose(self.file);
+            let bytes = std::slice::from_raw_parts(self.buf.0 as *const u8, self.buf.1).to_vec();
+            libc::free(self.buf.0 as *mut libc::c_void);
+            bytes
+        }
+    }
+}
\ No newline at end of file
@@ -1 +1,23 @@
 pub mod codegen;
+pub mod debug;
+pub mod native;
+pub mod jit;
+pub mod optimizer;
+pub mod runtime;
+pub mod c_backend;
+
+use crate::syntax::ast::Type;
+
+// closures are laid out like arrays of references, { number of captured references, [captured references],
+// function, [other captured values] }, so the collectors find the references without knowing the function,
+// returns the slot of each captured value and the slot of the function
+pub fn closure_slots(captures: &[Type]) -> (Vec<usize>, usize) {
+    let refs = captures.iter().filter(|ty| ty.is_reference()).count();
+    let (mut next_ref, mut next_val) = (0, refs + 1);
+    let slots = captures.iter().map(|ty| {
+        let next = if ty.is_reference() { &mut next_ref } else { &mut next_val };
+        *next += 1;
+        *next - 1
+    }).collect();
+    (slots, refs)
+}
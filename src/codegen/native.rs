@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::process::Command;
+use inkwell::module::Module;
+use inkwell::OptimizationLevel;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple};
+use crate::diagnostics::errors::BackendError;
+use crate::utils::TempDir;
+
+// compiles the module into a native executable
+pub fn build_executable(module: &Module, machine: &TargetMachine, exe_path: &Path) -> Result<(), BackendError> {
+    // the system c compiler only links for the host
+    let triple = machine.get_triple();
+    if !same_platform(&triple, &TargetMachine::get_default_triple()) {
+        return Err(BackendError::unsupported_target(format!(
+            "executables can only be built for the host, emit an object file for {} instead",
+            triple.as_str().to_string_lossy()
+        )));
+    }
+
+    // object file, in a temporary directory removed with it
+    let dir = TempDir::new("aguda-obj").map_err(|e| BackendError::object_emission(e.to_string()))?;
+    let obj_path = dir.path().join("program.o");
+    machine
+        .write_to_file(module, FileType::Object, &obj_path)
+        .map_err(|e| BackendError::object_emission(e.to_string()))?;
+
+    // link with the system c compiler, which also links libc
+    let output = Command::new("cc")
+        .arg(&obj_path)
+        .arg("-o")
+        .arg(exe_path)
+        .output()
+        .map_err(|e| BackendError::linking(e.to_string()))?;
+    if !output.status.success() {
+        return Err(BackendError::linking(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(())
+}
+
+// compares the architecture, system and environment of two triples, ignoring the vendor
+fn same_platform(a: &TargetTriple, b: &TargetTriple) -> bool {
+    let parts = |triple: &TargetTriple| {
+        let triple = TargetMachine::normalize_triple(triple).as_str().to_string_lossy().to_string();
+        triple
+            .split('-')
+            .enumerate()
+            .filter(|(i, _)| *i != 1)
+            .map(|(_, part)| part.to_string())
+            .collect::<Vec<_>>()
+    };
+    parts(a) == parts(b)
+}
+
+// assembly or object code of the module
+pub fn emit_to_memory(module: &Module, machine: &TargetMachine, file_type: FileType) -> Result<Vec<u8>, BackendError> {
+    machine
+        .write_to_memory_buffer(module, file_type)
+        .map(|buf| buf.as_slice().to_vec())
+        .map_err(|e| BackendError::object_emission(e.to_string()))
+}
+
+// target machine for the triple, or for the host when no triple is given
+pub fn target_machine(triple: Option<&str>) -> Result<TargetMachine, BackendError> {
+    let (triple, cpu, features) = match triple {
+        Some(triple) => {
+            Target::initialize_all(&InitializationConfig::default());
+            let triple = TargetMachine::normalize_triple(&TargetTriple::create(triple));
+            (triple, String::new(), String::new())
+        }
+        None => {
+            Target::initialize_native(&InitializationConfig::default()).map_err(BackendError::unsupported_target)?;
+            let cpu = TargetMachine::get_host_cpu_name().to_string();
+            let features = TargetMachine::get_host_cpu_features().to_string();
+            (TargetMachine::get_default_triple(), cpu, features)
+        }
+    };
+    let target = Target::from_triple(&triple).map_err(|e| BackendError::unsupported_target(e.to_string()))?;
+    target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            OptimizationLevel::Default,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| BackendError::unsupported_target(triple.as_str().to_string_lossy().to_string()))
+}
+
+// names and descriptions of the targets llvm was built with
+pub fn registered_targets() -> Vec<(String, String)> {
+    Target::initialize_all(&InitializationConfig::default());
+    let mut targets = vec![];
+    let mut target = Target::get_first();
+    while let Some(t) = target {
+        let name = t.get_name().to_string_lossy().to_string();
+        let description = t.get_description().to_string_lossy().to_string();
+        targets.push((name, description));
+        target = t.get_next();
+    }
+    targets.sort();
+    targets
+}
\ No newline at end of file
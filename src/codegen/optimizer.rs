@@ -0,0 +1,14 @@
+use inkwell::module::Module;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::TargetMachine;
+use crate::diagnostics::errors::BackendError;
+
+// runs the pass pipeline on the module, in the same format as opt's -passes argument
+pub fn optimize(module: &Module, pipeline: &str, machine: &TargetMachine) -> Result<(), BackendError> {
+    module.verify().map_err(|e| BackendError::optimization(e.to_string()))?;
+    let options = PassBuilderOptions::create();
+    options.set_verify_each(true);
+    module
+        .run_passes(pipeline, machine, options)
+        .map_err(|e| BackendError::optimization(e.to_string()))
+}
\ No newline at end of file
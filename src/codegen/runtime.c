@@ -0,0 +1,414 @@
+// runtime library of aguda programs compiled to c, included at the start of every program
+// configured with the AGUDA_GC_MARKSWEEP, AGUDA_OVERFLOW_TRAP, AGUDA_STACK_CHECK (with AGUDA_DEFAULT_STACK_SIZE and
+// AGUDA_STACK_MARGIN), AGUDA_MAX_DEPTH, AGUDA_MAX_ARRAY_LENGTH and AGUDA_TAIL_ARGS macros
+
+#include <inttypes.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef char aguda_unit;
+
+// arrays are { length, [elements] }, with the elements aligned like an int64_t
+typedef struct aguda_array {
+    int64_t length;
+    int64_t data[];
+} aguda_array;
+
+#define AGUDA_ELEMS(arr, type) ((type *) (arr)->data)
+
+// prints the formatted message to stderr and exits the program
+static void aguda_error(const char *fmt, ...) {
+    va_list args;
+    va_start(args, fmt);
+    vfprintf(stderr, fmt, args);
+    va_end(args);
+    exit(1);
+}
+
+static void aguda_print_int(int64_t n) {
+    printf("%" PRId64, n);
+}
+
+static void aguda_print_bool(bool b) {
+    printf("%s", b ? "true" : "false");
+}
+
+static void aguda_print_unit(void) {
+    printf("unit");
+}
+
+static void aguda_print_string(const char *s) {
+    printf("%s", s);
+}
+
+static void aguda_check_index(aguda_array *arr, int64_t index, const char *loc) {
+    // negative indexes are also caught by the unsigned comparison
+    if ((uint64_t) index >= (uint64_t) arr->length) {
+        aguda_error("%s: index %" PRId64 " out of bounds for length %" PRId64 "\n", loc, index, arr->length);
+    }
+}
+
+// dividing the minimum integer by -1 wraps around, like in the other backends, instead of trapping
+static int64_t aguda_div(int64_t a, int64_t b, const char *loc) {
+    if (b == 0) {
+        aguda_error("%s: division by zero in '/'\n", loc);
+    }
+    if (b == -1) {
+        return (int64_t) (0 - (uint64_t) a);
+    }
+    return a / b;
+}
+
+static int64_t aguda_mod(int64_t a, int64_t b, const char *loc) {
+    if (b == 0) {
+        aguda_error("%s: division by zero in '%%'\n", loc);
+    }
+    if (b == -1) {
+        return 0;
+    }
+    return a % b;
+}
+
+#ifdef AGUDA_OVERFLOW_TRAP
+
+static void aguda_overflow(const char *op, const char *loc) {
+    aguda_error("%s: integer overflow in '%s'\n", loc, op);
+}
+
+static int64_t aguda_add(int64_t a, int64_t b, const char *loc) {
+    if ((b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b)) {
+        aguda_overflow("+", loc);
+    }
+    return a + b;
+}
+
+static int64_t aguda_sub(int64_t a, int64_t b, const char *loc) {
+    if ((b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b)) {
+        aguda_overflow("-", loc);
+    }
+    return a - b;
+}
+
+static bool aguda_mul_overflows(int64_t a, int64_t b) {
+    if (a > 0) {
+        return b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a;
+    }
+    return b > 0 ? a < INT64_MIN / b : a != 0 && b < INT64_MAX / a;
+}
+
+static int64_t aguda_mul(int64_t a, int64_t b, const char *loc) {
+    if (aguda_mul_overflows(a, b)) {
+        aguda_overflow("*", loc);
+    }
+    return a * b;
+}
+
+// multiplies a by itself b times
+static int64_t aguda_pow(int64_t a, int64_t b, const char *loc) {
+    int64_t res = 1;
+    for (; b > 0; b--) {
+        if (aguda_mul_overflows(res, a)) {
+            aguda_overflow("^", loc);
+        }
+        res *= a;
+    }
+    return res;
+}
+
+#else
+
+// signed overflow is undefined in c, so the arithmetic wraps around in unsigned integers
+#define AGUDA_WRAP(a, op, b) ((int64_t) ((uint64_t) (a) op (uint64_t) (b)))
+
+static int64_t aguda_add(int64_t a, int64_t b, const char *loc) {
+    (void) loc;
+    return AGUDA_WRAP(a, +, b);
+}
+
+static int64_t aguda_sub(int64_t a, int64_t b, const char *loc) {
+    (void) loc;
+    return AGUDA_WRAP(a, -, b);
+}
+
+static int64_t aguda_mul(int64_t a, int64_t b, const char *loc) {
+    (void) loc;
+    return AGUDA_WRAP(a, *, b);
+}
+
+// multiplies a by itself b times
+static int64_t aguda_pow(int64_t a, int64_t b, const char *loc) {
+    int64_t res = 1;
+    (void) loc;
+    for (; b > 0; b--) {
+        res = AGUDA_WRAP(res, *, a);
+    }
+    return res;
+}
+
+#endif
+
+#if defined(AGUDA_STACK_CHECK)
+
+// the size limit of the stack is only known on posix systems
+#if defined(__unix__) || defined(__APPLE__)
+#include <sys/resource.h>
+#endif
+
+// lowest address of the stack that the frames of the functions may start at,
+// where the address of a local variable stands for the frame of its function
+static uintptr_t aguda_stack_limit = 0;
+
+// sets the stack limit from the size limit of the stack, below the frame of main
+static void aguda_init_stack_limit(void) {
+    char frame;
+    uintptr_t base = (uintptr_t) &frame;
+    uintptr_t size = AGUDA_DEFAULT_STACK_SIZE;
+#ifdef RLIMIT_STACK
+    struct rlimit rlimit;
+    if (getrlimit(RLIMIT_STACK, &rlimit) == 0 && rlimit.rlim_cur != RLIM_INFINITY) {
+        size = (uintptr_t) rlimit.rlim_cur;
+    }
+#endif
+    aguda_stack_limit = (size < base ? base - size : 0) + AGUDA_STACK_MARGIN;
+}
+
+static void aguda_enter(const char *fun, const char *loc) {
+    char frame;
+    if ((uintptr_t) &frame < aguda_stack_limit) {
+        aguda_error("%s: stack overflow in function `%s`\n", loc, fun);
+    }
+}
+
+#define aguda_leave() ((void) 0)
+
+#elif AGUDA_MAX_DEPTH > 0
+
+// calls that have not returned yet
+static int64_t aguda_depth = 0;
+
+static void aguda_enter(const char *fun, const char *loc) {
+    if (++aguda_depth > AGUDA_MAX_DEPTH) {
+        aguda_error("%s: stack overflow in function `%s`\n", loc, fun);
+    }
+}
+
+#define aguda_leave() (aguda_depth--)
+
+#else
+
+#define aguda_enter(fun, loc) ((void) 0)
+#define aguda_leave() ((void) 0)
+
+#endif
+
+// a call in tail position to another function stores the call and returns to a trampoline, which makes it,
+// so that tail calls run in constant stack space with any c compiler
+typedef union aguda_value {
+    int64_t i;
+    bool b;
+    const char *s;
+    aguda_unit u;
+    aguda_array *a;
+} aguda_value;
+
+// function making the pending tail call, and the called function with its closure and arguments
+static void (*aguda_tail_call)(void) = NULL;
+static void (*aguda_tail_fun)(void) = NULL;
+static aguda_array *aguda_tail_env = NULL;
+static aguda_value aguda_tail_args[AGUDA_TAIL_ARGS];
+
+// returns the result of a call once it made its pending tail calls, and these theirs
+#define AGUDA_TRAMPOLINE(name, type) \
+    static type name(type res) { \
+        while (aguda_tail_call != NULL) { \
+            type (*call)(void) = (type (*)(void)) aguda_tail_call; \
+            aguda_tail_call = NULL; \
+            res = call(); \
+        } \
+        return res; \
+    }
+
+AGUDA_TRAMPOLINE(aguda_trampoline_int, int64_t)
+AGUDA_TRAMPOLINE(aguda_trampoline_bool, bool)
+AGUDA_TRAMPOLINE(aguda_trampoline_string, const char *)
+AGUDA_TRAMPOLINE(aguda_trampoline_unit, aguda_unit)
+AGUDA_TRAMPOLINE(aguda_trampoline_ref, aguda_array *)
+
+#ifdef AGUDA_GC_MARKSWEEP
+
+// minimum number of bytes allocated between collections
+#define AGUDA_GC_MIN_THRESHOLD ((int64_t) 8 << 20)
+
+// heap objects are preceded by a header and linked into a list
+typedef struct aguda_gc_header {
+    struct aguda_gc_header *next;
+    int64_t mark;
+    int64_t has_refs;
+    int64_t size;
+} aguda_gc_header;
+
+// variables holding arrays live in the shadow stack, indexed from the base of their function
+static aguda_array **aguda_gc_stack = NULL;
+static int64_t aguda_gc_sp = 0;
+static int64_t aguda_gc_capacity = 0;
+
+#define AGUDA_ROOT(base, k) (aguda_gc_stack[(base) + (k)])
+
+static aguda_gc_header *aguda_gc_objects = NULL;
+static int64_t aguda_gc_allocated = 0;
+static int64_t aguda_gc_threshold = AGUDA_GC_MIN_THRESHOLD;
+
+// marks the object if it is not marked yet, pushing it on the stack of objects to scan,
+// with the previous top in its mark field, tagged with the low bit so that it is never zero
+static void aguda_gc_mark_push(aguda_array **top, aguda_array *arr) {
+    aguda_gc_header *header;
+    if (arr == NULL) {
+        return;
+    }
+    header = (aguda_gc_header *) arr - 1;
+    if (header->mark) {
+        return;
+    }
+    header->mark = (int64_t) ((uintptr_t) *top | 1);
+    *top = arr;
+}
+
+// marks the object and the objects reachable from it, without recursion:
+// the objects whose elements are still to be marked form a stack linked through their mark fields
+static void aguda_gc_mark(aguda_array *arr) {
+    aguda_array *top = NULL;
+    int64_t i;
+    aguda_gc_mark_push(&top, arr);
+    while (top != NULL) {
+        aguda_gc_header *header = (aguda_gc_header *) top - 1;
+        arr = top;
+        top = (aguda_array *) (uintptr_t) (header->mark & ~(int64_t) 1);
+        header->mark = 1;
+        if (header->has_refs) {
+            for (i = 0; i < arr->length; i++) {
+                aguda_gc_mark_push(&top, AGUDA_ELEMS(arr, aguda_array *)[i]);
+            }
+        }
+    }
+}
+
+// marks the objects reachable from the shadow stack and frees the others
+static void aguda_gc_collect(void) {
+    aguda_gc_header **link = &aguda_gc_objects;
+    int64_t live = 0;
+    int64_t i;
+    for (i = 0; i < aguda_gc_sp; i++) {
+        aguda_gc_mark(aguda_gc_stack[i]);
+    }
+    while (*link != NULL) {
+        aguda_gc_header *header = *link;
+        if (header->mark) {
+            header->mark = 0;
+            live += header->size;
+            link = &header->next;
+        } else {
+            *link = header->next;
+            free(header);
+        }
+    }
+    // the next collection happens once the heap doubles
+    aguda_gc_threshold = live < AGUDA_GC_MIN_THRESHOLD ? AGUDA_GC_MIN_THRESHOLD : live;
+    aguda_gc_allocated = 0;
+}
+
+// returns a zeroed object after its header, or null when there is no memory left
+static void *aguda_alloc(int64_t size, bool has_refs) {
+    aguda_gc_header *header;
+    int64_t total = size + (int64_t) sizeof(aguda_gc_header);
+    if (aguda_gc_allocated > aguda_gc_threshold) {
+        aguda_gc_collect();
+    }
+    header = calloc(1, (size_t) total);
+    if (header == NULL) {
+        return NULL;
+    }
+    header->has_refs = has_refs;
+    header->size = total;
+    header->next = aguda_gc_objects;
+    aguda_gc_objects = header;
+    aguda_gc_allocated += total;
+    return header + 1;
+}
+
+// reserves count null slots on the shadow stack, returning their base
+static int64_t aguda_gc_push(int64_t count) {
+    int64_t base = aguda_gc_sp;
+    int64_t i;
+    if (base + count > aguda_gc_capacity) {
+        aguda_gc_capacity = 2 * aguda_gc_capacity < base + count ? base + count : 2 * aguda_gc_capacity;
+        aguda_gc_stack = realloc(aguda_gc_stack, (size_t) aguda_gc_capacity * sizeof(aguda_array *));
+    }
+    for (i = base; i < base + count; i++) {
+        aguda_gc_stack[i] = NULL;
+    }
+    aguda_gc_sp = base + count;
+    return base;
+}
+
+#define aguda_gc_pop(base) (aguda_gc_sp = (base))
+
+#else
+
+// arrays are never freed
+static void *aguda_alloc(int64_t size, bool has_refs) {
+    (void) has_refs;
+    return calloc(1, (size_t) size);
+}
+
+#endif
+
+// stops the program when the allocation failed
+static void *aguda_check_alloc(void *obj, const char *loc) {
+    if (obj == NULL) {
+        aguda_error("%s: out of memory\n", loc);
+    }
+    return obj;
+}
+
+// the limit keeps the size in bytes in a size_t
+static aguda_array *aguda_new_array(int64_t length, size_t elem_size, bool has_refs, const char *loc) {
+    aguda_array *arr;
+    if (length < 0 || length > AGUDA_MAX_ARRAY_LENGTH || (uint64_t) length > SIZE_MAX >> 4) {
+        aguda_error("%s: invalid array size %" PRId64 "\n", loc, length);
+    }
+    arr = aguda_check_alloc(aguda_alloc((int64_t) (sizeof(aguda_array) + (size_t) length * elem_size), has_refs), loc);
+    arr->length = length;
+    return arr;
+}
+
+// closures are { number of captured references, [captured references], function, [other captured values] },
+// with every slot aligned like an int64_t, so the collector marks them like arrays of references
+#define AGUDA_SLOT(clo, type, k) (*(type *) &(clo)->data[k])
+#define AGUDA_CALLEE(clo, type) AGUDA_SLOT(clo, type, (clo)->length)
+
+static aguda_array *aguda_new_closure(int64_t refs, int64_t slots, const char *loc) {
+    aguda_array *clo = aguda_check_alloc(aguda_alloc((int64_t) (sizeof(aguda_array) + (size_t) slots * sizeof(int64_t)), true), loc);
+    clo->length = refs;
+    return clo;
+}
+
+// closures of the top-level functions used as values, which are never freed
+typedef struct aguda_static_closure {
+#ifdef AGUDA_GC_MARKSWEEP
+    aguda_gc_header header;
+#endif
+    int64_t refs;
+    void (*fun)(void);
+} aguda_static_closure;
+
+#ifdef AGUDA_GC_MARKSWEEP
+// always marked, so the collector never visits them
+#define AGUDA_STATIC_CLOSURE(fun) { { NULL, 1, 0, 0 }, 0, (void (*)(void)) (fun) }
+#else
+#define AGUDA_STATIC_CLOSURE(fun) { 0, (void (*)(void)) (fun) }
+#endif
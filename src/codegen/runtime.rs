@@ -0,0 +1,625 @@
+use inkwell::AddressSpace;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{BasicMetadataTypeEnum, BasicTypeEnum, FunctionType, IntType, PointerType, StructType};
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, GlobalValue, IntValue, PointerValue};
+use inkwell::intrinsics::Intrinsic;
+use inkwell::IntPredicate;
+use crate::{CompileOptions, Gc, Overflow};
+
+// minimum number of bytes allocated between collections
+const GC_MIN_THRESHOLD: u64 = 8 << 20;
+
+// runtime library of aguda programs, generated into every module
+pub struct Runtime<'a, 'ctx> {
+    context: &'ctx Context,
+    module: &'a Module<'ctx>,
+    builder: Builder<'ctx>,
+    int_type: IntType<'ctx>,
+    size_type: IntType<'ctx>, // size_t of the target
+}
+
+impl<'a, 'ctx> Runtime<'a, 'ctx> {
+
+    pub fn new(context: &'ctx Context, module: &'a Module<'ctx>, int_type: IntType<'ctx>, size_type: IntType<'ctx>) -> Self {
+        Runtime {
+            context,
+            module,
+            builder: context.create_builder(),
+            int_type,
+            size_type,
+        }
+    }
+
+    pub fn gen_runtime(&self, options: &CompileOptions) {
+        self.gen_print_int();
+        self.gen_print_bool();
+        self.gen_print_unit();
+        self.gen_print_string();
+        self.gen_pow();
+        if options.overflow == Overflow::Trap {
+            self.gen_pow_with_overflow();
+        }
+        match options.gc {
+            Gc::None => {}
+            Gc::Rc => {
+                self.gen_gc_alloc(false);
+                self.gen_gc_inc();
+                self.gen_gc_dec();
+            }
+            Gc::MarkSweep => {
+                self.gen_gc_globals();
+                self.gen_gc_mark();
+                self.gen_gc_collect();
+                self.gen_gc_alloc(true);
+            }
+        }
+    }
+
+    // void __print_int__(long long n)
+    fn gen_print_int(&self) {
+        let fun = self.add_fun("__print_int__", self.void_fn(&[self.int_type.into()]));
+        let fmt = self.global_str("%lld");
+        let n = fun.get_nth_param(0).unwrap();
+        self.call_printf(&[fmt.into(), n.into()]);
+        self.builder.build_return(None).unwrap();
+    }
+
+    // void __print_bool__(bool b)
+    fn gen_print_bool(&self) {
+        let fun = self.add_fun("__print_bool__", self.void_fn(&[self.context.bool_type().into()]));
+        let fmt = self.global_str("%s");
+        let b = fun.get_nth_param(0).unwrap().into_int_value();
+        let str = self.builder
+            .build_select(b, self.global_str("true"), self.global_str("false"), "str")
+            .unwrap();
+        self.call_printf(&[fmt.into(), str.into()]);
+        self.builder.build_return(None).unwrap();
+    }
+
+    // void __print_unit__()
+    fn gen_print_unit(&self) {
+        self.add_fun("__print_unit__", self.void_fn(&[]));
+        let fmt = self.global_str("unit");
+        self.call_printf(&[fmt.into()]);
+        self.builder.build_return(None).unwrap();
+    }
+
+    // void __print_string__(char *s)
+    fn gen_print_string(&self) {
+        let fun = self.add_fun("__print_string__", self.void_fn(&[self.str_type().into()]));
+        let fmt = self.global_str("%s");
+        let s = fun.get_nth_param(0).unwrap();
+        self.call_printf(&[fmt.into(), s.into()]);
+        self.builder.build_return(None).unwrap();
+    }
+
+    // long long __pow__(long long a, long long b), multiplies a by itself b times
+    fn gen_pow(&self) {
+        let int_ty = self.int_type;
+        let fun = self.add_fun("__pow__", int_ty.fn_type(&[int_ty.into(), int_ty.into()], false));
+        let a = fun.get_nth_param(0).unwrap().into_int_value();
+        let b = fun.get_nth_param(1).unwrap().into_int_value();
+        let entry = self.builder.get_insert_block().unwrap();
+        let cond_block = self.context.append_basic_block(fun, "cond");
+        let body_block = self.context.append_basic_block(fun, "body");
+        let after_block = self.context.append_basic_block(fun, "after");
+        self.builder.build_unconditional_branch(cond_block).unwrap();
+
+        // cond block
+        self.builder.position_at_end(cond_block);
+        let res = self.builder.build_phi(int_ty, "res").unwrap();
+        let exp = self.builder.build_phi(int_ty, "exp").unwrap();
+        let res_val = res.as_basic_value().into_int_value();
+        let exp_val = exp.as_basic_value().into_int_value();
+        let cmp = self.builder.build_int_compare(IntPredicate::SGT, exp_val, int_ty.const_zero(), "cmp").unwrap();
+        self.builder.build_conditional_branch(cmp, body_block, after_block).unwrap();
+
+        // body block
+        self.builder.position_at_end(body_block);
+        let next_res = self.builder.build_int_mul(res_val, a, "next_res").unwrap();
+        let next_exp = self.builder.build_int_sub(exp_val, int_ty.const_int(1, false), "next_exp").unwrap();
+        self.builder.build_unconditional_branch(cond_block).unwrap();
+        res.add_incoming(&[(&int_ty.const_int(1, false), entry), (&next_res, body_block)]);
+        exp.add_incoming(&[(&b, entry), (&next_exp, body_block)]);
+
+        // after block
+        self.builder.position_at_end(after_block);
+        self.builder.build_return(Some(&res_val)).unwrap();
+    }
+
+    // { long long, bool } __pow_with_overflow__(long long a, long long b), like llvm's *.with.overflow intrinsics
+    fn gen_pow_with_overflow(&self) {
+        let int_ty = self.int_type;
+        let bool_ty = self.context.bool_type();
+        let ret_ty = self.context.struct_type(&[int_ty.into(), bool_ty.into()], false);
+        let fun = self.add_fun("__pow_with_overflow__", ret_ty.fn_type(&[int_ty.into(), int_ty.into()], false));
+        let a = fun.get_nth_param(0).unwrap().into_int_value();
+        let b = fun.get_nth_param(1).unwrap().into_int_value();
+        let smul = Intrinsic::find("llvm.smul.with.overflow")
+            .and_then(|intrinsic| intrinsic.get_declaration(self.module, &[int_ty.into()]))
+            .unwrap();
+        let entry = self.builder.get_insert_block().unwrap();
+        let cond_block = self.context.append_basic_block(fun, "cond");
+        let body_block = self.context.append_basic_block(fun, "body");
+        let overflow_block = self.context.append_basic_block(fun, "overflow");
+        let after_block = self.context.append_basic_block(fun, "after");
+        self.builder.build_unconditional_branch(cond_block).unwrap();
+
+        // cond block
+        self.builder.position_at_end(cond_block);
+        let res = self.builder.build_phi(int_ty, "res").unwrap();
+        let exp = self.builder.build_phi(int_ty, "exp").unwrap();
+        let res_val = res.as_basic_value().into_int_value();
+        let exp_val = exp.as_basic_value().into_int_value();
+        let cmp = self.builder.build_int_compare(IntPredicate::SGT, exp_val, int_ty.const_zero(), "cmp").unwrap();
+        self.builder.build_conditional_branch(cmp, body_block, after_block).unwrap();
+
+        // body block
+        self.builder.position_at_end(body_block);
+        let mul = self.builder
+            .build_call(smul, &[res_val.into(), a.into()], "mul")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let next_res = self.builder.build_extract_value(mul, 0, "next_res").unwrap().into_int_value();
+        let overflow = self.builder.build_extract_value(mul, 1, "overflow").unwrap().into_int_value();
+        let next_exp = self.builder.build_int_sub(exp_val, int_ty.const_int(1, false), "next_exp").unwrap();
+        self.builder.build_conditional_branch(overflow, overflow_block, cond_block).unwrap();
+        res.add_incoming(&[(&int_ty.const_int(1, false), entry), (&next_res, body_block)]);
+        exp.add_incoming(&[(&b, entry), (&next_exp, body_block)]);
+
+        // overflow block
+        self.builder.position_at_end(overflow_block);
+        self.builder.build_aggregate_return(&[int_ty.const_zero().into(), bool_ty.const_int(1, false).into()]).unwrap();
+
+        // after block
+        self.builder.position_at_end(after_block);
+        self.builder.build_aggregate_return(&[res_val.into(), bool_ty.const_zero().into()]).unwrap();
+    }
+
+    // shadow stack of root frames, list of heap objects and allocation counters of the collector
+    fn gen_gc_globals(&self) {
+        let ptr_ty = self.ptr_type();
+        let i64_ty = self.context.i64_type();
+        self.add_global("__gc_roots__", ptr_ty.into()).set_initializer(&ptr_ty.const_null());
+        self.add_global("__gc_objects__", ptr_ty.into()).set_initializer(&ptr_ty.const_null());
+        self.add_global("__gc_allocated__", i64_ty.into()).set_initializer(&i64_ty.const_zero());
+        self.add_global("__gc_threshold__", i64_ty.into()).set_initializer(&i64_ty.const_int(GC_MIN_THRESHOLD, false));
+    }
+
+    // void *__gc_alloc__(size_t size, bool has_refs), returns a zeroed object after its header, or null
+    fn gen_gc_alloc(&self, mark_sweep: bool) {
+        let ptr_ty = self.ptr_type();
+        let i64_ty = self.context.i64_type();
+        let fun = self.add_fun("__gc_alloc__", ptr_ty.fn_type(&[i64_ty.into(), self.context.bool_type().into()], false));
+        let size = fun.get_nth_param(0).unwrap().into_int_value();
+        let has_refs = fun.get_nth_param(1).unwrap().into_int_value();
+
+        // collect when enough bytes were allocated since the last collection
+        if mark_sweep {
+            let collect_block = self.context.append_basic_block(fun, "collect");
+            let alloc_block = self.context.append_basic_block(fun, "alloc");
+            let allocated = self.load_global("__gc_allocated__", i64_ty.into()).into_int_value();
+            let threshold = self.load_global("__gc_threshold__", i64_ty.into()).into_int_value();
+            let cmp = self.builder.build_int_compare(IntPredicate::UGT, allocated, threshold, "cmp").unwrap();
+            self.builder.build_conditional_branch(cmp, collect_block, alloc_block).unwrap();
+            self.builder.position_at_end(collect_block);
+            self.builder.build_call(self.module.get_function("__gc_collect__").unwrap(), &[], "").unwrap();
+            self.builder.build_unconditional_branch(alloc_block).unwrap();
+            self.builder.position_at_end(alloc_block);
+        }
+
+        let header_size = self.header_type().size_of().unwrap();
+        let total = self.builder.build_int_add(size, header_size, "total").unwrap();
+        let size_ty = self.size_type;
+        let calloc = self.extern_fun("calloc", ptr_ty.fn_type(&[size_ty.into(), size_ty.into()], false));
+        let calloc_size = self.builder.build_int_truncate_or_bit_cast(total, size_ty, "calloc_size").unwrap();
+        let raw = self.builder
+            .build_call(calloc, &[size_ty.const_int(1, false).into(), calloc_size.into()], "raw")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+
+        // the caller reports that the program ran out of memory
+        let null_block = self.context.append_basic_block(fun, "null");
+        let init_block = self.context.append_basic_block(fun, "init");
+        let is_null = self.builder.build_is_null(raw, "is_null").unwrap();
+        self.builder.build_conditional_branch(is_null, null_block, init_block).unwrap();
+        self.builder.position_at_end(null_block);
+        self.builder.build_return(Some(&ptr_ty.const_null())).unwrap();
+        self.builder.position_at_end(init_block);
+
+        let header = self.builder.build_pointer_cast(raw, self.header_type().ptr_type(AddressSpace::default()), "header").unwrap();
+        let has_refs = self.builder.build_int_z_extend(has_refs, i64_ty, "has_refs").unwrap();
+        self.builder.build_store(self.header_field(header, 2), has_refs).unwrap();
+        self.builder.build_store(self.header_field(header, 3), total).unwrap();
+
+        // link the object into the list of heap objects
+        if mark_sweep {
+            let objects = self.module.get_global("__gc_objects__").unwrap().as_pointer_value();
+            let head = self.load_global("__gc_objects__", ptr_ty.into());
+            self.builder.build_store(self.header_field(header, 0), head).unwrap();
+            self.builder.build_store(objects, raw).unwrap();
+            let allocated = self.load_global("__gc_allocated__", i64_ty.into()).into_int_value();
+            let allocated = self.builder.build_int_add(allocated, total, "allocated").unwrap();
+            self.builder.build_store(self.module.get_global("__gc_allocated__").unwrap().as_pointer_value(), allocated).unwrap();
+        }
+
+        let obj = unsafe {
+            self.builder
+                .build_gep(self.context.i8_type(), raw, &[header_size], "obj")
+                .unwrap()
+        };
+        self.builder.build_return(Some(&obj)).unwrap();
+    }
+
+    // void __gc_inc__(void *obj)
+    fn gen_gc_inc(&self) {
+        let fun = self.add_fun("__gc_inc__", self.void_fn(&[self.ptr_type().into()]));
+        let obj = fun.get_nth_param(0).unwrap().into_pointer_value();
+        self.build_return_if(self.builder.build_is_null(obj, "is_null").unwrap());
+        let count = self.header_field(self.header_of(obj), 1);
+        let i64_ty = self.context.i64_type();
+        let val = self.builder.build_load(i64_ty, count, "count").unwrap().into_int_value();
+        let val = self.builder.build_int_add(val, i64_ty.const_int(1, false), "count").unwrap();
+        self.builder.build_store(count, val).unwrap();
+        self.builder.build_return(None).unwrap();
+    }
+
+    // void __gc_dec__(void *obj), frees the object and releases its elements when no references are left
+    fn gen_gc_dec(&self) {
+        let fun = self.add_fun("__gc_dec__", self.void_fn(&[self.ptr_type().into()]));
+        let obj = fun.get_nth_param(0).unwrap().into_pointer_value();
+        self.build_return_if(self.builder.build_is_null(obj, "is_null").unwrap());
+        let header = self.header_of(obj);
+        let count = self.header_field(header, 1);
+        let i64_ty = self.context.i64_type();
+        let val = self.builder.build_load(i64_ty, count, "count").unwrap().into_int_value();
+        let val = self.builder.build_int_sub(val, i64_ty.const_int(1, false), "count").unwrap();
+        self.builder.build_store(count, val).unwrap();
+        self.build_return_if(self.builder.build_int_compare(IntPredicate::SGT, val, i64_ty.const_zero(), "is_live").unwrap());
+        self.build_elems_loop(header, obj, |runtime, elem| {
+            runtime.builder.build_call(fun, &[elem.into()], "").unwrap();
+        });
+        self.build_free(header);
+        self.builder.build_return(None).unwrap();
+    }
+
+    // void __gc_mark__(void *obj), marks the object and the objects reachable from it, without recursion:
+    // the objects whose elements are still to be marked form a stack linked through their mark fields
+    fn gen_gc_mark(&self) {
+        let ptr_ty = self.ptr_type();
+        let i64_ty = self.context.i64_type();
+        let fun = self.add_fun("__gc_mark__", self.void_fn(&[ptr_ty.into()]));
+        let top = self.entry_alloca(ptr_ty.into(), "top");
+        self.builder.build_store(top, ptr_ty.const_null()).unwrap();
+        self.build_gc_push(top, fun.get_nth_param(0).unwrap().into_pointer_value());
+        self.build_while(
+            |runtime| {
+                let obj = runtime.builder.build_load(ptr_ty, top, "obj").unwrap().into_pointer_value();
+                runtime.builder.build_is_not_null(obj, "has_obj").unwrap()
+            },
+            |runtime| {
+                // pop the object, leaving it marked
+                let obj = runtime.builder.build_load(ptr_ty, top, "obj").unwrap().into_pointer_value();
+                let header = runtime.header_of(obj);
+                let mark = runtime.header_field(header, 1);
+                let link = runtime.builder.build_load(i64_ty, mark, "link").unwrap().into_int_value();
+                let next = runtime.builder.build_and(link, i64_ty.const_int(1, false).const_not(), "next").unwrap();
+                let next = runtime.builder.build_int_to_ptr(next, ptr_ty, "next").unwrap();
+                runtime.builder.build_store(top, next).unwrap();
+                runtime.builder.build_store(mark, i64_ty.const_int(1, false)).unwrap();
+                runtime.build_elems_loop(header, obj, |runtime, elem| runtime.build_gc_push(top, elem));
+            },
+        );
+        self.builder.build_return(None).unwrap();
+    }
+
+    // marks the object if it is not marked yet, pushing it on the stack of objects to scan,
+    // with the previous top in its mark field, tagged with the low bit so that it is never zero
+    fn build_gc_push(&self, top: PointerValue<'ctx>, obj: PointerValue<'ctx>) {
+        let ptr_ty = self.ptr_type();
+        let i64_ty = self.context.i64_type();
+        let fun = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let check_block = self.context.append_basic_block(fun, "check");
+        let push_block = self.context.append_basic_block(fun, "push");
+        let after_block = self.context.append_basic_block(fun, "pushed");
+        let is_null = self.builder.build_is_null(obj, "is_null").unwrap();
+        self.builder.build_conditional_branch(is_null, after_block, check_block).unwrap();
+
+        // check block
+        self.builder.position_at_end(check_block);
+        let mark = self.header_field(self.header_of(obj), 1);
+        let val = self.builder.build_load(i64_ty, mark, "mark").unwrap().into_int_value();
+        let is_marked = self.builder.build_int_compare(IntPredicate::NE, val, i64_ty.const_zero(), "is_marked").unwrap();
+        self.builder.build_conditional_branch(is_marked, after_block, push_block).unwrap();
+
+        // push block
+        self.builder.position_at_end(push_block);
+        let prev = self.builder.build_load(ptr_ty, top, "prev").unwrap().into_pointer_value();
+        let prev = self.builder.build_ptr_to_int(prev, i64_ty, "prev").unwrap();
+        let link = self.builder.build_or(prev, i64_ty.const_int(1, false), "link").unwrap();
+        self.builder.build_store(mark, link).unwrap();
+        self.builder.build_store(top, obj).unwrap();
+        self.builder.build_unconditional_branch(after_block).unwrap();
+
+        // after block
+        self.builder.position_at_end(after_block);
+    }
+
+    // void __gc_collect__(), marks the objects reachable from the shadow stack and frees the others
+    fn gen_gc_collect(&self) {
+        self.add_fun("__gc_collect__", self.void_fn(&[]));
+        let ptr_ty = self.ptr_type();
+        let i64_ty = self.context.i64_type();
+        let mark = self.module.get_function("__gc_mark__").unwrap();
+
+        // mark the roots of every frame, frames are { prev, count, [slots] }
+        let frame_ty = self.frame_type();
+        let frame = self.builder.build_alloca(ptr_ty, "frame").unwrap();
+        self.builder.build_store(frame, self.load_global("__gc_roots__", ptr_ty.into())).unwrap();
+        self.build_while(
+            |runtime| {
+                let val = runtime.builder.build_load(ptr_ty, frame, "frame").unwrap().into_pointer_value();
+                runtime.builder.build_is_not_null(val, "has_frame").unwrap()
+            },
+            |runtime| {
+                let val = runtime.builder.build_load(ptr_ty, frame, "frame").unwrap().into_pointer_value();
+                let val = runtime.builder.build_pointer_cast(val, frame_ty.ptr_type(AddressSpace::default()), "frame").unwrap();
+                let count_ptr = runtime.builder.build_struct_gep(frame_ty, val, 1, "count_ptr").unwrap();
+                let count = runtime.builder.build_load(i64_ty, count_ptr, "count").unwrap().into_int_value();
+                runtime.build_for(count, |runtime, i| {
+                    let slot = unsafe {
+                        runtime.builder
+                            .build_gep(frame_ty, val, &[i64_ty.const_zero(), runtime.context.i32_type().const_int(2, false), i], "slot_ptr")
+                            .unwrap()
+                    };
+                    let slot = runtime.builder.build_load(ptr_ty, slot, "slot").unwrap().into_pointer_value();
+                    let slot = runtime.builder.build_pointer_cast(slot, ptr_ty.ptr_type(AddressSpace::default()), "slot").unwrap();
+                    let obj = runtime.builder.build_load(ptr_ty, slot, "obj").unwrap();
+                    runtime.builder.build_call(mark, &[obj.into()], "").unwrap();
+                });
+                let prev_ptr = runtime.builder.build_struct_gep(frame_ty, val, 0, "prev_ptr").unwrap();
+                let prev = runtime.builder.build_load(ptr_ty, prev_ptr, "prev").unwrap();
+                runtime.builder.build_store(frame, prev).unwrap();
+            },
+        );
+
+        // sweep the list of objects, unlinking and freeing the unmarked ones
+        let objects = self.module.get_global("__gc_objects__").unwrap().as_pointer_value();
+        let link = self.builder.build_alloca(ptr_ty.ptr_type(AddressSpace::default()), "link").unwrap();
+        self.builder.build_store(link, objects).unwrap();
+        let live = self.builder.build_alloca(i64_ty, "live").unwrap();
+        self.builder.build_store(live, i64_ty.const_zero()).unwrap();
+        let header_ptr_ty = self.header_type().ptr_type(AddressSpace::default());
+        let load_raw = |runtime: &Self| {
+            let link = runtime.builder.build_load(ptr_ty.ptr_type(AddressSpace::default()), link, "link").unwrap().into_pointer_value();
+            (link, runtime.builder.build_load(ptr_ty, link, "raw").unwrap().into_pointer_value())
+        };
+        self.build_while(
+            |runtime| runtime.builder.build_is_not_null(load_raw(runtime).1, "has_object").unwrap(),
+            |runtime| {
+                let fun = runtime.builder.get_insert_block().unwrap().get_parent().unwrap();
+                let keep_block = runtime.context.append_basic_block(fun, "keep");
+                let free_block = runtime.context.append_basic_block(fun, "free");
+                let next_block = runtime.context.append_basic_block(fun, "next");
+                let (link_val, raw) = load_raw(runtime);
+                let header = runtime.builder.build_pointer_cast(raw, header_ptr_ty, "header").unwrap();
+                let mark = runtime.header_field(header, 1);
+                let mark_val = runtime.builder.build_load(i64_ty, mark, "mark").unwrap().into_int_value();
+                let is_marked = runtime.builder.build_int_compare(IntPredicate::NE, mark_val, i64_ty.const_zero(), "is_marked").unwrap();
+                runtime.builder.build_conditional_branch(is_marked, keep_block, free_block).unwrap();
+
+                // keep block
+                runtime.builder.position_at_end(keep_block);
+                runtime.builder.build_store(mark, i64_ty.const_zero()).unwrap();
+                let size = runtime.builder.build_load(i64_ty, runtime.header_field(header, 3), "size").unwrap().into_int_value();
+                let live_val = runtime.builder.build_load(i64_ty, live, "live").unwrap().into_int_value();
+                let live_val = runtime.builder.build_int_add(live_val, size, "live").unwrap();
+                runtime.builder.build_store(live, live_val).unwrap();
+                runtime.builder.build_store(link, runtime.header_field(header, 0)).unwrap();
+                runtime.builder.build_unconditional_branch(next_block).unwrap();
+
+                // free block
+                runtime.builder.position_at_end(free_block);
+                let next = runtime.builder.build_load(ptr_ty, runtime.header_field(header, 0), "next").unwrap();
+                runtime.builder.build_store(link_val, next).unwrap();
+                runtime.build_free(header);
+                runtime.builder.build_unconditional_branch(next_block).unwrap();
+
+                runtime.builder.position_at_end(next_block);
+            },
+        );
+
+        // the next collection happens once the heap doubles
+        let live = self.builder.build_load(i64_ty, live, "live").unwrap().into_int_value();
+        let min = i64_ty.const_int(GC_MIN_THRESHOLD, false);
+        let is_small = self.builder.build_int_compare(IntPredicate::ULT, live, min, "is_small").unwrap();
+        let threshold = self.builder.build_select(is_small, min, live, "threshold").unwrap();
+        self.builder.build_store(self.module.get_global("__gc_threshold__").unwrap().as_pointer_value(), threshold).unwrap();
+        self.builder.build_store(self.module.get_global("__gc_allocated__").unwrap().as_pointer_value(), i64_ty.const_zero()).unwrap();
+        self.builder.build_return(None).unwrap();
+    }
+
+    // loops over the elements of an array object, if they are references to other objects
+    fn build_elems_loop<F>(&self, header: PointerValue<'ctx>, obj: PointerValue<'ctx>, body: F)
+    where
+        F: Fn(&Self, PointerValue<'ctx>),
+    {
+        let i64_ty = self.context.i64_type();
+        let has_refs = self.builder.build_load(i64_ty, self.header_field(header, 2), "has_refs").unwrap().into_int_value();
+        let fun = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let elems_block = self.context.append_basic_block(fun, "elems");
+        let after_block = self.context.append_basic_block(fun, "elems_after");
+        let cmp = self.builder.build_int_compare(IntPredicate::NE, has_refs, i64_ty.const_zero(), "has_refs").unwrap();
+        self.builder.build_conditional_branch(cmp, elems_block, after_block).unwrap();
+
+        // arrays of references are { length, [pointers] }
+        self.builder.position_at_end(elems_block);
+        let arr_ty = self.context.struct_type(&[i64_ty.into(), self.ptr_type().array_type(0).into()], false);
+        let arr = self.builder.build_pointer_cast(obj, arr_ty.ptr_type(AddressSpace::default()), "arr").unwrap();
+        let len_ptr = self.builder.build_struct_gep(arr_ty, arr, 0, "len_ptr").unwrap();
+        let len = self.builder.build_load(i64_ty, len_ptr, "len").unwrap().into_int_value();
+        self.build_for(len, |runtime, i| {
+            let elem_ptr = unsafe {
+                runtime.builder
+                    .build_gep(arr_ty, arr, &[i64_ty.const_zero(), runtime.context.i32_type().const_int(1, false), i], "elem_ptr")
+                    .unwrap()
+            };
+            let elem = runtime.builder.build_load(runtime.ptr_type(), elem_ptr, "elem").unwrap().into_pointer_value();
+            body(runtime, elem);
+        });
+        self.builder.build_unconditional_branch(after_block).unwrap();
+        self.builder.position_at_end(after_block);
+    }
+
+    // generates a loop with the body called for each index from 0 to count
+    fn build_for<F>(&self, count: IntValue<'ctx>, body: F)
+    where
+        F: Fn(&Self, IntValue<'ctx>),
+    {
+        let i64_ty = self.context.i64_type();
+        let index = self.entry_alloca(i64_ty.into(), "index");
+        self.builder.build_store(index, i64_ty.const_zero()).unwrap();
+        self.build_while(
+            |runtime| {
+                let i = runtime.builder.build_load(i64_ty, index, "i").unwrap().into_int_value();
+                runtime.builder.build_int_compare(IntPredicate::SLT, i, count, "cmp").unwrap()
+            },
+            |runtime| {
+                let i = runtime.builder.build_load(i64_ty, index, "i").unwrap().into_int_value();
+                body(runtime, i);
+                let next = runtime.builder.build_int_add(i, i64_ty.const_int(1, false), "next").unwrap();
+                runtime.builder.build_store(index, next).unwrap();
+            },
+        );
+    }
+
+    fn build_while<C, F>(&self, cond: C, body: F)
+    where
+        C: Fn(&Self) -> IntValue<'ctx>,
+        F: Fn(&Self),
+    {
+        let fun = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let cond_block = self.context.append_basic_block(fun, "cond");
+        let body_block = self.context.append_basic_block(fun, "body");
+        let after_block = self.context.append_basic_block(fun, "after");
+        self.builder.build_unconditional_branch(cond_block).unwrap();
+
+        // cond block
+        self.builder.position_at_end(cond_block);
+        let cmp = cond(self);
+        self.builder.build_conditional_branch(cmp, body_block, after_block).unwrap();
+
+        // body block
+        self.builder.position_at_end(body_block);
+        body(self);
+        self.builder.build_unconditional_branch(cond_block).unwrap();
+
+        // after block
+        self.builder.position_at_end(after_block);
+    }
+
+    // allocas in the entry block, so that loops that run them do not grow the stack
+    fn entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let fun = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let entry = fun.get_first_basic_block().unwrap();
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instr) => builder.position_before(&instr),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name).unwrap()
+    }
+
+    fn build_return_if(&self, cond: IntValue<'ctx>) {
+        let fun = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let return_block = self.context.append_basic_block(fun, "return");
+        let continue_block = self.context.append_basic_block(fun, "continue");
+        self.builder.build_conditional_branch(cond, return_block, continue_block).unwrap();
+        self.builder.position_at_end(return_block);
+        self.builder.build_return(None).unwrap();
+        self.builder.position_at_end(continue_block);
+    }
+
+    fn build_free(&self, header: PointerValue<'ctx>) {
+        let free = self.extern_fun("free", self.void_fn(&[self.ptr_type().into()]));
+        let raw = self.builder.build_pointer_cast(header, self.ptr_type(), "raw").unwrap();
+        self.builder.build_call(free, &[raw.into()], "").unwrap();
+    }
+
+    // the header is right before the object
+    fn header_of(&self, obj: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let offset = self.header_type().size_of().unwrap().const_neg();
+        let raw = unsafe { self.builder.build_gep(self.context.i8_type(), obj, &[offset], "raw").unwrap() };
+        self.builder.build_pointer_cast(raw, self.header_type().ptr_type(AddressSpace::default()), "header").unwrap()
+    }
+
+    fn header_field(&self, header: PointerValue<'ctx>, index: u32) -> PointerValue<'ctx> {
+        self.builder.build_struct_gep(self.header_type(), header, index, "field").unwrap()
+    }
+
+    // heap objects are preceded by a header { next object, mark or reference count, has references, size }
+    fn header_type(&self) -> StructType<'ctx> {
+        let i64_ty = self.context.i64_type();
+        self.context.struct_type(&[self.ptr_type().into(), i64_ty.into(), i64_ty.into(), i64_ty.into()], false)
+    }
+
+    fn frame_type(&self) -> StructType<'ctx> {
+        let ptr_ty = self.ptr_type();
+        self.context.struct_type(&[ptr_ty.into(), self.context.i64_type().into(), ptr_ty.array_type(0).into()], false)
+    }
+
+    fn add_global(&self, name: &str, ty: BasicTypeEnum<'ctx>) -> GlobalValue<'ctx> {
+        let global = self.module.add_global(ty, None, name);
+        global.set_linkage(Linkage::Internal);
+        global
+    }
+
+    fn load_global(&self, name: &str, ty: BasicTypeEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        let global = self.module.get_global(name).unwrap().as_pointer_value();
+        self.builder.build_load(ty, global, name).unwrap()
+    }
+
+    // adds an internal function and positions the builder at its entry block
+    fn add_fun(&self, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        let fun = self.module.add_function(name, ty, Some(Linkage::Internal));
+        let entry = self.context.append_basic_block(fun, "entry");
+        self.builder.position_at_end(entry);
+        fun
+    }
+
+    fn call_printf(&self, args: &[BasicMetadataValueEnum<'ctx>]) {
+        let i32_ty = self.context.i32_type();
+        let printf = self.extern_fun("printf", i32_ty.fn_type(&[self.str_type().into()], true));
+        self.builder.build_call(printf, args, "printf").unwrap();
+    }
+
+    fn extern_fun(&self, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module
+            .get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, ty, None))
+    }
+
+    fn global_str(&self, str: &str) -> PointerValue<'ctx> {
+        self.builder.build_global_string_ptr(str, "str").unwrap().as_pointer_value()
+    }
+
+    fn void_fn(&self, params: &[BasicMetadataTypeEnum<'ctx>]) -> FunctionType<'ctx> {
+        self.context.void_type().fn_type(params, false)
+    }
+
+    fn str_type(&self) -> PointerType<'ctx> {
+        self.context.i8_type().ptr_type(AddressSpace::default())
+    }
+
+    fn ptr_type(&self) -> PointerType<'ctx> {
+        self.context.i8_type().ptr_type(AddressSpace::default())
+    }
+}
\ No newline at end of file
@@ -1,5 +1,6 @@
 use crate::syntax::ast::{Id, Span, Spanned, Type};
 use crate::syntax::lexer::Token;
+use crate::utils::get_position_in_src;
 
 #[derive(Debug, Clone)]
 pub enum AgudaError {
@@ -9,7 +10,37 @@ pub enum AgudaError {
 
 #[derive(Debug, Clone)]
 pub struct RuntimeError {
-    pub message: String,
+    pub kind: RuntimeErrorKind,
+    pub pos: Option<Location>, // location reported by the program
+}
+
+// lines and columns of the start and the end of the expression that failed, with the end exclusive
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Location {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    IndexOutOfBounds {
+        index: i64,
+        length: i64,
+    },
+    IntegerOverflow {
+        op: String,
+    },
+    DivisionByZero {
+        op: String,
+    },
+    StackOverflow {
+        fun: String,
+    },
+    InvalidArraySize {
+        size: i64,
+    },
+    OutOfMemory,
+    Unknown(String),
 }
 
 #[derive(Debug, Clone)]
@@ -17,6 +48,7 @@ pub enum CompileError {
     Lexical(LexicalError),
     Syntax(SyntaxError),
     Semantic(SemanticError),
+    Backend(BackendError),
 }
 
 #[derive(Debug, Clone)]
@@ -50,6 +82,23 @@ pub enum SyntaxErrorKind {
     ExtraToken,
 }
 
+#[derive(Debug, Clone)]
+pub struct BackendError {
+    pub kind: BackendErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendErrorKind {
+    UnsupportedTarget(String),
+    Optimization(String),
+    ObjectEmission(String),
+    Linking(String),
+    CCompilation(String),
+    UnsupportedOption(String),
+    InvalidBytecode(String),
+    InvalidIr(String), // bug in the lowering, found by the verifier
+}
+
 #[derive(Debug, Clone)]
 pub enum SemanticError {
     Declaration(DeclarationError),
@@ -73,6 +122,7 @@ pub enum DeclarationErrorKind {
     },
     DuplicateMain,
     MissingMain,
+    CapturedAssignment(Id),
 }
 
 #[derive(Debug, Clone)]
@@ -245,6 +295,13 @@ impl DeclarationError {
             span: Span::default(),
         }
     }
+
+    pub fn captured_assignment(spanned: Spanned<Id>) -> Self {
+        Self {
+            kind: DeclarationErrorKind::CapturedAssignment(spanned.value),
+            span: spanned.span,
+        }
+    }
 }
 
 impl From<DeclarationError> for CompileError {
@@ -303,6 +360,176 @@ impl From<TypeError> for CompileError {
     }
 }
 
+impl BackendError {
+    pub fn unsupported_target(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::UnsupportedTarget(message),
+        }
+    }
+
+    pub fn optimization(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::Optimization(message),
+        }
+    }
+
+    pub fn object_emission(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::ObjectEmission(message),
+        }
+    }
+
+    pub fn linking(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::Linking(message),
+        }
+    }
+
+    pub fn c_compilation(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::CCompilation(message),
+        }
+    }
+
+    pub fn unsupported_option(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::UnsupportedOption(message),
+        }
+    }
+
+    pub fn invalid_bytecode(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::InvalidBytecode(message),
+        }
+    }
+
+    pub fn invalid_ir(message: String) -> Self {
+        Self {
+            kind: BackendErrorKind::InvalidIr(message),
+        }
+    }
+}
+
+impl From<BackendError> for CompileError {
+    fn from(e: BackendError) -> Self {
+        CompileError::Backend(e)
+    }
+}
+
+impl Location {
+    pub fn from_span(src: &str, span: &Span) -> Self {
+        Self {
+            start: get_position_in_src(src, span.start),
+            end: get_position_in_src(src, span.end),
+        }
+    }
+}
+
+impl RuntimeError {
+    pub fn index_out_of_bounds(pos: Location, index: i64, length: i64) -> Self {
+        Self {
+            kind: RuntimeErrorKind::IndexOutOfBounds { index, length },
+            pos: Some(pos),
+        }
+    }
+
+    pub fn integer_overflow(pos: Location, op: String) -> Self {
+        Self {
+            kind: RuntimeErrorKind::IntegerOverflow { op },
+            pos: Some(pos),
+        }
+    }
+
+    pub fn division_by_zero(pos: Location, op: String) -> Self {
+        Self {
+            kind: RuntimeErrorKind::DivisionByZero { op },
+            pos: Some(pos),
+        }
+    }
+
+    pub fn stack_overflow(pos: Location, fun: String) -> Self {
+        Self {
+            kind: RuntimeErrorKind::StackOverflow { fun },
+            pos: Some(pos),
+        }
+    }
+
+    pub fn invalid_array_size(pos: Location, size: i64) -> Self {
+        Self {
+            kind: RuntimeErrorKind::InvalidArraySize { size },
+            pos: Some(pos),
+        }
+    }
+
+    pub fn out_of_memory(pos: Location) -> Self {
+        Self {
+            kind: RuntimeErrorKind::OutOfMemory,
+            pos: Some(pos),
+        }
+    }
+
+    pub fn unknown(message: String) -> Self {
+        Self {
+            kind: RuntimeErrorKind::Unknown(message),
+            pos: None,
+        }
+    }
+
+    // parses the errors printed by the generated code, in the format file:line:col-line:col: message
+    pub fn from_stderr(stderr: &str) -> Self {
+        stderr
+            .lines()
+            .find_map(Self::parse_located)
+            .unwrap_or_else(|| Self::unknown(stderr.trim().to_string()))
+    }
+
+    fn parse_located(line: &str) -> Option<Self> {
+        let (location, message) = line.split_once(": ")?;
+        let mut parts = location.rsplitn(4, ':');
+        let end_col = parts.next()?.parse().ok()?;
+        let (col, end_line) = parts.next()?.split_once('-')?;
+        let line = parts.next()?.parse().ok()?;
+        let pos = Location {
+            start: (line, col.parse().ok()?),
+            end: (end_line.parse().ok()?, end_col),
+        };
+
+        // integer overflow in '<op>'
+        if let Some(op) = message.strip_prefix("integer overflow in '") {
+            let op = op.trim().strip_suffix('\'')?;
+            return Some(Self::integer_overflow(pos, op.to_string()));
+        }
+
+        // division by zero in '<op>'
+        if let Some(op) = message.strip_prefix("division by zero in '") {
+            let op = op.trim().strip_suffix('\'')?;
+            return Some(Self::division_by_zero(pos, op.to_string()));
+        }
+
+        // stack overflow in function `<fun>`
+        if let Some(fun) = message.strip_prefix("stack overflow in function `") {
+            let fun = fun.trim().strip_suffix('`')?;
+            return Some(Self::stack_overflow(pos, fun.to_string()));
+        }
+
+        // invalid array size <size>
+        if let Some(size) = message.strip_prefix("invalid array size ") {
+            return Some(Self::invalid_array_size(pos, size.trim().parse().ok()?));
+        }
+
+        // out of memory
+        if message.trim() == "out of memory" {
+            return Some(Self::out_of_memory(pos));
+        }
+
+        // index <index> out of bounds for length <length>
+        let (index, length) = message
+            .strip_prefix("index ")?
+            .split_once(" out of bounds for length ")?;
+        Some(Self::index_out_of_bounds(pos, index.parse().ok()?, length.trim().parse().ok()?))
+    }
+}
+
 impl From<CompileError> for AgudaError {
     fn from(e: CompileError) -> Self {
         AgudaError::Compile(e)
@@ -4,7 +4,7 @@ use crate::diagnostics::errors::*;
 use crate::diagnostics::hints::get_syntax_hints;
 use crate::diagnostics::warnings::Warning;
 use crate::syntax::ast::Span;
-use crate::utils::get_position_in_src;
+use crate::utils::{get_index_in_src, get_position_in_src};
 
 pub fn format_aguda_errors(
     errors: Vec<AgudaError>,
@@ -102,6 +102,11 @@ fn format_aguda_error(e: &AgudaError, suppress_hints: bool, path: &str, src: &st
                                 );
                                 (msg, Some(hint))
                             }
+                            DeclarationErrorKind::CapturedAssignment(id) => {
+                                let msg = format!("captured variable {} cannot be assigned", id.bold());
+                                let hint = "nested functions capture variables by value".to_string();
+                                (msg, Some(hint))
+                            }
                         };
                         diagnostic
                             .hints(hint.map(|h| vec![h]).unwrap_or_default())
@@ -144,17 +149,65 @@ fn format_aguda_error(e: &AgudaError, suppress_hints: bool, path: &str, src: &st
                                 )
                             },
                             TypeErrorKind::MainSignatureMismatch => {
-                                format!("main function must have signature {}", "Unit -> Unit".bold())
+                                format!(
+                                    "main function must have signature {} or {}",
+                                    "Unit -> Unit".bold(),
+                                    "Unit -> Int".bold()
+                                )
                             }
                         };
                         diagnostic.render(label, &description, e.span.clone())
                     }
                 }
             }
+            CompileError::Backend(e) => {
+                let label = "Backend Error";
+                let description = match &e.kind {
+                    BackendErrorKind::UnsupportedTarget(message) => format!("unsupported target: {}", message),
+                    BackendErrorKind::Optimization(message) => format!("failed to optimize: {}", message),
+                    BackendErrorKind::ObjectEmission(message) => format!("failed to emit object file: {}", message),
+                    BackendErrorKind::Linking(message) => format!("failed to link executable: {}", message),
+                    BackendErrorKind::CCompilation(message) => format!("failed to compile c source: {}", message),
+                    BackendErrorKind::UnsupportedOption(message) => format!("unsupported option: {}", message),
+                    BackendErrorKind::InvalidBytecode(message) => format!("invalid bytecode file: {}", message),
+                    BackendErrorKind::InvalidIr(message) => format!("internal compiler error, invalid ir: {}", message),
+                };
+                diagnostic.render_simple(label, &description)
+            }
         },
         AgudaError::Runtime(e) => {
             let label = "Runtime Error";
-            diagnostic.render_simple(&label, &e.message)
+            let description = match &e.kind {
+                RuntimeErrorKind::IndexOutOfBounds { index, length } => {
+                    format!(
+                        "index {} out of bounds for length {}",
+                        index.to_string().bold(),
+                        length.to_string().bold()
+                    )
+                }
+                RuntimeErrorKind::IntegerOverflow { op } => {
+                    format!("integer overflow in {}", op.bold())
+                }
+                RuntimeErrorKind::DivisionByZero { op } => {
+                    format!("division by zero in {}", op.bold())
+                }
+                RuntimeErrorKind::StackOverflow { fun } => {
+                    format!("stack overflow in function {}", fun.bold())
+                }
+                RuntimeErrorKind::InvalidArraySize { size } => {
+                    format!("invalid array size {}", size.to_string().bold())
+                }
+                RuntimeErrorKind::OutOfMemory => "out of memory".to_string(),
+                RuntimeErrorKind::Unknown(message) => message.clone(),
+            };
+            match e.pos {
+                Some(Location { start: (line, col), end: (end_line, end_col) }) => {
+                    let start = get_index_in_src(src, line, col);
+                    let end = get_index_in_src(src, end_line, end_col).max(start + 1);
+                    diagnostic.render(&format!("{}:", label), &description, start..end)
+                }
+                None => diagnostic.render_simple(label, &description),
+            }
         }
     }
 }
@@ -193,6 +246,29 @@ fn format_warning(warning: &Warning, suppress_hints: bool, path: &str, src: &str
                 .hints(vec![hint])
                 .render(label, &msg, sym.span.clone())
         }
+        Warning::MisplacedTailCall(call) => {
+            let msg = format!("call to {} is not in tail position", call.value.bold());
+            let hint = "only calls whose result is returned by the function are tail calls, \
+                like the last expression of its body or of the branches of an if in tail position";
+            diagnostic
+                .hints(vec![hint.to_string()])
+                .render(label, &msg, call.span.clone())
+        }
+        Warning::ConstantOverflow(op) => {
+            let msg = format!("integer overflow in {} with constant operands", op.value.bold());
+            let hint = "the result wraps around, unless the program is compiled with --overflow trap, \
+                which stops it when the expression is evaluated";
+            diagnostic
+                .hints(vec![hint.to_string()])
+                .render(label, &msg, op.span.clone())
+        }
+        Warning::ConstantDivisionByZero(op) => {
+            let msg = format!("division by zero in {} with constant operands", op.value.bold());
+            let hint = "the program stops with a runtime error when the expression is evaluated";
+            diagnostic
+                .hints(vec![hint.to_string()])
+                .render(label, &msg, op.span.clone())
+        }
     }
 }
 
@@ -3,4 +3,7 @@ use crate::syntax::ast::{Id, Spanned};
 #[derive(Debug, Clone)]
 pub enum Warning {
     UnusedIdentifier(Spanned<Id>),
+    MisplacedTailCall(Spanned<Id>),
+    ConstantOverflow(Spanned<String>), // operator of an overflowing operation on literals
+    ConstantDivisionByZero(Spanned<String>),
 }
@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+use crate::diagnostics::errors::{Location, RuntimeError};
+use crate::interpreter::value::{Closure, Value};
+use crate::passes::subexprs;
+use crate::semantic::captures::captured_vars;
+use crate::semantic::RESERVED_IDENTIFIERS;
+use crate::syntax::ast::*;
+use crate::{CompileOptions, Overflow};
+
+// nested calls evaluate many expressions each, so the interpreter runs in a thread with a large stack
+const STACK_SIZE: usize = 1 << 30;
+
+// part of the stack left for the expressions evaluated by the innermost call, which calls do not enter
+// when the program has no maximum recursion depth
+const STACK_MARGIN: usize = 64 << 20;
+
+// runs the checked program, returning its output and exit status
+pub fn interpret(prog: &Program, src: &str, options: &CompileOptions) -> Result<(Vec<u8>, i32), RuntimeError> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || Interpreter::new(prog, src, options).run())
+            .map_err(|e| RuntimeError::unknown(format!("failed to start the interpreter: {}", e)))?
+            .join()
+            .unwrap_or_else(|_| Err(RuntimeError::unknown("the interpreter stopped unexpectedly".to_string())))
+    })
+}
+
+struct FunDef<'a> {
+    id: &'a Id,
+    params: &'a [Spanned<Id>],
+    ret: &'a Type,
+    body: &'a Spanned<Expr>,
+    span: &'a Span,
+    captures: Vec<&'a Id>, // variables captured by a nested function
+}
+
+// reasons for the evaluation to stop before the program ends
+enum Stop {
+    Error(Box<RuntimeError>), // boxed, so that the results of the nested calls take little stack
+    Exit(i32),
+}
+
+impl From<RuntimeError> for Stop {
+    fn from(e: RuntimeError) -> Self {
+        Stop::Error(Box::new(e))
+    }
+}
+
+type Eval<T> = Result<T, Stop>;
+
+pub struct Interpreter<'a> {
+    prog: &'a Program,
+    src: &'a str,
+    options: CompileOptions,
+    funs: Vec<FunDef<'a>>,
+    fun_ids: HashMap<&'a str, usize>,
+    nested_ids: HashMap<&'a Span, usize>, // nested functions by the span of their name
+    globals: HashMap<&'a str, Value>,
+    locals: Vec<(&'a str, Value)>, // variables of the active calls, the innermost last
+    frame: usize, // index of the first variable of the current call
+    tail_call: Option<(Value, Vec<Value>)>, // call made in tail position, run by the caller of the function
+    depth: u64,
+    stack_limit: usize, // lowest address of the stack that a call may start at
+    out: Vec<u8>,
+}
+
+impl<'a> Interpreter<'a> {
+
+    // created at the start of the thread of the interpreter, which owns the stack below
+    pub fn new(prog: &'a Program, src: &'a str, options: &CompileOptions) -> Self {
+        let mut funs = vec![];
+        for decl in &prog.decls {
+            if let Decl::Fun { id, params, ty, expr } = &decl.value {
+                funs.push(FunDef { id: &id.value, params, ret: &ty.value.ret, body: expr, span: &decl.span, captures: vec![] });
+            }
+        }
+        let fun_ids = funs.iter().enumerate().map(|(i, fun)| (fun.id.as_str(), i)).collect();
+
+        // nested functions come after the top-level ones
+        let mut captures = captured_vars(prog);
+        let mut nested_ids = HashMap::new();
+        let mut exprs = prog.decls.iter().map(|decl| match &decl.value {
+            Decl::Var { expr, .. } | Decl::Fun { expr, .. } => expr,
+        }).collect::<Vec<_>>();
+        while let Some(expr) = exprs.pop() {
+            if let Expr::LetFun { id, params, ty, expr: body } = &expr.value {
+                nested_ids.insert(&id.span, funs.len());
+                let captures = captures.remove(&id.span).unwrap_or_default();
+                funs.push(FunDef { id: &id.value, params, ret: &ty.value.ret, body, span: &expr.span, captures });
+            }
+            exprs.extend(subexprs(expr));
+        }
+        Interpreter {
+            prog,
+            src,
+            options: options.clone(),
+            funs,
+            fun_ids,
+            nested_ids,
+            globals: HashMap::new(),
+            locals: vec![],
+            frame: 0,
+            tail_call: None,
+            depth: 0,
+            stack_limit: stack_address().saturating_sub(STACK_SIZE - STACK_MARGIN),
+            out: vec![],
+        }
+    }
+
+    // initializes the globals in declaration order and calls main, which returns the exit status
+    pub fn run(mut self) -> Result<(Vec<u8>, i32), RuntimeError> {
+        let status = match self.run_main() {
+            Ok(status) | Err(Stop::Exit(status)) => status,
+            Err(Stop::Error(e)) => return Err(*e),
+        };
+        Ok((self.out, status))
+    }
+
+    fn run_main(&mut self) -> Eval<i32> {
+        for decl in &self.prog.decls {
+            if let Decl::Var { id, expr, .. } = &decl.value {
+                let val = self.eval(expr)?;
+                if id.value != "_" {
+                    self.globals.insert(&id.value, val);
+                }
+            }
+        }
+        let main = *self.fun_ids.get("main").expect("undefined function main");
+        let ret = self.call(Value::Fun(main), vec![Value::Unit])?;
+        match self.funs[main].ret {
+            Type::Int => Ok(ret.as_int() as i32),
+            _ => Ok(0),
+        }
+    }
+
+    // calls in tail position are run in a loop, so they do not grow the stack
+    fn call(&mut self, mut fun: Value, mut args: Vec<Value>) -> Eval<Value> {
+        self.depth += 1;
+        let too_deep = match self.options.max_recursion_depth {
+            None => stack_address() < self.stack_limit,
+            Some(max_depth) => max_depth > 0 && self.depth > max_depth,
+        };
+        if too_deep {
+            let def = &self.funs[fun_index(&fun)];
+            return Err(RuntimeError::stack_overflow(self.position(def.span), def.id.clone()).into());
+        }
+        let caller_frame = self.frame;
+        self.frame = self.locals.len();
+        let result = loop {
+            let def = &self.funs[fun_index(&fun)];
+            let (body, is_main) = (def.body, def.id == "main" && !matches!(fun, Value::Closure(_)));
+            self.locals.truncate(self.frame);
+            // nested functions see themselves and the values of the variables they capture
+            if let Value::Closure(closure) = &fun {
+                if def.id != "_" {
+                    self.locals.push((def.id, fun.clone()));
+                }
+                self.locals.extend(def.captures.iter().map(|id| id.as_str()).zip(closure.env.iter().cloned()));
+            }
+            for (param, arg) in def.params.iter().zip(args) {
+                if param.value != "_" {
+                    self.locals.push((&param.value, arg));
+                }
+            }
+            // main returns an exit code instead of the value of its body
+            let val = if is_main { self.eval(body) } else { self.eval_tail(body) };
+            match val {
+                Ok(val) => match self.tail_call.take() {
+                    Some((next, next_args)) => {
+                        fun = next;
+                        args = next_args;
+                    }
+                    None => break Ok(val),
+                },
+                Err(stop) => break Err(stop),
+            }
+        };
+        self.locals.truncate(self.frame);
+        self.frame = caller_frame;
+        self.depth -= 1;
+        result
+    }
+
+    fn eval(&mut self, expr: &'a Spanned<Expr>) -> Eval<Value> {
+        match &expr.value {
+            Expr::Chain { lhs, rhs } => {
+                let scope = self.locals.len();
+                self.eval(lhs)?;
+                let val = self.eval(rhs);
+                // the variable declared by a let is only visible in the rhs
+                self.locals.truncate(scope);
+                val
+            }
+            Expr::Let { id, expr, .. } => {
+                let val = self.eval(expr)?;
+                if id.value != "_" {
+                    self.locals.push((&id.value, val));
+                }
+                Ok(Value::Unit)
+            }
+            Expr::LetFun { id, .. } => {
+                let fun = self.nested_ids[&id.span];
+                let env = self.funs[fun].captures.iter().map(|var| self.lookup(var)).collect();
+                if id.value != "_" {
+                    self.locals.push((&id.value, Value::Closure(Rc::new(Closure { fun, env }))));
+                }
+                Ok(Value::Unit)
+            }
+            Expr::Set { lhs, expr } => {
+                match &lhs.value {
+                    Lhs::Var { id } => {
+                        let val = self.eval(expr)?;
+                        self.assign(&id.value, val);
+                    }
+                    Lhs::Index { lhs: arr, index } => {
+                        let (arr, index) = self.eval_index(arr, index, &lhs.span)?;
+                        let val = self.eval(expr)?;
+                        arr.as_array().borrow_mut()[index] = val;
+                    }
+                }
+                Ok(Value::Unit)
+            }
+            Expr::BinOp { lhs, op, rhs } => {
+                match op {
+                    // short circuit operators
+                    Op::And => Ok(Value::Bool(self.eval(lhs)?.as_bool() && self.eval(rhs)?.as_bool())),
+                    Op::Or => Ok(Value::Bool(self.eval(lhs)?.as_bool() || self.eval(rhs)?.as_bool())),
+                    Op::Eq => Ok(Value::Bool(self.eval(lhs)?.equals(&self.eval(rhs)?))),
+                    Op::Neq => Ok(Value::Bool(!self.eval(lhs)?.equals(&self.eval(rhs)?))),
+                    _ => {
+                        let l = self.eval(lhs)?.as_int();
+                        let r = self.eval(rhs)?.as_int();
+                        Ok(eval_int_op(l, op, r, self.options.overflow, || self.position(&expr.span))?)
+                    }
+                }
+            }
+            Expr::Not { expr } => Ok(Value::Bool(!self.eval(expr)?.as_bool())),
+            Expr::While { cond, expr } => {
+                while self.eval(cond)?.as_bool() {
+                    self.eval(expr)?;
+                }
+                Ok(Value::Unit)
+            }
+            Expr::IfElse { cond, then, els } => {
+                if self.eval(cond)?.as_bool() {
+                    self.eval(then)
+                } else {
+                    self.eval(els)
+                }
+            }
+            Expr::FunCall { id, args, .. } => {
+                match id.value.as_str() {
+                    "print" => {
+                        let val = self.eval(args.first().unwrap())?;
+                        write!(self.out, "{}", val).unwrap();
+                        return Ok(Value::Unit);
+                    }
+                    "length" => {
+                        let arr = self.eval(args.first().unwrap())?;
+                        return Ok(Value::Int(arr.as_array().borrow().len() as i64));
+                    }
+                    "exit" => {
+                        let code = self.eval(args.first().unwrap())?.as_int();
+                        return Err(Stop::Exit(code as i32));
+                    }
+                    _ => {}
+                }
+                let (fun, args) = self.eval_call(id, args)?;
+                self.call(fun, args)
+            }
+            Expr::NewArray { size, init, .. } => {
+                let size = self.eval(size)?.as_int();
+                let init = self.eval(init)?;
+                Ok(Value::new_array(size, init, self.position(&expr.span))?)
+            }
+            Expr::ArrayIndex { lhs, index } => {
+                let (arr, index) = self.eval_index(lhs, index, &expr.span)?;
+                let elem = arr.as_array().borrow()[index].clone();
+                Ok(elem)
+            }
+            Expr::Id(id) => Ok(self.lookup(&id.value)),
+            Expr::Int(n) => Ok(Value::Int(*n)),
+            Expr::Bool(b) => Ok(Value::Bool(*b)),
+            Expr::String(str) => Ok(Value::String(Rc::from(str.as_str()))),
+            Expr::Unit => Ok(Value::Unit),
+        }
+    }
+
+    // evaluates an expression whose value is returned by the function, with the same tail calls as `tail_calls`
+    fn eval_tail(&mut self, expr: &'a Spanned<Expr>) -> Eval<Value> {
+        match &expr.value {
+            Expr::Chain { lhs, rhs } => {
+                let scope = self.locals.len();
+                self.eval(lhs)?;
+                let val = self.eval_tail(rhs);
+                self.locals.truncate(scope);
+                val
+            }
+            Expr::IfElse { cond, then, els } => {
+                if self.eval(cond)?.as_bool() {
+                    self.eval_tail(then)
+                } else {
+                    self.eval_tail(els)
+                }
+            }
+            Expr::FunCall { id, args, .. } if !RESERVED_IDENTIFIERS.contains(&id.value) && id.value != "main" => {
+                // the value is discarded, since the caller runs the call right after returning
+                self.tail_call = Some(self.eval_call(id, args)?);
+                Ok(Value::Unit)
+            }
+            _ => self.eval(expr),
+        }
+    }
+
+    // returns the called function and its arguments
+    fn eval_call(&mut self, id: &Spanned<Id>, args: &'a [Spanned<Expr>]) -> Eval<(Value, Vec<Value>)> {
+        let fun = self.lookup(&id.value);
+        let args = args.iter().map(|arg| self.eval(arg)).collect::<Eval<Vec<_>>>()?;
+        Ok((fun, args))
+    }
+
+    // returns the indexed array and the index, stopping the program when it is out of bounds
+    fn eval_index(&mut self, lhs: &'a Spanned<Lhs>, index: &'a Spanned<Expr>, span: &Span) -> Eval<(Value, usize)> {
+        let arr = match &lhs.value {
+            Lhs::Var { id } => self.lookup(&id.value),
+            Lhs::Index { lhs: inner, index } => {
+                let (arr, index) = self.eval_index(inner, index, &lhs.span)?;
+                let elem = arr.as_array().borrow()[index].clone();
+                elem
+            }
+        };
+        let index = self.eval(index)?.as_int();
+        // indexes are always checked, since there is no memory outside of the array to access
+        let length = arr.as_array().borrow().len() as i64;
+        if index < 0 || index >= length {
+            return Err(RuntimeError::index_out_of_bounds(self.position(span), index, length).into());
+        }
+        Ok((arr, index as usize))
+    }
+
+    // local variables shadow globals, which shadow functions
+    fn lookup(&self, id: &str) -> Value {
+        if let Some((_, val)) = self.locals[self.frame..].iter().rev().find(|(name, _)| *name == id) {
+            return val.clone();
+        }
+        if let Some(val) = self.globals.get(id) {
+            return val.clone();
+        }
+        match self.fun_ids.get(id) {
+            Some(fun) => Value::Fun(*fun),
+            None => panic!("undefined variable {}", id),
+        }
+    }
+
+    fn assign(&mut self, id: &str, val: Value) {
+        if let Some((_, var)) = self.locals[self.frame..].iter_mut().rev().find(|(name, _)| *name == id) {
+            *var = val;
+        } else if let Some(var) = self.globals.get_mut(id) {
+            *var = val;
+        } else {
+            panic!("undefined variable {}", id);
+        }
+    }
+
+    fn position(&self, span: &Span) -> Location {
+        Location::from_span(self.src, span)
+    }
+}
+
+// index of the definition of a function value
+fn fun_index(fun: &Value) -> usize {
+    match fun {
+        Value::Fun(fun) => *fun,
+        Value::Closure(closure) => closure.fun,
+        _ => panic!("value {:?} is not callable", fun),
+    }
+}
+
+// address of a variable in the frame of the caller, which shows how far the stack has grown
+fn stack_address() -> usize {
+    let probe = 0u8;
+    std::ptr::addr_of!(probe) as usize
+}
+
+// integer operations, which wrap around or stop the program on overflow like the compiled code,
+// computing the position of the operation only for errors
+pub fn eval_int_op(
+    l: i64,
+    op: &Op,
+    r: i64,
+    overflow: Overflow,
+    pos: impl FnOnce() -> Location,
+) -> Result<Value, RuntimeError> {
+    let res = match op {
+        Op::Div | Op::Mod if r == 0 => return Err(RuntimeError::division_by_zero(pos(), op.to_text())),
+        Op::Div => Some(l.wrapping_div(r)),
+        Op::Mod => Some(l.wrapping_rem(r)),
+        Op::Lt => return Ok(Value::Bool(l < r)),
+        Op::Leq => return Ok(Value::Bool(l <= r)),
+        Op::Gt => return Ok(Value::Bool(l > r)),
+        Op::Geq => return Ok(Value::Bool(l >= r)),
+        _ if overflow == Overflow::Wrap => match op {
+            Op::Add => Some(l.wrapping_add(r)),
+            Op::Sub => Some(l.wrapping_sub(r)),
+            Op::Mul => Some(l.wrapping_mul(r)),
+            Op::Pow => Some(wrapping_pow(l, r)),
+            _ => panic!("operator {} is not arithmetic", op.to_text()),
+        },
+        Op::Add => l.checked_add(r),
+        Op::Sub => l.checked_sub(r),
+        Op::Mul => l.checked_mul(r),
+        Op::Pow => checked_pow(l, r),
+        _ => panic!("operator {} is not arithmetic", op.to_text()),
+    };
+    res.map(Value::Int).ok_or_else(|| RuntimeError::integer_overflow(pos(), op.to_text()))
+}
+
+// multiplies a by itself b times, wrapping around on overflow
+fn wrapping_pow(mut a: i64, mut b: i64) -> i64 {
+    // exponentiation by squaring gives the same result, since wrapping multiplication is associative
+    let mut res: i64 = 1;
+    while b > 0 {
+        if b & 1 == 1 {
+            res = res.wrapping_mul(a);
+        }
+        a = a.wrapping_mul(a);
+        b >>= 1;
+    }
+    res
+}
+
+// multiplies a by itself b times, or none if any of the products overflows
+fn checked_pow(a: i64, b: i64) -> Option<i64> {
+    match (a, u32::try_from(b)) {
+        (_, _) if b <= 0 => Some(1),
+        // the magnitude of the products never decreases, so only the last one can overflow first
+        (_, Ok(b)) => a.checked_pow(b),
+        (0 | 1, Err(_)) => Some(a),
+        (-1, Err(_)) => Some(if b % 2 == 0 { 1 } else { -1 }),
+        _ => None,
+    }
+}
\ No newline at end of file
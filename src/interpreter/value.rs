@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use crate::diagnostics::errors::{Location, RuntimeError};
+use crate::MAX_ARRAY_LENGTH;
+
+// runtime values of the interpreter, arrays are shared and mutable like the heap arrays of the compiled code
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    String(Rc<str>),
+    Unit,
+    Array(Rc<RefCell<Vec<Value>>>),
+    Fun(usize), // index of a top-level function
+    Closure(Rc<Closure>),
+}
+
+// nested function with the values of the variables it captures
+#[derive(Debug)]
+pub struct Closure {
+    pub fun: usize,
+    pub env: Vec<Value>,
+}
+
+impl Value {
+    pub fn new_array(size: i64, init: Value, pos: Location) -> Result<Self, RuntimeError> {
+        if !(0..=MAX_ARRAY_LENGTH).contains(&size) {
+            return Err(RuntimeError::invalid_array_size(pos, size));
+        }
+        let mut elems = Vec::new();
+        elems
+            .try_reserve_exact(size as usize)
+            .map_err(|_| RuntimeError::out_of_memory(pos))?;
+        elems.resize(size as usize, init);
+        Ok(Value::Array(Rc::new(RefCell::new(elems))))
+    }
+
+    pub fn as_int(&self) -> i64 {
+        match self {
+            Value::Int(n) => *n,
+            _ => panic!("value {:?} is not an integer", self),
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            _ => panic!("value {:?} is not a boolean", self),
+        }
+    }
+
+    pub fn as_array(&self) -> &Rc<RefCell<Vec<Value>>> {
+        match self {
+            Value::Array(arr) => arr,
+            _ => panic!("value {:?} is not an array", self),
+        }
+    }
+
+    // function values and arrays are compared by reference
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Fun(a), Value::Fun(b)) => a == b,
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+// same format as the print functions of the runtime library
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(str) => write!(f, "{}", str),
+            Value::Unit => write!(f, "unit"),
+            Value::Array(arr) => {
+                write!(f, "[")?;
+                for (i, elem) in arr.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, "]")
+            }
+            Value::Fun(_) | Value::Closure(_) => write!(f, "<function>"),
+        }
+    }
+}
\ No newline at end of file
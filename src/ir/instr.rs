@@ -0,0 +1,240 @@
+use crate::syntax::ast::{FunType, Id, Op, Span, Spanned, Type};
+
+pub type TempId = usize;
+pub type LocalId = usize;
+pub type GlobalId = usize;
+pub type FunId = usize;
+pub type BlockId = usize;
+
+// values read by the instructions, constants or temporaries defined by other instructions
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Temp(TempId),
+    Int(i64),
+    Bool(bool),
+    String(String),
+    Unit,
+    Fun(FunId), // top-level function used as a value
+    Undef(Type), // value of unreachable code
+}
+
+// every temporary is defined by exactly one instruction, while variables live in stack slots,
+// so the instructions that may fail at runtime are located by the span of their source expression
+#[derive(Debug, Clone)]
+pub enum Instr {
+    Param { dst: TempId, index: usize },
+    Load { dst: TempId, local: LocalId },
+    // owned arrays were already counted by the runtime, like the arguments of the function
+    Store { local: LocalId, value: Operand, owned: bool },
+    LoadGlobal { dst: TempId, global: GlobalId },
+    StoreGlobal { global: GlobalId, value: Operand },
+    // arithmetic and comparisons, logical operators are lowered to branches
+    BinOp { dst: TempId, op: Op, lhs: Operand, rhs: Operand },
+    Not { dst: TempId, value: Operand },
+    Call { dst: TempId, callee: Operand, args: Vec<Operand> },
+    NewArray { dst: TempId, elem_ty: Type, size: Operand, init: Operand },
+    // stops the program if the index is out of the bounds of the array, when bounds are checked
+    CheckIndex { array: Operand, index: Operand },
+    Index { dst: TempId, array: Operand, index: Operand },
+    SetIndex { array: Operand, index: Operand, value: Operand },
+    Length { dst: TempId, array: Operand },
+    Print { value: Operand },
+    // value of the operand of the predecessor block the control came from
+    Phi { dst: TempId, incoming: Vec<(Operand, BlockId)> },
+    // keeps the array or closure alive until the function returns
+    Root { value: Operand, owned: bool },
+    // gives a reference to the array or closure to the callee, which releases it
+    Retain { value: Operand },
+    // releases an owned array or closure that is not stored anywhere
+    Release { value: Operand },
+    // heap allocated closure of a nested function, holding the values of the variables it captures
+    Closure { dst: TempId, fun: FunId, captures: Vec<Operand> },
+    // value of a captured variable, read from the closure of the nested function
+    Capture { dst: TempId, index: usize },
+    // closure of the nested function being called
+    Env { dst: TempId },
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch { cond: Operand, then: BlockId, els: BlockId },
+    Return(Operand),
+    // call to another function that reuses the stack frame, returning its value
+    TailCall { callee: Operand, args: Vec<Operand> },
+    Exit(Operand),
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub label: &'static str,
+    pub instrs: Vec<Spanned<Instr>>,
+    pub term: Option<Spanned<Terminator>>, // none only while the block is being built
+}
+
+// stack slot of a parameter or variable
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub name: Id,
+    pub ty: Type,
+    pub span: Span,
+    pub param: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Id,
+    pub ty: FunType,
+    pub span: Span,
+    pub locals: Vec<Local>,
+    pub temps: Vec<Type>,
+    pub blocks: Vec<Block>, // starting with the entry block
+    pub captures: Vec<Type>, // types of the variables captured by a nested function
+}
+
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: Id,
+    pub ty: Type,
+    pub init: Option<Operand>, // initial value known at compile time
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+    pub init: FunId, // initializes the globals before main
+    pub main: FunId,
+}
+
+impl Operand {
+    // type of the operands that do not depend on the program
+    pub fn constant_type(&self) -> Type {
+        match self {
+            Operand::Int(_) => Type::Int,
+            Operand::Bool(_) => Type::Bool,
+            Operand::String(_) => Type::String,
+            Operand::Unit => Type::Unit,
+            Operand::Undef(ty) => ty.clone(),
+            Operand::Temp(_) | Operand::Fun(_) => panic!("operand {:?} is not a constant", self),
+        }
+    }
+}
+
+impl Instr {
+    // temporary defined by the instruction
+    pub fn dst(&self) -> Option<TempId> {
+        match self {
+            Instr::Param { dst, .. }
+            | Instr::Load { dst, .. }
+            | Instr::LoadGlobal { dst, .. }
+            | Instr::BinOp { dst, .. }
+            | Instr::Not { dst, .. }
+            | Instr::Call { dst, .. }
+            | Instr::NewArray { dst, .. }
+            | Instr::Index { dst, .. }
+            | Instr::Length { dst, .. }
+            | Instr::Phi { dst, .. }
+            | Instr::Closure { dst, .. }
+            | Instr::Capture { dst, .. }
+            | Instr::Env { dst } => Some(*dst),
+            Instr::Store { .. }
+            | Instr::StoreGlobal { .. }
+            | Instr::CheckIndex { .. }
+            | Instr::SetIndex { .. }
+            | Instr::Print { .. }
+            | Instr::Root { .. }
+            | Instr::Retain { .. }
+            | Instr::Release { .. } => None,
+        }
+    }
+
+    // operands read by the instruction, in evaluation order
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Instr::Param { .. }
+            | Instr::Load { .. }
+            | Instr::LoadGlobal { .. }
+            | Instr::Capture { .. }
+            | Instr::Env { .. } => vec![],
+            Instr::Store { value, .. }
+            | Instr::StoreGlobal { value, .. }
+            | Instr::Not { value, .. }
+            | Instr::Print { value }
+            | Instr::Root { value, .. }
+            | Instr::Retain { value }
+            | Instr::Release { value } => vec![value],
+            Instr::BinOp { lhs, rhs, .. } => vec![lhs, rhs],
+            Instr::Call { callee, args, .. } => std::iter::once(callee).chain(args).collect(),
+            Instr::NewArray { size, init, .. } => vec![size, init],
+            Instr::CheckIndex { array, index } | Instr::Index { array, index, .. } => vec![array, index],
+            Instr::SetIndex { array, index, value } => vec![array, index, value],
+            Instr::Length { array, .. } => vec![array],
+            Instr::Phi { incoming, .. } => incoming.iter().map(|(value, _)| value).collect(),
+            Instr::Closure { captures, .. } => captures.iter().collect(),
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(to) => vec![*to],
+            Terminator::Branch { then, els, .. } => vec![*then, *els],
+            Terminator::Return(_) | Terminator::TailCall { .. } | Terminator::Exit(_) => vec![],
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Jump(_) => vec![],
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(value) | Terminator::Exit(value) => vec![value],
+            Terminator::TailCall { callee, args } => std::iter::once(callee).chain(args).collect(),
+        }
+    }
+}
+
+impl Function {
+    pub fn operand_type(&self, operand: &Operand, prog: &Program) -> Type {
+        match operand {
+            Operand::Temp(temp) => self.temps[*temp].clone(),
+            Operand::Fun(fun) => Type::Fun(prog.functions[*fun].ty.clone()),
+            operand => operand.constant_type(),
+        }
+    }
+
+    // predecessors of every block
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.term.iter().flat_map(|term| term.value.successors()) {
+                preds[succ].push(id);
+            }
+        }
+        preds
+    }
+
+    // blocks reachable from the entry, each one after the blocks that dominate it
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut order = vec![];
+        let mut visited = vec![false; self.blocks.len()];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            let succs = self.blocks[block].term.iter().flat_map(|term| term.value.successors()).collect::<Vec<_>>();
+            match succs.get(next) {
+                Some(&succ) => {
+                    stack.push((block, next + 1));
+                    if !visited[succ] {
+                        visited[succ] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+        order.reverse();
+        order
+    }
+}
\ No newline at end of file
@@ -0,0 +1,608 @@
+use std::collections::{HashMap, HashSet};
+use crate::ir::instr::*;
+use crate::semantic::captures::captured_vars;
+use crate::semantic::symbol_table::SymbolTable;
+use crate::semantic::tail_calls::tail_calls;
+use crate::syntax::ast::{self, Decl, Expr, FunType, Id, Lhs, Op, Span, Spanned, Type};
+
+// what an identifier refers to in the scope being lowered
+#[derive(Debug, Clone)]
+enum Binding {
+    Local(LocalId),
+    Global(GlobalId),
+    Fun(FunId),
+    Capture(usize), // variable captured by the nested function being lowered
+    Env, // nested function being lowered, referring to itself
+}
+
+// state of the function being lowered
+struct FunState {
+    id: FunId,
+    locals: Vec<Local>,
+    temps: Vec<Type>,
+    blocks: Vec<Block>,
+    block: BlockId, // where the instructions are emitted
+    params: Vec<Option<LocalId>>, // slots of the parameters, none for wildcards
+    start: BlockId, // target of the tail calls of the function to itself
+    tail_calls: HashSet<Span>,
+    captures: Vec<Type>,
+    env: Option<Operand>, // closure of a nested function
+}
+
+impl FunState {
+    fn new(id: FunId, tail_calls: HashSet<Span>) -> Self {
+        FunState {
+            id,
+            locals: vec![],
+            temps: vec![],
+            blocks: vec![],
+            block: 0,
+            params: vec![],
+            start: 0,
+            tail_calls,
+            captures: vec![],
+            env: None,
+        }
+    }
+}
+
+// lowers the checked program to the control-flow graph of every function, resolving the scopes,
+// so that names become stack slots, globals and functions, and expressions become temporaries
+pub struct Lowering {
+    symbols: SymbolTable<Binding>,
+    globals: Vec<Global>,
+    fun_types: Vec<FunType>,
+    fun: FunState,
+    captures: HashMap<Span, Vec<Id>>, // variables captured by the nested functions
+    nested: Vec<(FunId, Function)>,
+}
+
+pub fn lower(prog: &ast::Program) -> Program {
+    Lowering::new().lower(prog)
+}
+
+impl Lowering {
+
+    fn new() -> Self {
+        Lowering {
+            symbols: SymbolTable::new(HashMap::new()),
+            globals: vec![],
+            fun_types: vec![],
+            fun: FunState::new(0, HashSet::new()),
+            captures: HashMap::new(),
+            nested: vec![],
+        }
+    }
+
+    fn lower(mut self, prog: &ast::Program) -> Program {
+        self.captures = captured_vars(prog)
+            .into_iter()
+            .map(|(span, vars)| (span, vars.into_iter().cloned().collect()))
+            .collect();
+
+        // declare all functions first to allow for mutually recursive function calls
+        for decl in &prog.decls {
+            if let Decl::Fun { id, ty, .. } = &decl.value {
+                self.symbols.declare(&id.value, &Binding::Fun(self.fun_types.len()));
+                self.fun_types.push(ty.value.clone());
+            }
+        }
+
+        // globals are initialized in declaration order, by a function that is not visible to the program
+        let init = self.fun_types.len();
+        let init_ty = FunType { params: vec![], ret: Box::new(Type::Unit) };
+        self.fun_types.push(init_ty.clone());
+        self.fun = FunState::new(init, HashSet::new());
+        self.fun.block = self.new_block("entry");
+        for decl in &prog.decls {
+            if let Decl::Var { id, ty, expr } = &decl.value {
+                self.lower_global(id, ty, expr);
+            }
+        }
+        self.terminate(Terminator::Return(Operand::Unit), &(0..0));
+        let init_fun = self.finish_function("__init_globals__", init_ty, &(0..0));
+
+        let mut functions = vec![];
+        let mut main = 0;
+        for decl in &prog.decls {
+            if let Decl::Fun { id, params, ty, expr } = &decl.value {
+                if id.value == "main" {
+                    main = functions.len();
+                }
+                functions.push(self.lower_fun(&id.value, functions.len(), params, &ty.value, expr, &decl.span));
+            }
+        }
+        functions.push(init_fun);
+
+        // nested functions come after the initializer, in the order they were found
+        self.nested.sort_by_key(|(id, _)| *id);
+        functions.extend(self.nested.drain(..).map(|(_, fun)| fun));
+
+        Program { globals: self.globals, functions, init, main }
+    }
+
+    fn lower_global(&mut self, id: &Spanned<Id>, ty: &Spanned<Type>, expr: &Spanned<Expr>) {
+        self.symbols.enter_scope();
+        let val = self.lower_expr(expr);
+        self.symbols.exit_scope();
+        if id.value == "_" {
+            // evaluated only for its side effects
+            return;
+        }
+        let global = self.globals.len();
+        // constants are the initial values of the globals, the other values are stored by the initializer
+        let init = match val {
+            Operand::Temp(_) | Operand::Fun(_) => {
+                self.emit(Instr::StoreGlobal { global, value: val }, &expr.span);
+                None
+            }
+            val => Some(val),
+        };
+        self.globals.push(Global { name: id.value.clone(), ty: ty.value.clone(), init });
+        self.symbols.declare(&id.value, &Binding::Global(global));
+    }
+
+    fn lower_fun(
+        &mut self,
+        id: &Id,
+        fun_id: FunId,
+        params: &[Spanned<Id>],
+        fun_ty: &FunType,
+        body: &Spanned<Expr>,
+        span: &Span,
+    ) -> Function {
+        self.fun = FunState::new(fun_id, tail_calls(id, body));
+        self.fun.block = self.new_block("entry");
+        self.symbols.enter_scope();
+        self.lower_params(params, fun_ty);
+        self.lower_body(id, fun_ty, body, span)
+    }
+
+    // nested functions take their closure, which holds the captured variables and is owned by the callee,
+    // returns the id of the function, lowered while the state of the enclosing function is saved
+    fn lower_nested_fun(
+        &mut self,
+        id: &Id,
+        params: &[Spanned<Id>],
+        fun_ty: &FunType,
+        body: &Spanned<Expr>,
+        span: &Span,
+        captures: &[(Id, Type)],
+    ) -> FunId {
+        let fun_id = self.fun_types.len();
+        self.fun_types.push(fun_ty.clone());
+        let outer = std::mem::replace(&mut self.fun, FunState::new(fun_id, tail_calls(id, body)));
+        self.fun.block = self.new_block("entry");
+        self.fun.captures = captures.iter().map(|(_, ty)| ty.clone()).collect();
+        let env_ty = Type::Fun(fun_ty.clone());
+        let env = self.emit_value(|dst| Instr::Env { dst }, env_ty.clone(), span);
+        self.root(env.clone(), &env_ty, true, span);
+        self.fun.env = Some(env);
+
+        // the captured variables are shadowed by the function, which is shadowed by its params
+        self.symbols.enter_scope();
+        for (index, (var, _)) in captures.iter().enumerate() {
+            self.symbols.declare(var, &Binding::Capture(index));
+        }
+        if id != "_" {
+            self.symbols.declare(id, &Binding::Env);
+        }
+        self.lower_params(params, fun_ty);
+        let fun = self.lower_body(id, fun_ty, body, span);
+        self.nested.push((fun_id, fun));
+        self.fun = outer;
+        fun_id
+    }
+
+    fn lower_params(&mut self, params: &[Spanned<Id>], fun_ty: &FunType) {
+        // parameters are stored in stack slots, array and closure arguments are owned by the callee
+        for (index, (param, param_ty)) in params.iter().zip(&fun_ty.params).enumerate() {
+            let val = self.emit_value(|dst| Instr::Param { dst, index }, param_ty.clone(), &param.span);
+            if param.value == "_" {
+                // skip wildcard parameters
+                self.release(val, param_ty, &param.span);
+                self.fun.params.push(None);
+                continue;
+            }
+            let local = self.new_local(&param.value, param_ty, &param.span, Some(index));
+            self.emit(Instr::Store { local, value: val, owned: true }, &param.span);
+            self.symbols.declare(&param.value, &Binding::Local(local));
+            self.fun.params.push(Some(local));
+        }
+    }
+
+    fn lower_body(&mut self, id: &Id, fun_ty: &FunType, body: &Spanned<Expr>, span: &Span) -> Function {
+        // tail calls of the function to itself jump back here with the new arguments
+        let start = self.new_block("start");
+        self.terminate(Terminator::Jump(start), span);
+        self.fun.block = start;
+        self.fun.start = start;
+
+        let ret_val = self.lower_expr(body);
+        self.symbols.exit_scope();
+        self.terminate(Terminator::Return(ret_val), &body.span);
+        self.finish_function(id, fun_ty.clone(), span)
+    }
+
+    fn finish_function(&mut self, name: &str, ty: FunType, span: &Span) -> Function {
+        let fun = std::mem::replace(&mut self.fun, FunState::new(0, HashSet::new()));
+        Function {
+            name: name.to_string(),
+            ty,
+            span: span.clone(),
+            locals: fun.locals,
+            temps: fun.temps,
+            blocks: fun.blocks,
+            captures: fun.captures,
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &Spanned<Expr>) -> Operand {
+        let span = &expr.span;
+        match &expr.value {
+            Expr::Chain { lhs, rhs } => {
+                self.lower_expr(lhs);
+                let rhs = self.lower_expr(rhs);
+                if matches!(lhs.value, Expr::Let { .. } | Expr::LetFun { .. }) {
+                    self.symbols.exit_scope();
+                }
+                rhs
+            }
+            Expr::Let { id, ty, expr } => {
+                let val = self.lower_expr(expr);
+                self.symbols.enter_scope();
+                // scope is exited in chain expression
+
+                // only allocate a slot if not a wildcard
+                if id.value != "_" {
+                    let local = self.new_local(&id.value, &ty.value, &id.span, None);
+                    self.emit(Instr::Store { local, value: val, owned: false }, span);
+                    self.symbols.declare(&id.value, &Binding::Local(local));
+                }
+                Operand::Unit
+            }
+            Expr::LetFun { id, params, ty, expr: body } => {
+                let captures = self.captures.get(&id.span).cloned().unwrap_or_default();
+                let mut values = Vec::with_capacity(captures.len());
+                let mut types = Vec::with_capacity(captures.len());
+                for var in captures {
+                    let binding = self.lookup(&var);
+                    let value = self.load(binding, span);
+                    types.push((var, self.operand_type(&value)));
+                    values.push(value);
+                }
+
+                let fun_id = self.lower_nested_fun(&id.value, params, &ty.value, body, span, &types);
+                let fun_ty = Type::Fun(ty.value.clone());
+                let closure = self.emit_value(|dst| Instr::Closure { dst, fun: fun_id, captures: values }, fun_ty.clone(), span);
+                self.root(closure.clone(), &fun_ty, false, span);
+                self.symbols.enter_scope();
+                // scope is exited in chain expression
+                if id.value != "_" {
+                    let local = self.new_local(&id.value, &fun_ty, &id.span, None);
+                    self.emit(Instr::Store { local, value: closure, owned: false }, span);
+                    self.symbols.declare(&id.value, &Binding::Local(local));
+                }
+                Operand::Unit
+            }
+            Expr::Set { lhs, expr } => {
+                match &lhs.value {
+                    Lhs::Var { id } => {
+                        let binding = self.lookup(&id.value);
+                        let value = self.lower_expr(expr);
+                        match binding {
+                            Binding::Local(local) => self.emit(Instr::Store { local, value, owned: false }, span),
+                            Binding::Global(global) => self.emit(Instr::StoreGlobal { global, value }, span),
+                            Binding::Fun(_) | Binding::Env => panic!("function {} is not assignable", id.value),
+                            Binding::Capture(_) => panic!("captured variable {} is not assignable", id.value),
+                        }
+                    }
+                    Lhs::Index { lhs: arr, index } => {
+                        let (array, index) = self.lower_index(arr, index, &lhs.span);
+                        let value = self.lower_expr(expr);
+                        self.emit(Instr::SetIndex { array, index, value }, span);
+                    }
+                }
+                Operand::Unit
+            }
+            Expr::BinOp { lhs, op, rhs } => match op {
+                Op::And => self.lower_short_circuit(lhs, rhs, true, span),
+                Op::Or => self.lower_short_circuit(lhs, rhs, false, span),
+                _ => {
+                    let l = self.lower_expr(lhs);
+                    // the array compared by reference stays alive while the rhs is evaluated
+                    let l_ty = self.operand_type(&l);
+                    self.root(l.clone(), &l_ty, false, span);
+                    let r = self.lower_expr(rhs);
+                    let ty = match op {
+                        Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod | Op::Pow => Type::Int,
+                        _ => Type::Bool,
+                    };
+                    self.emit_value(|dst| Instr::BinOp { dst, op: op.clone(), lhs: l, rhs: r }, ty, span)
+                }
+            },
+            Expr::Not { expr } => {
+                let value = self.lower_expr(expr);
+                self.emit_value(|dst| Instr::Not { dst, value }, Type::Bool, span)
+            }
+            Expr::While { cond, expr } => {
+                let cond_block = self.new_block("cond");
+                let body_block = self.new_block("body");
+                let after_block = self.new_block("after");
+                self.terminate(Terminator::Jump(cond_block), span);
+
+                self.fun.block = cond_block;
+                let cond = self.lower_expr(cond);
+                self.terminate(Terminator::Branch { cond, then: body_block, els: after_block }, span);
+
+                self.fun.block = body_block;
+                self.lower_expr(expr);
+                self.terminate(Terminator::Jump(cond_block), span);
+
+                self.fun.block = after_block;
+                Operand::Unit
+            }
+            Expr::IfElse { cond, then, els } => {
+                let cond = self.lower_expr(cond);
+                let then_block = self.new_block("then");
+                let else_block = self.new_block("else");
+                let merge_block = self.new_block("merge");
+                self.terminate(Terminator::Branch { cond, then: then_block, els: else_block }, span);
+
+                self.fun.block = then_block;
+                let then_val = self.lower_expr(then);
+                let then_end = self.fun.block;
+                self.terminate(Terminator::Jump(merge_block), span);
+
+                self.fun.block = else_block;
+                let else_val = self.lower_expr(els);
+                let else_end = self.fun.block;
+                self.terminate(Terminator::Jump(merge_block), span);
+
+                self.fun.block = merge_block;
+                let ty = self.operand_type(&then_val);
+                let incoming = vec![(then_val, then_end), (else_val, else_end)];
+                self.emit_value(|dst| Instr::Phi { dst, incoming }, ty, span)
+            }
+            Expr::FunCall { id, args, .. } => self.lower_call(id, args, span),
+            Expr::Id(id) => {
+                let binding = self.lookup(&id.value);
+                self.load(binding, span)
+            }
+            Expr::Int(n) => Operand::Int(*n),
+            Expr::Bool(b) => Operand::Bool(*b),
+            Expr::Unit => Operand::Unit,
+            Expr::String(str) => Operand::String(str.clone()),
+            Expr::NewArray { ty, size, init } => {
+                let size = self.lower_expr(size);
+                let init = self.lower_expr(init);
+                self.root(init.clone(), &ty.value, false, span);
+                let arr_ty = Type::Array(Box::new(ty.value.clone()));
+                let elem_ty = ty.value.clone();
+                let arr = self.emit_value(|dst| Instr::NewArray { dst, elem_ty, size, init }, arr_ty.clone(), span);
+                self.root(arr.clone(), &arr_ty, false, span);
+                arr
+            }
+            Expr::ArrayIndex { lhs, index } => {
+                let (array, index) = self.lower_index(lhs, index, span);
+                let ty = self.elem_type(&array);
+                self.emit_value(|dst| Instr::Index { dst, array, index }, ty, span)
+            }
+        }
+    }
+
+    fn lower_call(&mut self, id: &Spanned<Id>, args: &[Spanned<Expr>], span: &Span) -> Operand {
+        match id.value.as_str() {
+            "print" => {
+                let value = self.lower_expr(&args[0]);
+                self.emit(Instr::Print { value }, span);
+                return Operand::Unit;
+            }
+            "length" => {
+                let array = self.lower_expr(&args[0]);
+                return self.emit_value(|dst| Instr::Length { dst, array }, Type::Int, span);
+            }
+            "exit" => {
+                let code = self.lower_expr(&args[0]);
+                self.terminate(Terminator::Exit(code), span);
+                // code after the exit is unreachable
+                self.fun.block = self.new_block("after_exit");
+                return Operand::Unit;
+            }
+            _ => {}
+        }
+        let binding = self.lookup(&id.value);
+        let fun_ty = match &binding {
+            Binding::Fun(fun) => self.fun_types[*fun].clone(),
+            Binding::Local(local) => fun_type(&self.fun.locals[*local].ty),
+            Binding::Global(global) => fun_type(&self.globals[*global].ty),
+            Binding::Capture(index) => fun_type(&self.fun.captures[*index]),
+            Binding::Env => self.fun_types[self.fun.id].clone(),
+        };
+        let mut arg_vals = Vec::with_capacity(args.len());
+        for (arg, param_ty) in args.iter().zip(&fun_ty.params) {
+            let val = self.lower_expr(arg);
+            if param_ty.is_reference() {
+                // the callee takes the reference to its array and closure arguments
+                self.emit(Instr::Retain { value: val.clone() }, span);
+            }
+            arg_vals.push(val);
+        }
+        // function values are loaded after the arguments are evaluated,
+        // while nested functions call themselves directly
+        let (callee, env) = match binding {
+            Binding::Fun(fun) => (Operand::Fun(fun), None),
+            Binding::Env => (Operand::Fun(self.fun.id), self.fun.env.clone()),
+            binding => {
+                let closure = self.load(binding, span);
+                (closure.clone(), Some(closure))
+            }
+        };
+        let is_tail_call = self.fun.tail_calls.contains(span);
+        // the callee also takes the reference to its closure, except when the function jumps back to its start
+        if let (Some(env), false) = (env, is_tail_call && callee == Operand::Fun(self.fun.id)) {
+            self.emit(Instr::Retain { value: env }, span);
+        }
+        if is_tail_call {
+            return self.lower_tail_call(callee, arg_vals, &fun_ty, span);
+        }
+        let ret_ty = *fun_ty.ret.clone();
+        let ret_val = self.emit_value(|dst| Instr::Call { dst, callee, args: arg_vals }, ret_ty.clone(), span);
+        // returned arrays are owned by the caller
+        self.root(ret_val.clone(), &ret_ty, true, span);
+        ret_val
+    }
+
+    // a call in tail position jumps back to the start of the function when it calls itself,
+    // otherwise it is a tail call, so neither of them grows the stack
+    fn lower_tail_call(&mut self, callee: Operand, args: Vec<Operand>, fun_ty: &FunType, span: &Span) -> Operand {
+        if callee == Operand::Fun(self.fun.id) {
+            let params = self.fun.params.clone();
+            for ((slot, value), param_ty) in params.into_iter().zip(args).zip(&fun_ty.params) {
+                match slot {
+                    Some(local) => self.emit(Instr::Store { local, value, owned: true }, span),
+                    None => self.release(value, param_ty, span),
+                }
+            }
+            self.terminate(Terminator::Jump(self.fun.start), span);
+        } else {
+            self.terminate(Terminator::TailCall { callee, args }, span);
+        }
+        // code after the tail call is unreachable
+        self.fun.block = self.new_block("after_tail");
+        Operand::Undef(*fun_ty.ret.clone())
+    }
+
+    // returns the indexed array, kept alive while the index is evaluated, and the checked index
+    fn lower_index(&mut self, lhs: &Spanned<Lhs>, index: &Spanned<Expr>, span: &Span) -> (Operand, Operand) {
+        let array = self.lower_lhs(lhs);
+        let arr_ty = self.operand_type(&array);
+        self.root(array.clone(), &arr_ty, false, span);
+        let index = self.lower_expr(index);
+        self.emit(Instr::CheckIndex { array: array.clone(), index: index.clone() }, span);
+        (array, index)
+    }
+
+    // value of the lhs, for the arrays that are indexed
+    fn lower_lhs(&mut self, lhs: &Spanned<Lhs>) -> Operand {
+        match &lhs.value {
+            Lhs::Var { id } => {
+                let binding = self.lookup(&id.value);
+                self.load(binding, &lhs.span)
+            }
+            Lhs::Index { lhs: arr, index } => {
+                let (array, index) = self.lower_index(arr, index, &lhs.span);
+                let ty = self.elem_type(&array);
+                self.emit_value(|dst| Instr::Index { dst, array, index }, ty, &lhs.span)
+            }
+        }
+    }
+
+    fn lower_short_circuit(&mut self, lhs: &Spanned<Expr>, rhs: &Spanned<Expr>, and_op: bool, span: &Span) -> Operand {
+        let lhs_val = self.lower_expr(lhs);
+        let lhs_end = self.fun.block;
+        let rhs_block = self.new_block("rhs");
+        let merge_block = self.new_block("merge");
+
+        // and => branch to rhs when lhs is true
+        // or => branch to rhs only when lhs is false
+        let (then, els) = if and_op { (rhs_block, merge_block) } else { (merge_block, rhs_block) };
+        self.terminate(Terminator::Branch { cond: lhs_val.clone(), then, els }, span);
+
+        self.fun.block = rhs_block;
+        let rhs_val = self.lower_expr(rhs);
+        let rhs_end = self.fun.block;
+        self.terminate(Terminator::Jump(merge_block), span);
+
+        self.fun.block = merge_block;
+        let incoming = vec![(lhs_val, lhs_end), (rhs_val, rhs_end)];
+        self.emit_value(|dst| Instr::Phi { dst, incoming }, Type::Bool, span)
+    }
+
+    // value of the variable or function
+    fn load(&mut self, binding: Binding, span: &Span) -> Operand {
+        match binding {
+            Binding::Local(local) => {
+                let ty = self.fun.locals[local].ty.clone();
+                self.emit_value(|dst| Instr::Load { dst, local }, ty, span)
+            }
+            Binding::Global(global) => {
+                let ty = self.globals[global].ty.clone();
+                self.emit_value(|dst| Instr::LoadGlobal { dst, global }, ty, span)
+            }
+            Binding::Capture(index) => {
+                let ty = self.fun.captures[index].clone();
+                self.emit_value(|dst| Instr::Capture { dst, index }, ty, span)
+            }
+            // top-level functions are used as static closures
+            Binding::Fun(fun) => Operand::Fun(fun),
+            Binding::Env => self.fun.env.clone().unwrap(),
+        }
+    }
+
+    fn root(&mut self, value: Operand, ty: &Type, owned: bool, span: &Span) {
+        if ty.is_reference() {
+            self.emit(Instr::Root { value, owned }, span);
+        }
+    }
+
+    fn release(&mut self, value: Operand, ty: &Type, span: &Span) {
+        if ty.is_reference() {
+            self.emit(Instr::Release { value }, span);
+        }
+    }
+
+    fn lookup(&self, id: &str) -> Binding {
+        self.symbols.lookup(id).unwrap_or_else(|| panic!("undefined identifier {}", id))
+    }
+
+    fn new_local(&mut self, name: &str, ty: &Type, span: &Span, param: Option<usize>) -> LocalId {
+        self.fun.locals.push(Local { name: name.to_string(), ty: ty.clone(), span: span.clone(), param });
+        self.fun.locals.len() - 1
+    }
+
+    fn new_block(&mut self, label: &'static str) -> BlockId {
+        self.fun.blocks.push(Block { label, instrs: vec![], term: None });
+        self.fun.blocks.len() - 1
+    }
+
+    fn emit(&mut self, instr: Instr, span: &Span) {
+        let block = &mut self.fun.blocks[self.fun.block];
+        block.instrs.push(Spanned { value: instr, span: span.clone() });
+    }
+
+    // emits the instruction that defines a new temporary of the type
+    fn emit_value(&mut self, instr: impl FnOnce(TempId) -> Instr, ty: Type, span: &Span) -> Operand {
+        let dst = self.fun.temps.len();
+        self.fun.temps.push(ty);
+        self.emit(instr(dst), span);
+        Operand::Temp(dst)
+    }
+
+    fn terminate(&mut self, term: Terminator, span: &Span) {
+        let block = &mut self.fun.blocks[self.fun.block];
+        block.term = Some(Spanned { value: term, span: span.clone() });
+    }
+
+    fn operand_type(&self, operand: &Operand) -> Type {
+        match operand {
+            Operand::Temp(temp) => self.fun.temps[*temp].clone(),
+            Operand::Fun(fun) => Type::Fun(self.fun_types[*fun].clone()),
+            operand => operand.constant_type(),
+        }
+    }
+
+    fn elem_type(&self, array: &Operand) -> Type {
+        match self.operand_type(array) {
+            Type::Array(elem_ty) => *elem_ty,
+            ty => panic!("type {:?} is not indexable", ty),
+        }
+    }
+}
+
+fn fun_type(ty: &Type) -> FunType {
+    match ty {
+        Type::Fun(fun_ty) => fun_ty.clone(),
+        ty => panic!("type {:?} is not callable", ty),
+    }
+}
\ No newline at end of file
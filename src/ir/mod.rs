@@ -0,0 +1,4 @@
+pub mod instr;
+pub mod lowering;
+pub mod printer;
+pub mod verifier;
\ No newline at end of file
@@ -0,0 +1,146 @@
+use std::fmt::Write;
+use crate::ir::instr::*;
+use crate::syntax::ast::Op;
+
+// textual form of the ir, where temporaries are t0, t1, ..., stack slots %name.n,
+// globals and functions @name, and blocks label.n
+impl Program {
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for global in &self.globals {
+            match &global.init {
+                Some(init) => writeln!(out, "global @{} : {} = {}", global.name, global.ty.to_text(), self.operand(init)),
+                None => writeln!(out, "global @{} : {}", global.name, global.ty.to_text()),
+            }.unwrap();
+        }
+        let functions = self.functions.iter().map(|fun| self.function(fun)).collect::<Vec<_>>();
+        if !self.globals.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&functions.join("\n"));
+        out
+    }
+
+    fn function(&self, fun: &Function) -> String {
+        let mut out = String::new();
+        let params = fun.ty.params.iter().enumerate().map(|(index, ty)| {
+            match fun.locals.iter().position(|local| local.param == Some(index)) {
+                Some(local) => format!("{} : {}", local_name(fun, local), ty.to_text()),
+                None => format!("_ : {}", ty.to_text()),
+            }
+        }).collect::<Vec<_>>();
+        let captures = fun.captures.iter().map(|ty| ty.to_text()).collect::<Vec<_>>();
+        let captures = if captures.is_empty() { String::new() } else { format!(" captures ({})", captures.join(", ")) };
+        writeln!(out, "fun @{}({}) -> {}{} {{", fun.name, params.join(", "), fun.ty.ret.to_text(), captures).unwrap();
+        for (id, local) in fun.locals.iter().enumerate().filter(|(_, local)| local.param.is_none()) {
+            writeln!(out, "  local {} : {}", local_name(fun, id), local.ty.to_text()).unwrap();
+        }
+        for (id, block) in fun.blocks.iter().enumerate() {
+            writeln!(out, "{}:", block_name(fun, id)).unwrap();
+            for instr in &block.instrs {
+                writeln!(out, "  {}", self.instr(fun, &instr.value)).unwrap();
+            }
+            match &block.term {
+                Some(term) => writeln!(out, "  {}", self.terminator(fun, &term.value)),
+                None => writeln!(out, "  <unterminated>"),
+            }.unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn instr(&self, fun: &Function, instr: &Instr) -> String {
+        let op = |operand: &Operand| self.operand(operand);
+        let text = match instr {
+            Instr::Param { index, .. } => format!("param {}", index),
+            Instr::Load { local, .. } => format!("load {}", local_name(fun, *local)),
+            Instr::Store { local, value, owned } => {
+                format!("store {}, {}{}", local_name(fun, *local), op(value), if *owned { " owned" } else { "" })
+            }
+            Instr::LoadGlobal { global, .. } => format!("load @{}", self.globals[*global].name),
+            Instr::StoreGlobal { global, value } => format!("store @{}, {}", self.globals[*global].name, op(value)),
+            Instr::BinOp { op: bin_op, lhs, rhs, .. } => format!("{} {}, {}", op_name(bin_op), op(lhs), op(rhs)),
+            Instr::Not { value, .. } => format!("not {}", op(value)),
+            Instr::Call { callee, args, .. } => format!("call {}", self.call(callee, args)),
+            Instr::NewArray { elem_ty, size, init, .. } => format!("new {}[{} | {}]", elem_ty.to_text(), op(size), op(init)),
+            Instr::CheckIndex { array, index } => format!("checkindex {}, {}", op(array), op(index)),
+            Instr::Index { array, index, .. } => format!("index {}, {}", op(array), op(index)),
+            Instr::SetIndex { array, index, value } => format!("setindex {}, {}, {}", op(array), op(index), op(value)),
+            Instr::Length { array, .. } => format!("length {}", op(array)),
+            Instr::Print { value } => format!("print {}", op(value)),
+            Instr::Phi { incoming, .. } => {
+                let incoming = incoming
+                    .iter()
+                    .map(|(value, block)| format!("[{}, {}]", op(value), block_name(fun, *block)))
+                    .collect::<Vec<_>>();
+                format!("phi {}", incoming.join(", "))
+            }
+            Instr::Root { value, owned } => format!("root {}{}", op(value), if *owned { " owned" } else { "" }),
+            Instr::Retain { value } => format!("retain {}", op(value)),
+            Instr::Release { value } => format!("release {}", op(value)),
+            Instr::Closure { fun, captures, .. } => format!("closure {}", self.call(&Operand::Fun(*fun), captures)),
+            Instr::Capture { index, .. } => format!("capture {}", index),
+            Instr::Env { .. } => "env".to_string(),
+        };
+        match instr.dst() {
+            Some(dst) => format!("t{} : {} = {}", dst, fun.temps[dst].to_text(), text),
+            None => text,
+        }
+    }
+
+    fn terminator(&self, fun: &Function, term: &Terminator) -> String {
+        match term {
+            Terminator::Jump(to) => format!("jump {}", block_name(fun, *to)),
+            Terminator::Branch { cond, then, els } => {
+                format!("branch {}, {}, {}", self.operand(cond), block_name(fun, *then), block_name(fun, *els))
+            }
+            Terminator::Return(value) => format!("return {}", self.operand(value)),
+            Terminator::TailCall { callee, args } => format!("tailcall {}", self.call(callee, args)),
+            Terminator::Exit(code) => format!("exit {}", self.operand(code)),
+        }
+    }
+
+    fn call(&self, callee: &Operand, args: &[Operand]) -> String {
+        let args = args.iter().map(|arg| self.operand(arg)).collect::<Vec<_>>();
+        format!("{}({})", self.operand(callee), args.join(", "))
+    }
+
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Temp(temp) => format!("t{}", temp),
+            Operand::Int(n) => n.to_string(),
+            Operand::Bool(b) => b.to_string(),
+            Operand::String(str) => format!("{:?}", str),
+            Operand::Unit => "unit".to_string(),
+            Operand::Fun(fun) => format!("@{}", self.functions[*fun].name),
+            Operand::Undef(ty) => format!("undef {}", ty.to_text()),
+        }
+    }
+}
+
+fn local_name(fun: &Function, local: LocalId) -> String {
+    format!("%{}.{}", fun.locals[local].name, local)
+}
+
+fn block_name(fun: &Function, block: BlockId) -> String {
+    format!("{}.{}", fun.blocks[block].label, block)
+}
+
+fn op_name(op: &Op) -> &'static str {
+    match op {
+        Op::Add => "add",
+        Op::Sub => "sub",
+        Op::Mul => "mul",
+        Op::Div => "div",
+        Op::Mod => "mod",
+        Op::Pow => "pow",
+        Op::And => "and",
+        Op::Or => "or",
+        Op::Eq => "eq",
+        Op::Neq => "neq",
+        Op::Lt => "lt",
+        Op::Leq => "leq",
+        Op::Gt => "gt",
+        Op::Geq => "geq",
+    }
+}
\ No newline at end of file
@@ -0,0 +1,320 @@
+use crate::ir::instr::*;
+use crate::syntax::ast::{Op, Type};
+
+// checks that the program is well formed: blocks end with a terminator and branch to existing blocks,
+// phis come first and have one value for each predecessor, temporaries are defined once and before
+// they are used in every path, and the operands have the types expected by the instructions
+pub fn verify(prog: &Program) -> Result<(), String> {
+    if prog.init >= prog.functions.len() {
+        return Err("invalid initializer function".to_string());
+    }
+    if prog.main >= prog.functions.len() {
+        return Err("invalid main function".to_string());
+    }
+    for fun in &prog.functions {
+        FunVerifier::new(prog, fun)
+            .and_then(|verifier| verifier.verify())
+            .map_err(|e| format!("function {}: {}", fun.name, e))?;
+    }
+    Ok(())
+}
+
+struct FunVerifier<'a> {
+    prog: &'a Program,
+    fun: &'a Function,
+    preds: Vec<Vec<BlockId>>,
+    idom: Vec<Option<BlockId>>, // immediate dominator of every reachable block
+    defs: Vec<Option<(BlockId, usize)>>, // block and position of the definition of every temporary
+}
+
+impl<'a> FunVerifier<'a> {
+
+    // checks the structure of the graph, which the rest of the checks rely on
+    fn new(prog: &'a Program, fun: &'a Function) -> Result<Self, String> {
+        if fun.blocks.is_empty() {
+            return Err("no entry block".to_string());
+        }
+        let mut defs = vec![None; fun.temps.len()];
+        for (id, block) in fun.blocks.iter().enumerate() {
+            let term = block.term.as_ref().ok_or_else(|| format!("block {} has no terminator", id))?;
+            if let Some(to) = term.value.successors().into_iter().find(|to| *to >= fun.blocks.len()) {
+                return Err(format!("block {} branches to undefined block {}", id, to));
+            }
+            for (pos, instr) in block.instrs.iter().enumerate() {
+                let Some(dst) = instr.value.dst() else {
+                    continue;
+                };
+                match defs.get_mut(dst) {
+                    None => return Err(format!("undeclared temporary t{} in block {}", dst, id)),
+                    Some(Some(_)) => return Err(format!("temporary t{} is defined more than once", dst)),
+                    Some(def) => *def = Some((id, pos)),
+                }
+            }
+        }
+        let preds = fun.predecessors();
+        let idom = dominators(fun, &preds);
+        Ok(FunVerifier { prog, fun, preds, idom, defs })
+    }
+
+    fn verify(&self) -> Result<(), String> {
+        for (id, block) in self.fun.blocks.iter().enumerate() {
+            let in_block = |e: String| format!("block {}: {}", id, e);
+            let mut phis = true;
+            for (pos, instr) in block.instrs.iter().enumerate() {
+                if let Instr::Phi { incoming, .. } = &instr.value {
+                    if !phis {
+                        return Err(in_block("phi after other instructions".to_string()));
+                    }
+                    self.check_phi(id, incoming).map_err(in_block)?;
+                } else {
+                    phis = false;
+                    for operand in instr.value.operands() {
+                        self.check_use(operand, id, pos).map_err(in_block)?;
+                    }
+                }
+                self.check_instr(&instr.value).map_err(in_block)?;
+            }
+            let term = &block.term.as_ref().unwrap().value;
+            for operand in term.operands() {
+                self.check_use(operand, id, block.instrs.len()).map_err(in_block)?;
+            }
+            self.check_terminator(term).map_err(in_block)?;
+        }
+        Ok(())
+    }
+
+    fn check_phi(&self, block: BlockId, incoming: &[(Operand, BlockId)]) -> Result<(), String> {
+        let mut from = incoming.iter().map(|(_, pred)| *pred).collect::<Vec<_>>();
+        let mut preds = self.preds[block].clone();
+        from.sort();
+        preds.sort();
+        if from != preds {
+            return Err(format!("phi has values from blocks {:?}, but the predecessors are {:?}", from, preds));
+        }
+        // the value is used at the end of the predecessor
+        for (value, pred) in incoming {
+            self.check_use(value, *pred, self.fun.blocks[*pred].instrs.len())?;
+        }
+        Ok(())
+    }
+
+    // the definition of a temporary must dominate its uses, which is trivially true in unreachable blocks
+    fn check_use(&self, operand: &Operand, block: BlockId, pos: usize) -> Result<(), String> {
+        self.operand_type(operand)?;
+        let Operand::Temp(temp) = operand else {
+            return Ok(());
+        };
+        let (def_block, def_pos) = self.defs[*temp].ok_or_else(|| format!("temporary t{} is never defined", temp))?;
+        if self.idom[block].is_none() {
+            return Ok(());
+        }
+        let dominates = if def_block == block { def_pos < pos } else { self.dominates(def_block, block) };
+        if !dominates {
+            return Err(format!("temporary t{} is used before its definition", temp));
+        }
+        Ok(())
+    }
+
+    fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom[b] {
+                Some(parent) if parent != b => b = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    fn check_instr(&self, instr: &Instr) -> Result<(), String> {
+        let dst_ty = instr.dst().map(|dst| &self.fun.temps[dst]);
+        let expect_dst = |ty: &Type| expect("result", dst_ty.unwrap(), ty);
+        match instr {
+            Instr::Param { index, .. } => {
+                let param_ty = self.fun.ty.params.get(*index).ok_or_else(|| format!("undefined parameter {}", index))?;
+                expect_dst(param_ty)
+            }
+            Instr::Load { local, .. } => expect_dst(&self.local(*local)?.ty),
+            Instr::Store { local, value, .. } => expect("stored value", &self.operand_type(value)?, &self.local(*local)?.ty),
+            Instr::LoadGlobal { global, .. } => expect_dst(&self.global(*global)?.ty),
+            Instr::StoreGlobal { global, value } => {
+                expect("stored value", &self.operand_type(value)?, &self.global(*global)?.ty)
+            }
+            Instr::BinOp { op, lhs, rhs, .. } => {
+                let (lhs_ty, rhs_ty) = (self.operand_type(lhs)?, self.operand_type(rhs)?);
+                match op {
+                    Op::And | Op::Or => Err(format!("operator {} must be lowered to branches", op.to_text())),
+                    Op::Eq | Op::Neq => {
+                        expect("rhs", &rhs_ty, &lhs_ty)?;
+                        expect_dst(&Type::Bool)
+                    }
+                    _ => {
+                        expect("lhs", &lhs_ty, &Type::Int)?;
+                        expect("rhs", &rhs_ty, &Type::Int)?;
+                        match op {
+                            Op::Lt | Op::Leq | Op::Gt | Op::Geq => expect_dst(&Type::Bool),
+                            _ => expect_dst(&Type::Int),
+                        }
+                    }
+                }
+            }
+            Instr::Not { value, .. } => {
+                expect("operand", &self.operand_type(value)?, &Type::Bool)?;
+                expect_dst(&Type::Bool)
+            }
+            Instr::Call { callee, args, .. } => {
+                let ret_ty = self.check_call(callee, args)?;
+                expect_dst(&ret_ty)
+            }
+            Instr::NewArray { elem_ty, size, init, .. } => {
+                expect("size", &self.operand_type(size)?, &Type::Int)?;
+                expect("initial value", &self.operand_type(init)?, elem_ty)?;
+                expect_dst(&Type::Array(Box::new(elem_ty.clone())))
+            }
+            Instr::CheckIndex { array, index } => self.elem_type(array, index).map(|_| ()),
+            Instr::Index { array, index, .. } => expect_dst(&self.elem_type(array, index)?),
+            Instr::SetIndex { array, index, value } => {
+                expect("stored value", &self.operand_type(value)?, &self.elem_type(array, index)?)
+            }
+            Instr::Length { array, .. } => {
+                self.array_type(array)?;
+                expect_dst(&Type::Int)
+            }
+            Instr::Print { value } => self.operand_type(value).map(|_| ()),
+            Instr::Phi { incoming, .. } => {
+                for (value, _) in incoming {
+                    expect_dst(&self.operand_type(value)?)?;
+                }
+                Ok(())
+            }
+            Instr::Root { value, .. } | Instr::Retain { value } | Instr::Release { value } => {
+                match self.operand_type(value)? {
+                    ty if ty.is_reference() => Ok(()),
+                    ty => Err(format!("expected an array or a function, found {}", ty.to_text())),
+                }
+            }
+            Instr::Closure { fun, captures, .. } => {
+                let nested = self.prog.functions.get(*fun).ok_or_else(|| format!("undefined function {}", fun))?;
+                if captures.len() != nested.captures.len() {
+                    return Err(format!("closure with {} values of a function with {} captures", captures.len(), nested.captures.len()));
+                }
+                for (value, ty) in captures.iter().zip(&nested.captures) {
+                    expect("captured value", &self.operand_type(value)?, ty)?;
+                }
+                expect_dst(&Type::Fun(nested.ty.clone()))
+            }
+            Instr::Capture { index, .. } => {
+                let ty = self.fun.captures.get(*index).ok_or_else(|| format!("undefined capture {}", index))?;
+                expect_dst(ty)
+            }
+            Instr::Env { .. } => expect_dst(&Type::Fun(self.fun.ty.clone())),
+        }
+    }
+
+    fn check_terminator(&self, term: &Terminator) -> Result<(), String> {
+        match term {
+            Terminator::Jump(_) => Ok(()),
+            Terminator::Branch { cond, .. } => expect("condition", &self.operand_type(cond)?, &Type::Bool),
+            Terminator::Return(value) => expect("returned value", &self.operand_type(value)?, &self.fun.ty.ret),
+            Terminator::TailCall { callee, args } => {
+                let ret_ty = self.check_call(callee, args)?;
+                expect("returned value", &ret_ty, &self.fun.ty.ret)
+            }
+            Terminator::Exit(code) => expect("exit code", &self.operand_type(code)?, &Type::Int),
+        }
+    }
+
+    // returns the type of the value returned by the call
+    fn check_call(&self, callee: &Operand, args: &[Operand]) -> Result<Type, String> {
+        let Type::Fun(fun_ty) = self.operand_type(callee)? else {
+            return Err("callee is not a function".to_string());
+        };
+        if args.len() != fun_ty.params.len() {
+            return Err(format!("call with {} arguments to a function with {} parameters", args.len(), fun_ty.params.len()));
+        }
+        for (arg, param_ty) in args.iter().zip(&fun_ty.params) {
+            expect("argument", &self.operand_type(arg)?, param_ty)?;
+        }
+        Ok(*fun_ty.ret)
+    }
+
+    fn elem_type(&self, array: &Operand, index: &Operand) -> Result<Type, String> {
+        expect("index", &self.operand_type(index)?, &Type::Int)?;
+        self.array_type(array)
+    }
+
+    // returns the type of the elements of the array
+    fn array_type(&self, array: &Operand) -> Result<Type, String> {
+        match self.operand_type(array)? {
+            Type::Array(elem_ty) => Ok(*elem_ty),
+            ty => Err(format!("expected an array, found {}", ty.to_text())),
+        }
+    }
+
+    fn operand_type(&self, operand: &Operand) -> Result<Type, String> {
+        match operand {
+            Operand::Temp(temp) if *temp >= self.fun.temps.len() => Err(format!("undeclared temporary t{}", temp)),
+            Operand::Fun(fun) if *fun >= self.prog.functions.len() => Err(format!("undefined function {}", fun)),
+            operand => Ok(self.fun.operand_type(operand, self.prog)),
+        }
+    }
+
+    fn local(&self, local: LocalId) -> Result<&Local, String> {
+        self.fun.locals.get(local).ok_or_else(|| format!("undefined local {}", local))
+    }
+
+    fn global(&self, global: GlobalId) -> Result<&Global, String> {
+        self.prog.globals.get(global).ok_or_else(|| format!("undefined global {}", global))
+    }
+}
+
+fn expect(what: &str, found: &Type, expected: &Type) -> Result<(), String> {
+    if found != expected {
+        return Err(format!("{} of type {}, expected {}", what, found.to_text(), expected.to_text()));
+    }
+    Ok(())
+}
+
+// immediate dominators of the blocks reachable from the entry, where the entry dominates itself,
+// computed by the iterative algorithm of cooper, harvey and kennedy
+fn dominators(fun: &Function, preds: &[Vec<BlockId>]) -> Vec<Option<BlockId>> {
+    let order = fun.reverse_postorder();
+    let mut rank = vec![usize::MAX; fun.blocks.len()];
+    for (i, block) in order.iter().enumerate() {
+        rank[*block] = i;
+    }
+
+    let mut idom = vec![None; fun.blocks.len()];
+    idom[0] = Some(0);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order[1..] {
+            let mut new_idom = None;
+            for &pred in preds[block].iter().filter(|pred| idom[**pred].is_some()) {
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(other) => intersect(&idom, &rank, pred, other),
+                });
+            }
+            if new_idom.is_some() && idom[block] != new_idom {
+                idom[block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+fn intersect(idom: &[Option<BlockId>], rank: &[usize], mut a: BlockId, mut b: BlockId) -> BlockId {
+    while a != b {
+        while rank[a] > rank[b] {
+            a = idom[a].unwrap();
+        }
+        while rank[b] > rank[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
\ No newline at end of file
@@ -1,42 +1,306 @@
 use std::process::{Command, Output};
+use clap::ValueEnum;
 use inkwell::context::Context;
+use crate::bytecode::compiler::BytecodeCompiler;
+use crate::bytecode::format;
+use crate::bytecode::instr::Bytecode;
+use crate::bytecode::vm::Vm;
+use crate::codegen::c_backend::{self, CCodeGen};
 use crate::codegen::codegen::CodeGen;
+use crate::codegen::jit;
+use crate::codegen::native::{build_executable, emit_to_memory, registered_targets, target_machine};
+use crate::codegen::optimizer::optimize;
+use crate::interpreter::interpreter::interpret;
 use crate::semantic::type_checker::TypeChecker;
 use crate::semantic::declaration_checker::DeclarationChecker;
-use crate::syntax::ast::Program;
-use crate::syntax::lexer::Lexer;
+use crate::semantic::tail_calls::TailCallChecker;
+use crate::passes::constant_folding::ConstantFolder;
+use crate::passes::let_scoping::LetScoping;
+use crate::passes::{Pass, PassManager};
+use crate::ir::lowering::lower;
+use crate::ir::verifier::verify;
+use crate::syntax::lexer::{tokens_to_text, Lexer};
 use crate::syntax::parser::Parser;
-use crate::diagnostics::errors::{CompileError, RuntimeError, SemanticError};
+use crate::syntax::ast::Program;
+use crate::diagnostics::errors::{BackendError, CompileError, RuntimeError, SemanticError};
 use crate::diagnostics::warnings::Warning;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use inkwell::targets::FileType;
 
 pub mod syntax;
 pub mod semantic;
 pub mod codegen;
+pub mod interpreter;
+pub mod bytecode;
+pub mod passes;
+pub mod ir;
 pub mod utils;
 pub mod cli;
 pub mod diagnostics;
 
+// largest array length accepted by every backend, so that the size of an array in bytes never overflows
+pub const MAX_ARRAY_LENGTH: i64 = 1 << 58;
+
+// stack size assumed by the compiled programs when the stack has no size limit
+pub const DEFAULT_STACK_SIZE: u64 = 8 << 20;
+
+// part of the stack left for the runtime functions and the frame of the innermost call,
+// which the compiled programs do not enter when checking for stack overflows
+pub const STACK_MARGIN: u64 = 256 << 10;
+
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub opt: OptLevel,
+    pub passes: Option<String>,
+    pub print_after: Option<String>, // name of the ast pass after which the program is printed
+    pub bounds_checks: bool,
+    pub overflow: Overflow,
+    pub gc: Gc,
+    pub backend: Backend,
+    pub max_recursion_depth: Option<u64>, // by default calls stop when the stack runs out, 0 for no limit
+    pub target: Option<String>,
+    pub emit: Emit,
+    pub output: Option<String>,
+    pub debug: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Emit {
+    /// Tokens produced by the lexer
+    Tokens,
+    /// Abstract syntax tree
+    Ast,
+    /// Abstract syntax tree annotated with types
+    TypedAst,
+    /// AGUDA intermediate representation, lowered to LLVM IR
+    Ir,
+    /// LLVM IR (.ll)
+    LlvmIr,
+    /// LLVM bitcode (.bc)
+    Bitcode,
+    /// Native assembly (.s)
+    Asm,
+    /// Native object file (.o)
+    Obj,
+    /// C source (.c), generated by the C backend
+    C,
+    /// Bytecode (.agb), run by the bytecode VM
+    Bytecode,
+    /// Native executable
+    Exe,
+}
+
+impl Emit {
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Emit::Bitcode | Emit::Obj | Emit::Bytecode | Emit::Exe)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Overflow {
+    /// Integer arithmetic wraps around on overflow
+    Wrap,
+    /// Integer overflow stops the program with a runtime error
+    Trap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Gc {
+    /// Arrays are never freed
+    None,
+    /// Reference counting, arrays are freed as soon as they become unreachable
+    Rc,
+    /// Mark-and-sweep collector with a shadow stack of roots
+    #[value(name = "marksweep")]
+    MarkSweep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Backend {
+    /// LLVM, which generates native code for any of its targets
+    Llvm,
+    /// C99 source, built with the C compiler given by $CC, or cc
+    C,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum OptLevel {
+    #[value(name = "0")]
+    O0,
+    #[value(name = "1")]
+    O1,
+    #[value(name = "2")]
+    O2,
+    #[value(name = "3")]
+    O3,
+    /// Optimize for size
+    #[value(name = "s")]
+    Os,
+    /// Optimize aggressively for size
+    #[value(name = "z")]
+    Oz,
+}
+
+impl std::fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self {
+            OptLevel::O0 => "0",
+            OptLevel::O1 => "1",
+            OptLevel::O2 => "2",
+            OptLevel::O3 => "3",
+            OptLevel::Os => "s",
+            OptLevel::Oz => "z",
+        };
+        write!(f, "-O{}", level)
+    }
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            opt: OptLevel::O0,
+            passes: None,
+            print_after: None,
+            bounds_checks: true,
+            overflow: Overflow::Wrap,
+            gc: Gc::None,
+            backend: Backend::Llvm,
+            max_recursion_depth: None,
+            target: None,
+            emit: Emit::Exe,
+            output: None,
+            debug: false,
+        }
+    }
+}
+
+impl CompileOptions {
+    // executable path given with -o, or the source path without extension
+    pub fn exe_path(&self, file: &str) -> PathBuf {
+        match &self.output {
+            Some(output) => PathBuf::from(output),
+            None => Path::new(file).with_extension(""),
+        }
+    }
+
+    // custom pipeline given with --passes, or the default pipeline for the optimization level
+    pub fn pass_pipeline(&self) -> Option<String> {
+        if let Some(passes) = &self.passes {
+            return Some(passes.clone());
+        }
+        match self.opt {
+            OptLevel::O0 => None,
+            OptLevel::O1 => Some("default<O1>".to_string()),
+            OptLevel::O2 => Some("default<O2>".to_string()),
+            OptLevel::O3 => Some("default<O3>".to_string()),
+            OptLevel::Os => Some("default<Os>".to_string()),
+            OptLevel::Oz => Some("default<Oz>".to_string()),
+        }
+    }
+}
+
+// returns the output of the compiler stage selected by the emit option,
+// except for executables, which are written to the executable path
 pub fn compile_aguda_program(
     src: &str,
     file: &str,
-    opt: u32,
-) -> Result<(Program, Vec<Warning>), Vec<CompileError>> {
+    options: &CompileOptions,
+) -> Result<(Vec<u8>, Vec<Warning>), Vec<CompileError>> {
 
     // lexing
     let tokens = Lexer::new(src)
         .tokenize()
         .map_err(|e| vec![CompileError::from(e)])?;
+    if options.emit == Emit::Tokens {
+        return Ok((tokens_to_text(src, &tokens).into_bytes(), Vec::new()));
+    }
 
     // parsing
     let ast = Parser::new(tokens)
         .parse()
         .map_err(|e| vec![CompileError::from(e)])?;
+    if options.emit == Emit::Ast {
+        return Ok((ast.to_text().into_bytes(), Vec::new()));
+    }
 
     // declaration and type checking
-    let (decl_errors, warnings) = DeclarationChecker::new().check(&ast);
-    let _ = TypeChecker::new()
-        .check(&ast)
+    let (type_checker, mut warnings) = check_program(&ast)?;
+    if options.emit == Emit::TypedAst {
+        return Ok((ast.to_typed_text(type_checker.types()).into_bytes(), warnings));
+    }
+
+    // ast optimization passes, including constant folding, which the backends rely on to never see operations on literals
+    let mut ast = ast;
+    warnings.extend(PassManager::for_options(options).run(&mut ast));
+
+    // bytecode generation, which does not go through llvm
+    if options.emit == Emit::Bytecode {
+        let output = compile_to_bytecode(&ast, src, file, options).map_err(|e| vec![CompileError::from(e)])?;
+        return Ok((output, warnings));
+    }
+
+    // c source generation, which does not go through llvm
+    if options.backend == Backend::C || options.emit == Emit::C {
+        let output = compile_to_c(&ast, src, file, options).map_err(|e| vec![CompileError::from(e)])?;
+        return Ok((output, warnings));
+    }
+
+    // lowering to the control-flow graph of every function, which llvm code generation consumes
+    let ir = lower(&ast);
+    verify(&ir).map_err(|e| vec![CompileError::from(BackendError::invalid_ir(e))])?;
+    if options.emit == Emit::Ir {
+        return Ok((ir.to_text().into_bytes(), warnings));
+    }
+
+    // code generation for the target, the host by default
+    let machine = target_machine(options.target.as_deref()).map_err(|e| vec![CompileError::from(e)])?;
+    let context = Context::create();
+    let mut codegen = CodeGen::new(file, src, options, &machine, &context);
+    codegen.gen_program(&ir);
+    let module = codegen.module();
+
+    // llvm optimization
+    if let Some(pipeline) = options.pass_pipeline() {
+        optimize(module, &pipeline, &machine).map_err(|e| vec![CompileError::from(e)])?;
+    }
+
+    // llvm and native code generation
+    let output = match options.emit {
+        Emit::LlvmIr => Ok(module.print_to_string().to_bytes().to_vec()),
+        Emit::Bitcode => Ok(module.write_bitcode_to_memory().as_slice().to_vec()),
+        Emit::Asm => emit_to_memory(module, &machine, FileType::Assembly),
+        Emit::Obj => emit_to_memory(module, &machine, FileType::Object),
+        Emit::Exe => build_executable(module, &machine, &options.exe_path(file)).map(|_| Vec::new()),
+        Emit::Tokens | Emit::Ast | Emit::TypedAst | Emit::Ir | Emit::C | Emit::Bytecode => unreachable!(),
+    };
+    let output = output.map_err(|e| vec![CompileError::from(e)])?;
+
+    Ok((output, warnings))
+}
+
+// returns the checked program, ready to be interpreted
+pub fn check_aguda_program(src: &str) -> Result<(Program, Vec<Warning>), Vec<CompileError>> {
+    let tokens = Lexer::new(src)
+        .tokenize()
+        .map_err(|e| vec![CompileError::from(e)])?;
+    let mut ast = Parser::new(tokens)
+        .parse()
+        .map_err(|e| vec![CompileError::from(e)])?;
+    let (_, mut warnings) = check_program(&ast)?;
+    // the interpreter relies on the lets being scoped, like the compiled backends
+    LetScoping.run(&mut ast);
+    // folding as with --overflow=trap leaves to the program every operation whose result depends on the mode,
+    // and warns about the same constant overflows and divisions by zero as the compiler
+    warnings.extend(ConstantFolder::new(Overflow::Trap).run(&mut ast));
+    Ok((ast, warnings))
+}
+
+// declaration and type checking, returning the type checker with the types of the expressions
+fn check_program(ast: &Program) -> Result<(TypeChecker, Vec<Warning>), Vec<CompileError>> {
+    let (decl_errors, mut warnings) = DeclarationChecker::new().check(ast);
+    let mut type_checker = TypeChecker::new();
+    let _ = type_checker
+        .check(ast)
         .map_err(|type_errors|
             SemanticError::from_both(decl_errors.clone(), type_errors.clone())
                 .into_iter()
@@ -47,41 +311,88 @@ pub fn compile_aguda_program(
     if !decl_errors.is_empty() {
         return Err(decl_errors.iter().map(|e| CompileError::from(e.to_owned())).collect::<Vec<_>>());
     }
+    warnings.extend(TailCallChecker::new().check(ast));
+    Ok((type_checker, warnings))
+}
 
-    // code generation
-    let context = Context::create();
-    let mut codegen = CodeGen::new(file, &context);
-    let ll_path = Path::new(file);
-    codegen.gen_program(&ast);
-    codegen.gen_ll(Box::from(ll_path));
-
-    // llvm optimization
-    if opt > 0 {
-        let ll_path = ll_path.with_extension("ll");
-        Command::new("opt")
-            .arg(format!("-O{}", opt))
-            .arg(&ll_path)
-            .arg("-o")
-            .arg(&ll_path)
-            .output()
-            .expect("failed to optimize LLVM IR");
+// returns the c source of the program, or builds it into an executable
+fn compile_to_c(ast: &Program, src: &str, file: &str, options: &CompileOptions) -> Result<Vec<u8>, BackendError> {
+    let unsupported = |option: &str| Err(BackendError::unsupported_option(format!("{} requires the llvm backend", option)));
+    if options.gc == Gc::Rc {
+        return unsupported("--gc rc");
+    }
+    if options.passes.is_some() {
+        return unsupported("--passes");
+    }
+    if options.target.is_some() {
+        return unsupported("--target");
+    }
+    let source = CCodeGen::new(file, src, options).gen_program(ast);
+    match options.emit {
+        Emit::C => Ok(source.into_bytes()),
+        Emit::Exe => c_backend::build_executable(&source, options, &options.exe_path(file)).map(|_| Vec::new()),
+        emit => unsupported(&format!("--emit {}", emit.to_possible_value().unwrap().get_name())),
     }
+}
 
-    Ok((ast, warnings))
+// returns the encoded bytecode of the program
+fn compile_to_bytecode(ast: &Program, src: &str, file: &str, options: &CompileOptions) -> Result<Vec<u8>, BackendError> {
+    if options.backend == Backend::C {
+        return Err(BackendError::unsupported_option("--emit bytecode cannot be used with the c backend".to_string()));
+    }
+    let bytecode = BytecodeCompiler::new(file, src).compile(ast);
+    Ok(format::encode(&bytecode))
 }
 
-pub fn run_aguda_program(path: &str) -> Result<Output, RuntimeError> {
-    // run the program with lli
-    let ll_path = Path::new(path).with_extension("ll");
-    let output = Command::new("lli")
-        .arg(&ll_path)
+pub fn run_aguda_program(exe_path: &Path) -> Result<Output, RuntimeError> {
+    // relative paths are resolved against the current directory, not PATH
+    let output = Command::new(Path::new(".").join(exe_path))
         .output()
-        .expect("failed to execute command");
+        .map_err(|e| RuntimeError::unknown(format!("failed to run {}: {}", exe_path.display(), e)))?;
+    program_result(output)
+}
+
+// programs may exit with any status, but runtime errors are reported on stderr
+fn program_result(output: Output) -> Result<Output, RuntimeError> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match output.status.code() {
+        Some(_) if stderr.trim().is_empty() => Ok(output),
+        None if stderr.trim().is_empty() => {
+            Err(RuntimeError::unknown(format!("program terminated by {}", output.status)))
+        }
+        _ => Err(RuntimeError::from_stderr(&stderr)),
+    }
+}
+
+pub fn supported_targets() -> Vec<(String, String)> {
+    // targets that can be given to --target
+    registered_targets()
+}
+
+// runs the program in the tree-walking interpreter, without any native toolchain,
+// returning its output and exit status
+pub fn interpret_aguda_program(ast: &Program, src: &str, options: &CompileOptions) -> Result<(Vec<u8>, i32), RuntimeError> {
+    interpret(ast, src, options)
+}
+
+// reads a program emitted with --emit bytecode
+pub fn load_bytecode(bytes: &[u8]) -> Result<Bytecode, BackendError> {
+    format::decode(bytes).map_err(BackendError::invalid_bytecode)
+}
+
+// runs the bytecode in the stack vm, returning its output and exit status
+pub fn run_bytecode(bytecode: &Bytecode, options: &CompileOptions) -> Result<(Vec<u8>, i32), RuntimeError> {
+    Vm::new(bytecode, options).run()
+}
 
-    if output.status.success() {
-        Ok(output)
+// runs the bitcode in the jit execution engine, without an external toolchain, in the compiler process,
+// returning its output and exit status
+pub fn jit_aguda_program(bitcode: &[u8]) -> Result<(Vec<u8>, i32), RuntimeError> {
+    let (stdout, stderr, status) = jit::execute(bitcode)?;
+    let stderr = String::from_utf8_lossy(&stderr);
+    if stderr.trim().is_empty() {
+        Ok((stdout, status))
     } else {
-        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
-        Err(RuntimeError { message })
+        Err(RuntimeError::from_stderr(&stderr))
     }
 }
\ No newline at end of file
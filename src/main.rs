@@ -1,26 +1,54 @@
+use std::fs;
+use std::io::{self, Write};
 use std::process::exit;
 use clap::Parser;
 use colored::Colorize;
 use aguda_rs::cli::Cli;
-use aguda_rs::{compile_aguda_program, run_aguda_program};
-use aguda_rs::diagnostics::errors::AgudaError;
+use aguda_rs::{check_aguda_program, compile_aguda_program, interpret_aguda_program, jit_aguda_program, load_bytecode, run_aguda_program, run_bytecode, supported_targets, CompileOptions, Emit, OptLevel};
+use aguda_rs::bytecode::instr::Bytecode;
+use aguda_rs::diagnostics::errors::{AgudaError, CompileError};
 use aguda_rs::diagnostics::formatting::{format_aguda_errors, format_warnings};
+use aguda_rs::diagnostics::warnings::Warning;
 use aguda_rs::utils::read_aguda_file;
 
 fn main() {
     let args = Cli::parse();
-    let src = read_aguda_file(&args.file)
-        .unwrap_or_else(|_| {
-            eprintln!("{}: {}", "Error".red().bold(), "Failed to read source file");
-            exit(1);
-        });
-    match run_aguda_compiler(args, &src) {
-        Ok(output) => println!("{}", output),
-        Err(err) => eprintln!("{}", err)
+    if args.print_targets {
+        println!("{}", "Registered Targets:".bold());
+        for (name, description) in supported_targets() {
+            println!("  {:<12} - {}", name, description);
+        }
+        return;
     }
+    // bytecode files are run without compiling their source again
+    let result = if args.file.ends_with(".agb") {
+        run_bytecode_file(&args)
+    } else {
+        let src = read_aguda_file(&args.file)
+            .unwrap_or_else(|_| {
+                eprintln!("{}: {}", "Error".red().bold(), "Failed to read source file");
+                exit(1);
+            });
+        run_aguda_compiler(args, &src)
+    };
+    let status = match result {
+        Ok((Some(output), status)) => {
+            println!("{}", output);
+            status
+        }
+        Ok((None, status)) => status,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    };
+    // exit with the status of the program that was run, or 1 if compiling or running it failed
+    let _ = io::stdout().flush();
+    exit(status);
 }
 
-fn run_aguda_compiler(args: Cli, src: &str) -> Result<String, String> {
+// returns the output to display and the exit status
+fn run_aguda_compiler(args: Cli, src: &str) -> Result<(Option<String>, i32), String> {
     let fmt_errors = |errors: Vec<AgudaError>| {
         if args.suppress_errors {
             String::new()
@@ -29,31 +57,106 @@ fn run_aguda_compiler(args: Cli, src: &str) -> Result<String, String> {
         }
     };
 
+    if args.interpret {
+        let (ast, warnings) = check_aguda_program(src)
+            .map_err(|errs| fmt_errors(errs.into_iter().map(AgudaError::from).collect()))?;
+        print_warnings(&args, warnings, src, false);
+        println!("{} {}", "Interpreting".green().bold(), args.file);
+        let (stdout, status) = interpret_aguda_program(&ast, src, &CompileOptions::from(&args))
+            .map_err(|e| fmt_errors(vec![AgudaError::from(e)]))?;
+        return Ok((Some(String::from_utf8_lossy(&stdout).trim().to_string()), status));
+    }
+
     // compilation
-    let opt_str = if args.opt > 0 { format!("with -O{}", args.opt) } else { String::new() };
-    println!("{} {} {}", "Compiling".green().bold(), args.file, opt_str);
+    let opt_str = match (&args.passes, args.opt) {
+        (Some(passes), _) => format!("with passes {}", passes),
+        (None, OptLevel::O0) => String::new(),
+        (None, opt) => format!("with {}", opt),
+    };
+    // keep stdout clean when the emitted output is written to it
+    let emit_to_stdout = args.disassemble || (!args.jit && args.emit != Emit::Exe && args.output.is_none());
+    if !emit_to_stdout {
+        println!("{} {} {}", "Compiling".green().bold(), args.file, opt_str);
+    }
 
-    let (ast, warnings) = compile_aguda_program(&src, &args.file, args.opt)
+    let options = CompileOptions::from(&args);
+    let (output, warnings) = compile_aguda_program(&src, &args.file, &options)
         .map_err(|errs| {
             let aguda_errs = errs.into_iter().map(AgudaError::from).collect();
             fmt_errors(aguda_errs)
         })?;
 
-    // warnings
-    if !args.suppress_warnings && !warnings.is_empty() {
-        println!("{}", format_warnings(warnings, args.max_warnings, args.suppress_hints, &args.file, &src));
+    print_warnings(&args, warnings, src, emit_to_stdout);
+
+    // bytecode run in the vm or disassembled
+    if args.vm || args.disassemble {
+        let bytecode = load_bytecode(&output).map_err(|e| fmt_errors(vec![AgudaError::from(CompileError::from(e))]))?;
+        return run_bytecode_program(&args, &bytecode);
     }
 
-    // ast output
-    if args.ast {
-        return Ok(ast.to_text());
+    // emitted output
+    if !args.jit && options.emit != Emit::Exe {
+        return match &args.output {
+            Some(path) => {
+                fs::write(path, &output).map_err(|e| format!("{}: {}", "Error".red().bold(), e))?;
+                Ok((Some(format!("{} {}", "Generated".green().bold(), path)), 0))
+            }
+            None if options.emit.is_binary() => {
+                io::stdout().write_all(&output).map_err(|e| format!("{}: {}", "Error".red().bold(), e))?;
+                Ok((None, 0))
+            }
+            None => Ok((Some(String::from_utf8_lossy(&output).to_string()), 0)),
+        };
     }
 
     // execution
-    println!("{} {}", "Running".green().bold(), args.file.replace(".agu", ".ll"));
-    let run_out = run_aguda_program(&args.file)
-        .map_err(|e| fmt_errors(vec![AgudaError::from(e)]))?;
+    let (stdout, status) = if args.jit {
+        println!("{} {}", "Running".green().bold(), args.file);
+        jit_aguda_program(&output)
+    } else {
+        let exe_path = options.exe_path(&args.file);
+        println!("{} {}", "Running".green().bold(), exe_path.display());
+        run_aguda_program(&exe_path).map(|output| (output.stdout, output.status.code().unwrap_or(1)))
+    }.map_err(|e| fmt_errors(vec![AgudaError::from(e)]))?;
+
+    Ok((Some(String::from_utf8_lossy(&stdout).trim().to_string()), status))
+}
 
-    let stdout = String::from_utf8_lossy(&run_out.stdout);
-    Ok(stdout.trim().to_string())
+fn run_bytecode_file(args: &Cli) -> Result<(Option<String>, i32), String> {
+    let bytes = fs::read(&args.file)
+        .map_err(|e| format!("{}: Failed to read bytecode file: {}", "Error".red().bold(), e))?;
+    let bytecode = load_bytecode(&bytes).map_err(|e| {
+        let errors = vec![AgudaError::from(CompileError::from(e))];
+        format_aguda_errors(errors, args.max_errors, args.suppress_hints, &args.file, "")
+    })?;
+    run_bytecode_program(args, &bytecode)
+}
+
+fn run_bytecode_program(args: &Cli, bytecode: &Bytecode) -> Result<(Option<String>, i32), String> {
+    if args.disassemble {
+        return Ok((Some(bytecode.disassemble().trim_end().to_string()), 0));
+    }
+    println!("{} {}", "Running".green().bold(), args.file);
+    // runtime errors are shown in the source stored in the bytecode
+    let (stdout, status) = run_bytecode(bytecode, &CompileOptions::from(args)).map_err(|e| {
+        if args.suppress_errors {
+            String::new()
+        } else {
+            let errors = vec![AgudaError::from(e)];
+            format_aguda_errors(errors, args.max_errors, args.suppress_hints, &bytecode.file, &bytecode.src)
+        }
+    })?;
+    Ok((Some(String::from_utf8_lossy(&stdout).trim().to_string()), status))
+}
+
+fn print_warnings(args: &Cli, warnings: Vec<Warning>, src: &str, to_stderr: bool) {
+    if args.suppress_warnings || warnings.is_empty() {
+        return;
+    }
+    let warnings = format_warnings(warnings, args.max_warnings, args.suppress_hints, &args.file, src);
+    if to_stderr {
+        eprintln!("{}", warnings);
+    } else {
+        println!("{}", warnings);
+    }
 }
@@ -0,0 +1,110 @@
+use crate::diagnostics::errors::{Location, RuntimeErrorKind};
+use crate::diagnostics::warnings::Warning;
+use crate::interpreter::interpreter::eval_int_op;
+use crate::interpreter::value::Value;
+use crate::passes::{scoped, subexprs_mut, take, Pass};
+use crate::syntax::ast::{Decl, Expr, Op, Program, Span, Spanned};
+use crate::Overflow;
+
+// evaluates the expressions with constant operands at compile time, so that the backends
+// never see operations on literals, such as the 0 - n of negative numbers
+pub struct ConstantFolder {
+    overflow: Overflow,
+    warnings: Vec<Warning>,
+}
+
+impl ConstantFolder {
+    pub fn new(overflow: Overflow) -> Self {
+        Self {
+            overflow,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn fold_expr(&mut self, expr: &mut Spanned<Expr>) {
+        // operands are folded first, so that nested constant expressions fold completely
+        for subexpr in subexprs_mut(expr) {
+            self.fold_expr(subexpr);
+        }
+        if let Some(folded) = self.simplify(expr) {
+            *expr = folded;
+        }
+    }
+
+    // returns the expression that replaces the one with folded operands, if it can be simplified
+    fn simplify(&mut self, expr: &mut Spanned<Expr>) -> Option<Spanned<Expr>> {
+        let span = expr.span.clone();
+        let constant = |value: Expr| Some(Spanned { value, span: span.clone() });
+        match &mut expr.value {
+            Expr::BinOp { lhs, op, rhs } => match (&*op, &lhs.value, &rhs.value) {
+                // the operand that is not a literal may have side effects, so it is kept
+                (Op::And, Expr::Bool(true), _) | (Op::Or, Expr::Bool(false), _) => Some(take(rhs)),
+                (Op::And, _, Expr::Bool(true)) | (Op::Or, _, Expr::Bool(false)) => Some(take(lhs)),
+                (Op::And, Expr::Bool(false), _) => constant(Expr::Bool(false)),
+                (Op::Or, Expr::Bool(true), _) => constant(Expr::Bool(true)),
+                (Op::Eq, Expr::Bool(l), Expr::Bool(r)) => constant(Expr::Bool(l == r)),
+                (Op::Neq, Expr::Bool(l), Expr::Bool(r)) => constant(Expr::Bool(l != r)),
+                (Op::Eq, Expr::Int(l), Expr::Int(r)) => constant(Expr::Bool(l == r)),
+                (Op::Neq, Expr::Int(l), Expr::Int(r)) => constant(Expr::Bool(l != r)),
+                (op, Expr::Int(l), Expr::Int(r)) => {
+                    let (l, r, op) = (*l, *r, op.clone());
+                    self.fold_int_op(l, &op, r, &span).and_then(constant)
+                }
+                _ => None,
+            },
+            Expr::Not { expr: inner } => match &mut inner.value {
+                Expr::Bool(b) => constant(Expr::Bool(!*b)),
+                Expr::Not { expr } => Some(take(expr)),
+                _ => None,
+            },
+            // the variable of a let in the branch must not become visible in the rest of the chain
+            Expr::IfElse { cond, then, els } => match cond.value {
+                Expr::Bool(true) => Some(scoped(take(then))),
+                Expr::Bool(false) => Some(scoped(take(els))),
+                _ => None,
+            },
+            Expr::While { cond, .. } if matches!(cond.value, Expr::Bool(false)) => constant(Expr::Unit),
+            _ => None,
+        }
+    }
+
+    // integer operation on literals, with the same result as at runtime,
+    // or none if it must be left for the program to report the error
+    fn fold_int_op(&mut self, l: i64, op: &Op, r: i64, span: &Span) -> Option<Expr> {
+        let res = match eval_int_op(l, op, r, Overflow::Trap, Location::default) {
+            Err(e) if e.kind == RuntimeErrorKind::IntegerOverflow { op: op.to_text() } => {
+                self.warnings.push(Warning::ConstantOverflow(Spanned { value: op.to_text(), span: span.clone() }));
+                match self.overflow {
+                    Overflow::Wrap => eval_int_op(l, op, r, Overflow::Wrap, Location::default).ok()?,
+                    Overflow::Trap => return None,
+                }
+            }
+            Err(_) => {
+                self.warnings.push(Warning::ConstantDivisionByZero(Spanned { value: op.to_text(), span: span.clone() }));
+                return None;
+            }
+            Ok(res) => res,
+        };
+        match res {
+            Value::Int(n) => Some(Expr::Int(n)),
+            Value::Bool(b) => Some(Expr::Bool(b)),
+            _ => None,
+        }
+    }
+}
+
+impl Pass for ConstantFolder {
+    fn name(&self) -> &'static str {
+        "constant-folding"
+    }
+
+    // warns about the operations that fail at runtime when evaluated
+    fn run(&mut self, prog: &mut Program) -> Vec<Warning> {
+        for decl in &mut prog.decls {
+            match &mut decl.value {
+                Decl::Var { expr, .. } | Decl::Fun { expr, .. } => self.fold_expr(expr),
+            }
+        }
+        std::mem::take(&mut self.warnings)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use crate::diagnostics::warnings::Warning;
+use crate::passes::{referenced_names, scoped, subexprs, subexprs_mut, take, Pass};
+use crate::syntax::ast::{Decl, Expr, Op, Program, Spanned};
+use crate::Overflow;
+
+// removes the variables that are never used, keeping the expressions
+// of their values when they may have side effects or fail at runtime
+pub struct DeadLetElimination {
+    overflow: Overflow,
+}
+
+impl DeadLetElimination {
+    pub fn new(overflow: Overflow) -> Self {
+        Self { overflow }
+    }
+
+    fn eliminate(&self, expr: &mut Spanned<Expr>) {
+        // inner variables are removed first, which may leave the outer ones unused
+        for subexpr in subexprs_mut(expr) {
+            self.eliminate(subexpr);
+        }
+        let span = expr.span.clone();
+        let Expr::Chain { lhs, rhs } = &mut expr.value else {
+            return;
+        };
+        let (Expr::Let { id, .. } | Expr::LetFun { id, .. }) = &lhs.value else {
+            return;
+        };
+        // the scope of the variable is the rest of the chain
+        let mut names = HashSet::new();
+        referenced_names(rhs, &mut names);
+        if names.contains(&id.value) {
+            return;
+        }
+        // a let kept as the value must not declare its variable in the rest of the chain
+        *expr = match &mut lhs.value {
+            Expr::Let { expr: val, .. } if !self.is_pure(val) => {
+                Spanned { value: Expr::Chain { lhs: Box::new(scoped(take(val))), rhs: Box::new(take(rhs)) }, span }
+            }
+            _ => take(rhs),
+        };
+    }
+
+    // whether evaluating the expression can be skipped, since it has no side effects and never fails
+    fn is_pure(&self, expr: &Spanned<Expr>) -> bool {
+        let pure_node = match &expr.value {
+            Expr::Id(_) | Expr::Int(_) | Expr::String(_) | Expr::Bool(_) | Expr::Unit => true,
+            Expr::Not { .. } | Expr::Chain { .. } | Expr::Let { .. } | Expr::IfElse { .. } => true,
+            // the body of a nested function only runs when it is called
+            Expr::LetFun { .. } => return true,
+            Expr::BinOp { op, rhs, .. } => match op {
+                Op::Add | Op::Sub | Op::Mul | Op::Pow => self.overflow == Overflow::Wrap,
+                Op::Div | Op::Mod => matches!(rhs.value, Expr::Int(n) if n != 0),
+                _ => true,
+            },
+            // calls, arrays and loops may fail, not terminate or change the state of the program
+            Expr::FunCall { .. } | Expr::NewArray { .. } | Expr::ArrayIndex { .. } | Expr::Set { .. } | Expr::While { .. } => false,
+        };
+        pure_node && subexprs(expr).into_iter().all(|subexpr| self.is_pure(subexpr))
+    }
+}
+
+impl Pass for DeadLetElimination {
+    fn name(&self) -> &'static str {
+        "dead-let-elimination"
+    }
+
+    fn run(&mut self, prog: &mut Program) -> Vec<Warning> {
+        for decl in &mut prog.decls {
+            match &mut decl.value {
+                Decl::Var { expr, .. } | Decl::Fun { expr, .. } => self.eliminate(expr),
+            }
+        }
+        Vec::new()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use crate::diagnostics::warnings::Warning;
+use crate::passes::{subexprs, subexprs_mut, take, Pass};
+use crate::semantic::RESERVED_IDENTIFIERS;
+use crate::syntax::ast::{Decl, Expr, Id, Lhs, Program, Span, Spanned, Type};
+
+// function whose calls can be replaced by its body
+struct Candidate {
+    params: Vec<Id>,
+    types: Vec<Type>,
+    body: Spanned<Expr>,
+}
+
+// replaces the calls to small functions that call no other functions, which are never recursive,
+// by their bodies, with the arguments bound to variables whose names cannot be written in the source
+pub struct Inliner {
+    threshold: usize, // maximum number of nodes of the body of an inlined function
+    candidates: HashMap<Id, Candidate>,
+    temps: usize,
+}
+
+impl Inliner {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            candidates: HashMap::new(),
+            temps: 0,
+        }
+    }
+
+    fn collect_candidates(&mut self, prog: &Program) {
+        for decl in &prog.decls {
+            if let Decl::Fun { id, params, ty, expr } = &decl.value {
+                if id.value != "main" && !calls_functions(expr) && size(expr) <= self.threshold {
+                    let candidate = Candidate {
+                        params: params.iter().map(|param| param.value.clone()).collect(),
+                        types: ty.value.params.clone(),
+                        body: expr.clone(),
+                    };
+                    self.candidates.insert(id.value.clone(), candidate);
+                }
+            }
+        }
+    }
+
+    // inlines the calls of the expression, where the scope holds the local variables in it
+    fn inline_expr(&mut self, expr: &mut Spanned<Expr>, scope: &mut Vec<Id>) {
+        if let Expr::Chain { lhs, rhs } = &mut expr.value {
+            if let Expr::Let { id, expr: val, .. } = &mut lhs.value {
+                self.inline_expr(val, scope);
+                scope.push(id.value.clone());
+                self.inline_expr(rhs, scope);
+                scope.pop();
+                return;
+            }
+            if let Expr::LetFun { id, .. } = &lhs.value {
+                let id = id.value.clone();
+                self.inline_expr(lhs, scope);
+                scope.push(id);
+                self.inline_expr(rhs, scope);
+                scope.pop();
+                return;
+            }
+        }
+        // the function and its parameters are local variables of the body of a nested function
+        if let Expr::LetFun { id, params, expr: body, .. } = &mut expr.value {
+            let len = scope.len();
+            scope.push(id.value.clone());
+            scope.extend(params.iter().map(|param| param.value.clone()));
+            self.inline_expr(body, scope);
+            scope.truncate(len);
+            return;
+        }
+        for subexpr in subexprs_mut(expr) {
+            self.inline_expr(subexpr, scope);
+        }
+        let span = expr.span.clone();
+        if let Expr::FunCall { id, args, .. } = &mut expr.value {
+            // local variables may shadow the function
+            if self.candidates.contains_key(&id.value) && !scope.contains(&id.value) {
+                if let Some(inlined) = self.inline_call(&id.value, args, span, scope) {
+                    *expr = inlined;
+                }
+            }
+        }
+    }
+
+    // body of the function bound to the arguments, unless it uses globals shadowed at the call
+    fn inline_call(&mut self, id: &Id, args: &mut [Spanned<Expr>], span: Span, scope: &[Id]) -> Option<Spanned<Expr>> {
+        let candidate = &self.candidates[id];
+        let mut body = candidate.body.clone();
+        let mut renamer = Renamer { fun: id, names: HashMap::new(), free: HashSet::new(), temps: &mut self.temps };
+        let params = candidate.params.iter().map(|param| renamer.declare(param)).collect::<Vec<_>>();
+        renamer.rename(&mut body);
+        if renamer.free.iter().any(|name| scope.contains(name)) {
+            return None;
+        }
+        let bindings = params.into_iter().zip(&candidate.types).zip(args.iter_mut());
+        let inlined = bindings.rev().fold(body, |inlined, ((param, ty), arg)| {
+            let binding = Expr::Let {
+                id: Spanned { value: param, span: span.clone() },
+                ty: Spanned { value: ty.clone(), span: span.clone() },
+                expr: Box::new(take(arg)),
+            };
+            let binding = Spanned { value: binding, span: span.clone() };
+            Spanned { value: Expr::Chain { lhs: Box::new(binding), rhs: Box::new(inlined) }, span: span.clone() }
+        });
+        Some(inlined)
+    }
+}
+
+impl Pass for Inliner {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    // calls are only inlined in functions, so that the initialization of globals stays simple
+    fn run(&mut self, prog: &mut Program) -> Vec<Warning> {
+        self.collect_candidates(prog);
+        for decl in &mut prog.decls {
+            if let Decl::Fun { params, expr, .. } = &mut decl.value {
+                let mut scope = params.iter().map(|param| param.value.clone()).collect();
+                self.inline_expr(expr, &mut scope);
+            }
+        }
+        Vec::new()
+    }
+}
+
+// gives new names to the parameters and variables of an inlined body, so that they never
+// clash with the variables of the caller, while collecting the names it uses from outside
+struct Renamer<'a> {
+    fun: &'a Id,
+    names: HashMap<Id, Id>,
+    free: HashSet<Id>,
+    temps: &'a mut usize,
+}
+
+impl Renamer<'_> {
+    fn fresh(&mut self, id: &Id) -> Id {
+        if id == "_" {
+            return id.clone();
+        }
+        let name = format!("{}.{}.{}", self.fun, id, self.temps);
+        *self.temps += 1;
+        name
+    }
+
+    fn declare(&mut self, id: &Id) -> Id {
+        let name = self.fresh(id);
+        if id != "_" {
+            self.names.insert(id.clone(), name.clone());
+        }
+        name
+    }
+
+    fn rename(&mut self, expr: &mut Spanned<Expr>) {
+        match &mut expr.value {
+            Expr::Chain { lhs, rhs } if matches!(lhs.value, Expr::Let { .. }) => {
+                let Expr::Let { id, expr: val, .. } = &mut lhs.value else {
+                    unreachable!()
+                };
+                self.rename(val);
+                // the variable is in scope in the rest of the chain, shadowing any outer one
+                let shadowed = self.names.get(&id.value).cloned();
+                let old_id = id.value.clone();
+                id.value = self.declare(&old_id);
+                self.rename(rhs);
+                match shadowed {
+                    Some(name) => self.names.insert(old_id, name),
+                    None => self.names.remove(&old_id),
+                };
+                return;
+            }
+            Expr::Let { id, expr: val, .. } => {
+                self.rename(val);
+                // not followed by any expression, so the variable is never used
+                id.value = self.fresh(&id.value.clone());
+                return;
+            }
+            Expr::Id(id) => self.rename_id(id),
+            Expr::FunCall { id, .. } => {
+                self.free.insert(id.value.clone());
+            }
+            Expr::Set { lhs, .. } | Expr::ArrayIndex { lhs, .. } => self.rename_id(base_var(lhs)),
+            _ => {}
+        }
+        for subexpr in subexprs_mut(expr) {
+            self.rename(subexpr);
+        }
+    }
+
+    fn rename_id(&mut self, id: &mut Spanned<Id>) {
+        match self.names.get(&id.value) {
+            Some(name) => id.value = name.clone(),
+            None => {
+                self.free.insert(id.value.clone());
+            }
+        }
+    }
+}
+
+// variable whose array elements are accessed by the lhs, or the variable itself
+fn base_var(lhs: &mut Spanned<Lhs>) -> &mut Spanned<Id> {
+    match &mut lhs.value {
+        Lhs::Var { id } => id,
+        Lhs::Index { lhs, .. } => base_var(lhs),
+    }
+}
+
+// whether the expression calls functions other than the builtins, or declares nested functions
+fn calls_functions(expr: &Spanned<Expr>) -> bool {
+    let calls = match &expr.value {
+        Expr::FunCall { id, .. } => !RESERVED_IDENTIFIERS.contains(&id.value),
+        Expr::LetFun { .. } => true,
+        _ => false,
+    };
+    calls || subexprs(expr).into_iter().any(calls_functions)
+}
+
+// number of nodes of the expression
+fn size(expr: &Spanned<Expr>) -> usize {
+    1 + subexprs(expr).into_iter().map(size).sum::<usize>()
+}
\ No newline at end of file
@@ -0,0 +1,45 @@
+use crate::diagnostics::warnings::Warning;
+use crate::passes::{scoped, subexprs_mut, take, Pass};
+use crate::syntax::ast::{Decl, Expr, Program, Spanned};
+
+// the variable of a let is only in scope in the rest of its chain, so the lets anywhere else,
+// such as in a branch or in the value of another let, get a chain of their own, which keeps
+// their variables out of the enclosing chain in every backend
+pub struct LetScoping;
+
+impl LetScoping {
+    fn scope_expr(&self, expr: &mut Spanned<Expr>) {
+        if let Expr::Chain { lhs, rhs } = &mut expr.value {
+            if matches!(lhs.value, Expr::Let { .. } | Expr::LetFun { .. }) {
+                for subexpr in subexprs_mut(lhs) {
+                    self.scope_expr(subexpr);
+                }
+            } else {
+                self.scope_expr(lhs);
+            }
+            self.scope_expr(rhs);
+            return;
+        }
+        for subexpr in subexprs_mut(expr) {
+            self.scope_expr(subexpr);
+        }
+        if matches!(expr.value, Expr::Let { .. } | Expr::LetFun { .. }) {
+            *expr = scoped(take(expr));
+        }
+    }
+}
+
+impl Pass for LetScoping {
+    fn name(&self) -> &'static str {
+        "let-scoping"
+    }
+
+    fn run(&mut self, prog: &mut Program) -> Vec<Warning> {
+        for decl in &mut prog.decls {
+            match &mut decl.value {
+                Decl::Var { expr, .. } | Decl::Fun { expr, .. } => self.scope_expr(expr),
+            }
+        }
+        Vec::new()
+    }
+}
\ No newline at end of file
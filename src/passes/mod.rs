@@ -0,0 +1,181 @@
+use crate::diagnostics::warnings::Warning;
+use crate::passes::constant_folding::ConstantFolder;
+use crate::passes::dead_lets::DeadLetElimination;
+use crate::passes::inlining::Inliner;
+use crate::passes::let_scoping::LetScoping;
+use crate::passes::pow_lowering::PowLowering;
+use crate::syntax::ast::{Expr, Id, Lhs, Program, Spanned};
+use std::collections::HashSet;
+use crate::{CompileOptions, OptLevel};
+
+pub mod constant_folding;
+pub mod dead_lets;
+pub mod inlining;
+pub mod let_scoping;
+pub mod pow_lowering;
+
+// names of the passes that can be given to --print-after
+pub const PASS_NAMES: [&str; 5] = ["let-scoping", "constant-folding", "inline", "lower-pow", "dead-let-elimination"];
+
+// rewriting of the checked program, run before code generation
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    // rewrites the program, returning the warnings found
+    fn run(&mut self, prog: &mut Program) -> Vec<Warning>;
+}
+
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    print_after: Option<String>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager {
+            passes: Vec::new(),
+            print_after: None,
+        }
+    }
+
+    // pipeline for the optimization level, where let scoping and constant folding always run since the backends rely on them
+    pub fn for_options(options: &CompileOptions) -> Self {
+        let mut manager = PassManager::new().print_after(options.print_after.clone());
+        manager.add(LetScoping);
+        manager.add(ConstantFolder::new(options.overflow));
+        if options.opt == OptLevel::O0 {
+            return manager;
+        }
+        // inlining makes the program larger, so it is not done when optimizing for size
+        match options.opt {
+            OptLevel::O2 => manager.add(Inliner::new(12)),
+            OptLevel::O3 => manager.add(Inliner::new(40)),
+            _ => {}
+        }
+        manager.add(PowLowering::new());
+        manager.add(DeadLetElimination::new(options.overflow));
+        manager
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    // prints the program to stderr after every run of the pass with the given name
+    pub fn print_after(mut self, pass: Option<String>) -> Self {
+        self.print_after = pass;
+        self
+    }
+
+    pub fn run(&mut self, prog: &mut Program) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for pass in &mut self.passes {
+            warnings.extend(pass.run(prog));
+            if self.print_after.as_deref() == Some(pass.name()) {
+                eprintln!("; after {}\n{}", pass.name(), prog.to_text());
+            }
+        }
+        warnings
+    }
+}
+
+
+// subexpressions in evaluation order, including the indexes of the lhs of assignments and array accesses,
+// and the bodies of nested functions
+pub fn subexprs(expr: &Spanned<Expr>) -> Vec<&Spanned<Expr>> {
+    let mut subexprs = vec![];
+    match &expr.value {
+        Expr::Chain { lhs, rhs } | Expr::BinOp { lhs, rhs, .. } => subexprs.extend([&**lhs, &**rhs]),
+        Expr::Let { expr, .. } | Expr::LetFun { expr, .. } | Expr::Not { expr } => subexprs.push(&**expr),
+        Expr::Set { lhs, expr } => {
+            lhs_indexes(lhs, &mut subexprs);
+            subexprs.push(&**expr);
+        }
+        Expr::While { cond, expr } => subexprs.extend([&**cond, &**expr]),
+        Expr::IfElse { cond, then, els } => subexprs.extend([&**cond, &**then, &**els]),
+        Expr::FunCall { args, .. } => subexprs.extend(args),
+        Expr::NewArray { size, init, .. } => subexprs.extend([&**size, &**init]),
+        Expr::ArrayIndex { lhs, index } => {
+            lhs_indexes(lhs, &mut subexprs);
+            subexprs.push(&**index);
+        }
+        Expr::Id(_) | Expr::Int(_) | Expr::String(_) | Expr::Bool(_) | Expr::Unit => {}
+    }
+    subexprs
+}
+
+fn lhs_indexes<'a>(lhs: &'a Spanned<Lhs>, subexprs: &mut Vec<&'a Spanned<Expr>>) {
+    if let Lhs::Index { lhs, index } = &lhs.value {
+        lhs_indexes(lhs, subexprs);
+        subexprs.push(index);
+    }
+}
+
+pub fn subexprs_mut(expr: &mut Spanned<Expr>) -> Vec<&mut Spanned<Expr>> {
+    let mut subexprs = vec![];
+    match &mut expr.value {
+        Expr::Chain { lhs, rhs } | Expr::BinOp { lhs, rhs, .. } => subexprs.extend([&mut **lhs, &mut **rhs]),
+        Expr::Let { expr, .. } | Expr::LetFun { expr, .. } | Expr::Not { expr } => subexprs.push(&mut **expr),
+        Expr::Set { lhs, expr } => {
+            lhs_indexes_mut(lhs, &mut subexprs);
+            subexprs.push(&mut **expr);
+        }
+        Expr::While { cond, expr } => subexprs.extend([&mut **cond, &mut **expr]),
+        Expr::IfElse { cond, then, els } => subexprs.extend([&mut **cond, &mut **then, &mut **els]),
+        Expr::FunCall { args, .. } => subexprs.extend(args),
+        Expr::NewArray { size, init, .. } => subexprs.extend([&mut **size, &mut **init]),
+        Expr::ArrayIndex { lhs, index } => {
+            lhs_indexes_mut(lhs, &mut subexprs);
+            subexprs.push(&mut **index);
+        }
+        Expr::Id(_) | Expr::Int(_) | Expr::String(_) | Expr::Bool(_) | Expr::Unit => {}
+    }
+    subexprs
+}
+
+fn lhs_indexes_mut<'a>(lhs: &'a mut Spanned<Lhs>, subexprs: &mut Vec<&'a mut Spanned<Expr>>) {
+    if let Lhs::Index { lhs, index } = &mut lhs.value {
+        lhs_indexes_mut(lhs, subexprs);
+        subexprs.push(index);
+    }
+}
+
+// names of the variables and functions used by the expression, including shadowed ones
+pub fn referenced_names(expr: &Spanned<Expr>, names: &mut HashSet<Id>) {
+    match &expr.value {
+        Expr::Id(id) | Expr::FunCall { id, .. } => {
+            names.insert(id.value.clone());
+        }
+        Expr::Set { lhs, .. } | Expr::ArrayIndex { lhs, .. } => {
+            let mut lhs = lhs;
+            while let Lhs::Index { lhs: inner, .. } = &lhs.value {
+                lhs = inner;
+            }
+            if let Lhs::Var { id } = &lhs.value {
+                names.insert(id.value.clone());
+            }
+        }
+        _ => {}
+    }
+    for subexpr in subexprs(expr) {
+        referenced_names(subexpr, names);
+    }
+}
+
+// chain of a let and unit, which keeps the variable out of the scope of the chain the let is moved into,
+// or the expression itself if it declares no variable
+pub fn scoped(expr: Spanned<Expr>) -> Spanned<Expr> {
+    if !matches!(expr.value, Expr::Let { .. } | Expr::LetFun { .. }) {
+        return expr;
+    }
+    let span = expr.span.clone();
+    let unit = Spanned { value: Expr::Unit, span: span.clone() };
+    Spanned { value: Expr::Chain { lhs: Box::new(expr), rhs: Box::new(unit) }, span }
+}
+
+// moves the expression out of the tree, leaving unit in its place
+pub fn take(expr: &mut Spanned<Expr>) -> Spanned<Expr> {
+    let span = expr.span.clone();
+    std::mem::replace(expr, Spanned { value: Expr::Unit, span })
+}
\ No newline at end of file
@@ -0,0 +1,62 @@
+use crate::diagnostics::warnings::Warning;
+use crate::passes::{subexprs_mut, take, Pass};
+use crate::syntax::ast::{Decl, Expr, Op, Program, Spanned, Type};
+
+// replaces x ^ 2 with x * x, which avoids the call to the pow function of the runtime,
+// keeping the span of the ^ so that overflows are reported in the operator of the source
+#[derive(Default)]
+pub struct PowLowering {
+    temps: usize,
+}
+
+impl PowLowering {
+    pub fn new() -> Self {
+        Self { temps: 0 }
+    }
+
+    fn lower_expr(&mut self, expr: &mut Spanned<Expr>) {
+        for subexpr in subexprs_mut(expr) {
+            self.lower_expr(subexpr);
+        }
+        let Expr::BinOp { lhs, op: Op::Pow, rhs } = &mut expr.value else {
+            return;
+        };
+        if !matches!(rhs.value, Expr::Int(2)) {
+            return;
+        }
+        let span = expr.span.clone();
+        let square = |operand: &Spanned<Expr>| Spanned {
+            value: Expr::BinOp { lhs: Box::new(operand.clone()), op: Op::Mul, rhs: Box::new(operand.clone()) },
+            span: span.clone(),
+        };
+        *expr = match &lhs.value {
+            Expr::Id(_) => square(lhs),
+            // the base is evaluated once, into a variable whose name cannot be written in the source
+            _ => {
+                let id = Spanned { value: format!("pow.{}", self.temps), span: span.clone() };
+                self.temps += 1;
+                let temp = Spanned { value: Expr::Id(id.clone()), span: span.clone() };
+                let base = Spanned {
+                    value: Expr::Let { id, ty: Spanned { value: Type::Int, span: span.clone() }, expr: Box::new(take(lhs)) },
+                    span: span.clone(),
+                };
+                Spanned { value: Expr::Chain { lhs: Box::new(base), rhs: Box::new(square(&temp)) }, span: span.clone() }
+            }
+        };
+    }
+}
+
+impl Pass for PowLowering {
+    fn name(&self) -> &'static str {
+        "lower-pow"
+    }
+
+    fn run(&mut self, prog: &mut Program) -> Vec<Warning> {
+        for decl in &mut prog.decls {
+            match &mut decl.value {
+                Decl::Var { expr, .. } | Decl::Fun { expr, .. } => self.lower_expr(expr),
+            }
+        }
+        Vec::new()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use crate::passes::subexprs;
+use crate::syntax::ast::{Decl, Expr, Id, Lhs, Program, Span, Spanned};
+
+// local variables captured by every nested function, by the span of its name, in the order they are first used,
+// where the variables declared at the top level are not captured since they are globals or functions
+pub fn captured_vars(prog: &Program) -> HashMap<Span, Vec<&Id>> {
+    let mut captures = HashMap::new();
+    for decl in &prog.decls {
+        match &decl.value {
+            Decl::Var { expr, .. } => collect_captures(expr, &mut vec![], &mut captures),
+            Decl::Fun { params, expr, .. } => {
+                let mut locals = params.iter().map(|param| &param.value).collect();
+                collect_captures(expr, &mut locals, &mut captures);
+            }
+        }
+    }
+    captures
+}
+
+// the locals are the variables in scope declared by the enclosing function
+fn collect_captures<'a>(expr: &'a Spanned<Expr>, locals: &mut Vec<&'a Id>, captures: &mut HashMap<Span, Vec<&'a Id>>) {
+    match &expr.value {
+        Expr::Chain { lhs, rhs } => {
+            collect_captures(lhs, locals, captures);
+            let len = locals.len();
+            if let Expr::Let { id, .. } | Expr::LetFun { id, .. } = &lhs.value {
+                locals.push(&id.value);
+            }
+            collect_captures(rhs, locals, captures);
+            locals.truncate(len);
+        }
+        Expr::LetFun { id, params, expr: body, .. } => {
+            let mut bound = params.iter().map(|param| &param.value).collect::<Vec<_>>();
+            bound.push(&id.value);
+            let mut free = vec![];
+            free_vars(body, &mut bound, &mut free);
+            free.retain(|name| locals.contains(name));
+            captures.insert(id.span.clone(), free.clone());
+
+            // the locals of the body are the captured variables, the function itself and its params
+            let mut inner = free;
+            inner.push(&id.value);
+            inner.extend(params.iter().map(|param| &param.value));
+            collect_captures(body, &mut inner, captures);
+        }
+        _ => {
+            for subexpr in subexprs(expr) {
+                collect_captures(subexpr, locals, captures);
+            }
+        }
+    }
+}
+
+// names used by the expression that are not bound inside it, in the order they are first used
+fn free_vars<'a>(expr: &'a Spanned<Expr>, bound: &mut Vec<&'a Id>, free: &mut Vec<&'a Id>) {
+    let mut used = |id: &'a Id, bound: &Vec<&'a Id>| {
+        if !bound.contains(&id) && !free.contains(&id) {
+            free.push(id);
+        }
+    };
+    match &expr.value {
+        Expr::Chain { lhs, rhs } => {
+            free_vars(lhs, bound, free);
+            let len = bound.len();
+            if let Expr::Let { id, .. } | Expr::LetFun { id, .. } = &lhs.value {
+                bound.push(&id.value);
+            }
+            free_vars(rhs, bound, free);
+            bound.truncate(len);
+            return;
+        }
+        Expr::LetFun { id, params, expr: body, .. } => {
+            let len = bound.len();
+            bound.push(&id.value);
+            bound.extend(params.iter().map(|param| &param.value));
+            free_vars(body, bound, free);
+            bound.truncate(len);
+            return;
+        }
+        Expr::Id(id) | Expr::FunCall { id, .. } => used(&id.value, bound),
+        Expr::Set { lhs, .. } | Expr::ArrayIndex { lhs, .. } => {
+            let mut lhs = lhs;
+            while let Lhs::Index { lhs: inner, .. } = &lhs.value {
+                lhs = inner;
+            }
+            if let Lhs::Var { id } = &lhs.value {
+                used(&id.value, bound);
+            }
+        }
+        _ => {}
+    }
+    for subexpr in subexprs(expr) {
+        free_vars(subexpr, bound, free);
+    }
+}
\ No newline at end of file
@@ -1,7 +1,8 @@
 use crate::diagnostics::errors::DeclarationError;
 use crate::diagnostics::warnings::Warning;
 use crate::semantic::{get_init_symbols, Symbol, RESERVED_IDENTIFIERS};
-use crate::syntax::ast::{Program, Decl, Expr, Lhs, Type, Id, Spanned};
+use std::collections::{HashMap, HashSet};
+use crate::syntax::ast::{Program, Decl, Expr, Lhs, Type, Id, Span, Spanned};
 use crate::semantic::symbol_table::SymbolTable;
 use crate::utils::get_similar;
 
@@ -10,6 +11,10 @@ pub struct DeclarationChecker {
     unused_symbols: Vec<Spanned<Id>>,
     errors: Vec<DeclarationError>,
     main_declared: bool,
+    level: usize, // nesting level of the function being checked, where the top level is 0
+    levels: HashMap<Span, usize>, // level of the function declaring each local variable
+    captured: HashSet<Span>, // local variables used by nested functions
+    assignments: Vec<(Spanned<Id>, Span)>, // assigned variables with the span of their declaration
 }
 
 impl DeclarationChecker {
@@ -19,6 +24,10 @@ impl DeclarationChecker {
             unused_symbols: Vec::new(),
             errors: Vec::new(),
             main_declared: false,
+            level: 0,
+            levels: HashMap::new(),
+            captured: HashSet::new(),
+            assignments: Vec::new(),
         }
     }
 
@@ -37,6 +46,12 @@ impl DeclarationChecker {
         } else {
             self.errors.push(DeclarationError::missing_main());
         }
+        // nested functions capture variables by value, so they cannot be assigned
+        for (id, decl_span) in &self.assignments {
+            if self.captured.contains(decl_span) {
+                self.errors.push(DeclarationError::captured_assignment(id.clone()));
+            }
+        }
         (self.errors.clone(), self.get_warnings())
     }
 
@@ -62,7 +77,9 @@ impl DeclarationChecker {
             Decl::Var { expr, id, ty } => {
                 // variable scope
                 self.symbols.enter_scope();
+                self.level = 1;
                 self.check_expr(&expr.value);
+                self.level = 0;
                 self.symbols.exit_scope();
 
                 // only declare after exiting scope so it's not visible inside
@@ -71,10 +88,12 @@ impl DeclarationChecker {
             Decl::Fun { params, ty, expr, .. } => {
                 // function scope
                 self.symbols.enter_scope();
+                self.level = 1;
                 for (param_id, param_ty) in params.iter().zip(ty.value.params.iter()) {
                     self.declare(&param_id, &param_ty);
                 }
                 self.check_expr(&expr.value);
+                self.level = 0;
                 self.symbols.exit_scope();
             }
         }
@@ -88,6 +107,9 @@ impl DeclarationChecker {
                     // declare the let binding in the scope
                     self.declare(&id, &ty.value);
                 }
+                if let Expr::LetFun { id, ty, .. } = &lhs.value {
+                    self.declare(&id, &Type::Fun(ty.value.clone()));
+                }
                 self.check_expr(&rhs.value);
             }
             Expr::Let { id, expr, .. } => {
@@ -99,8 +121,34 @@ impl DeclarationChecker {
                 self.check_expr(&expr.value);
                 self.symbols.exit_scope();
             }
+            Expr::LetFun { id, params, ty, expr } => {
+                if params.len() != ty.value.params.len() {
+                    self.errors.push(DeclarationError::function_signature_mismatch(id.span.clone(), params.len(), ty.value.params.len()));
+                }
+                if RESERVED_IDENTIFIERS.contains(&id.value) {
+                    self.errors.push(DeclarationError::reserved_identifier(id.clone()));
+                }
+                // function scope, where the function is visible for recursive calls,
+                // as a variable of the enclosing function captured by its own body
+                self.symbols.enter_scope();
+                let symbol = Symbol { ty: Type::Fun(ty.value.clone()), span: id.span.clone() };
+                self.symbols.declare(&id.value, &symbol);
+                self.levels.insert(id.span.clone(), self.level);
+                self.level += 1;
+                for (param_id, param_ty) in params.iter().zip(ty.value.params.iter()) {
+                    self.declare(&param_id, &param_ty);
+                }
+                self.check_expr(&expr.value);
+                self.level -= 1;
+                self.symbols.exit_scope();
+            }
             Expr::Set { lhs, expr } => {
                 self.check_lhs(&lhs.value);
+                if let Lhs::Var { id } = &lhs.value {
+                    if let Some(symbol) = self.symbols.lookup(&id.value) {
+                        self.assignments.push((id.clone(), symbol.span));
+                    }
+                }
                 self.check_expr(&expr.value);
             }
             Expr::BinOp { lhs, rhs, .. } => {
@@ -110,7 +158,7 @@ impl DeclarationChecker {
             Expr::Not { expr } => {
                 self.check_expr(&expr.value)
             },
-            Expr::FunCall { id, args } => {
+            Expr::FunCall { id, args, .. } => {
                 self.check_id(id);
                 for arg in args {
                     self.check_expr(&arg.value);
@@ -156,10 +204,18 @@ impl DeclarationChecker {
     }
 
     fn check_id(&mut self, id: &Spanned<Id>) {
-        if self.lookup(&id.value).is_none() {
-            let all_symbols = self.symbols.get_symbols_in_scope().iter().map(|(id, _)| id.to_owned()).collect::<Vec<_>>();
-            let similar = get_similar(all_symbols, &id.value);
-            self.errors.push(DeclarationError::undeclared_identifier(id.clone(), similar));
+        match self.lookup(&id.value) {
+            Some(symbol) => {
+                // local variables of enclosing functions are captured
+                if self.levels.get(&symbol.span).is_some_and(|level| *level < self.level) {
+                    self.captured.insert(symbol.span);
+                }
+            }
+            None => {
+                let all_symbols = self.symbols.get_symbols_in_scope().iter().map(|(id, _)| id.to_owned()).collect::<Vec<_>>();
+                let similar = get_similar(all_symbols, &id.value);
+                self.errors.push(DeclarationError::undeclared_identifier(id.clone(), similar));
+            }
         }
     }
 
@@ -172,6 +228,9 @@ impl DeclarationChecker {
             span: id.span.clone(),
         };
         self.symbols.declare(&id.value, &symbol);
+        if self.level > 0 {
+            self.levels.insert(id.span.clone(), self.level);
+        }
         if !id.value.starts_with("_") {
             self.unused_symbols.push(id.clone());
         }
@@ -5,6 +5,8 @@ use crate::syntax::ast::{FunType, Span, Type};
 pub mod symbol_table;
 pub mod declaration_checker;
 pub mod type_checker;
+pub mod tail_calls;
+pub mod captures;
 
 #[derive(Debug, Clone)]
 pub struct Symbol {
@@ -21,7 +23,7 @@ pub fn get_init_symbols() -> HashMap<String, Symbol> {
 }
 
 lazy_static! {
-    pub static ref INIT_SYMBOLS: [(&'static str, Type); 2] = [
+    pub static ref INIT_SYMBOLS: [(&'static str, Type); 3] = [
         (
             // print: Any -> Unit
             "print",
@@ -42,6 +44,16 @@ lazy_static! {
                 }
             )
         ),
+        (
+            // exit: Int -> Unit
+            "exit",
+            Type::Fun(
+                FunType {
+                    params: vec![Type::Int],
+                    ret: Box::new(Type::Unit)
+                }
+            )
+        ),
     ];
     pub static ref RESERVED_IDENTIFIERS: Vec<String> =
         INIT_SYMBOLS.iter().map(|s| s.0.to_string()).collect::<Vec<_>>();
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+use crate::diagnostics::warnings::Warning;
+use crate::semantic::RESERVED_IDENTIFIERS;
+use crate::syntax::ast::{Decl, Expr, Id, Lhs, Program, Span, Spanned};
+
+// spans of the calls in tail position of a function body, whose result is returned by the function
+pub fn tail_calls(fun_id: &Id, body: &Spanned<Expr>) -> HashSet<Span> {
+    let mut calls = HashSet::new();
+    // main returns an exit code instead of the value of its body
+    if fun_id != "main" {
+        collect_tail_calls(body, &mut calls);
+    }
+    calls
+}
+
+fn collect_tail_calls(expr: &Spanned<Expr>, calls: &mut HashSet<Span>) {
+    match &expr.value {
+        Expr::Chain { rhs, .. } => collect_tail_calls(rhs, calls),
+        Expr::IfElse { then, els, .. } => {
+            collect_tail_calls(then, calls);
+            collect_tail_calls(els, calls);
+        }
+        // builtins are not function calls and main has a different return type
+        Expr::FunCall { id, .. } if !RESERVED_IDENTIFIERS.contains(&id.value) && id.value != "main" => {
+            calls.insert(expr.span.clone());
+        }
+        _ => {}
+    }
+}
+
+#[derive(Default)]
+pub struct TailCallChecker {
+    tail_calls: HashSet<Span>,
+    warnings: Vec<Warning>,
+}
+
+impl TailCallChecker {
+    pub fn new() -> Self {
+        Self {
+            tail_calls: HashSet::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // warns about the calls marked as tail calls that are not in tail position
+    pub fn check(&mut self, prog: &Program) -> Vec<Warning> {
+        for decl in &prog.decls {
+            match &decl.value {
+                Decl::Var { expr, .. } => {
+                    self.tail_calls.clear();
+                    self.check_expr(expr);
+                }
+                Decl::Fun { id, expr, .. } => {
+                    self.tail_calls = tail_calls(&id.value, expr);
+                    self.check_expr(expr);
+                }
+            }
+        }
+        self.warnings.clone()
+    }
+
+    fn check_expr(&mut self, expr: &Spanned<Expr>) {
+        match &expr.value {
+            Expr::Chain { lhs, rhs } => {
+                self.check_expr(lhs);
+                self.check_expr(rhs);
+            }
+            Expr::Let { expr, .. } | Expr::Not { expr } => self.check_expr(expr),
+            // the body of a nested function has its own tail calls
+            Expr::LetFun { id, expr, .. } => {
+                let tail_calls = std::mem::replace(&mut self.tail_calls, tail_calls(&id.value, expr));
+                self.check_expr(expr);
+                self.tail_calls = tail_calls;
+            }
+            Expr::Set { lhs, expr } => {
+                self.check_lhs(lhs);
+                self.check_expr(expr);
+            }
+            Expr::BinOp { lhs, rhs, .. } => {
+                self.check_expr(lhs);
+                self.check_expr(rhs);
+            }
+            Expr::While { cond, expr } => {
+                self.check_expr(cond);
+                self.check_expr(expr);
+            }
+            Expr::IfElse { cond, then, els } => {
+                self.check_expr(cond);
+                self.check_expr(then);
+                self.check_expr(els);
+            }
+            Expr::FunCall { id, args, tail } => {
+                if *tail && !self.tail_calls.contains(&expr.span) {
+                    let call = Spanned { value: id.value.clone(), span: expr.span.clone() };
+                    self.warnings.push(Warning::MisplacedTailCall(call));
+                }
+                for arg in args {
+                    self.check_expr(arg);
+                }
+            }
+            Expr::NewArray { size, init, .. } => {
+                self.check_expr(size);
+                self.check_expr(init);
+            }
+            Expr::ArrayIndex { lhs, index } => {
+                self.check_lhs(lhs);
+                self.check_expr(index);
+            }
+            Expr::Id(_) | Expr::Int(_) | Expr::String(_) | Expr::Bool(_) | Expr::Unit => {}
+        }
+    }
+
+    fn check_lhs(&mut self, lhs: &Spanned<Lhs>) {
+        if let Lhs::Index { lhs, index } = &lhs.value {
+            self.check_lhs(lhs);
+            self.check_expr(index);
+        }
+    }
+}
\ No newline at end of file
@@ -7,6 +7,7 @@ use crate::syntax::ast::*;
 pub struct TypeChecker {
     symbols: SymbolTable<Symbol>,
     errors: Vec<TypeError>,
+    types: TypeMap,
 }
 
 impl TypeChecker {
@@ -14,10 +15,16 @@ impl TypeChecker {
     pub fn new() -> Self {
         TypeChecker {
             symbols: SymbolTable::new(get_init_symbols()),
-            errors: Vec::new()
+            errors: Vec::new(),
+            types: TypeMap::new(),
         }
     }
 
+    // types inferred for each expression during the last check
+    pub fn types(&self) -> &TypeMap {
+        &self.types
+    }
+
     pub fn check(&mut self, prog: &Program) -> Result<(), Vec<TypeError>> {
         for decl in &prog.decls {
             match &decl.value {
@@ -38,11 +45,11 @@ impl TypeChecker {
                     self.symbols.exit_scope();
                     self.declare(&id, &Type::Fun(ty.value.clone()));
 
-                    // check main function signature
+                    // check main function signature, which may return the exit code
                     if id.value == "main" && (
                         ty.value.params.len() != 1
                         || *ty.value.params.first().unwrap() != Type::Unit
-                        || *ty.value.ret != Type::Unit
+                        || !matches!(*ty.value.ret, Type::Unit | Type::Int)
                     ) {
                         self.errors.push(TypeError::main_signature_mismatch(ty.span.clone()));
                     }
@@ -57,6 +64,12 @@ impl TypeChecker {
     }
 
     fn type_of(&mut self, expr: &Spanned<Expr>) -> Type {
+        let ty = self.infer_type(expr);
+        self.types.insert(expr.span.clone(), ty.clone());
+        ty
+    }
+
+    fn infer_type(&mut self, expr: &Spanned<Expr>) -> Type {
         let span = expr.span.clone();
         match &expr.value {
             Expr::Chain { lhs, rhs } => {
@@ -65,6 +78,9 @@ impl TypeChecker {
                     // declare the let binding in the scope
                     self.declare(&id, &ty.value);
                 }
+                if let Expr::LetFun { id, ty, .. } = &lhs.value {
+                    self.declare(&id, &Type::Fun(ty.value.clone()));
+                }
                 self.type_of(rhs)
             }
             Expr::Let { ty, expr, .. } => {
@@ -74,6 +90,17 @@ impl TypeChecker {
                 self.symbols.exit_scope();
                 Type::Unit
             }
+            Expr::LetFun { id, params, ty, expr } => {
+                // function scope, where the function is visible for recursive calls
+                self.symbols.enter_scope();
+                self.declare(&id, &Type::Fun(ty.value.clone()));
+                for (param_id, param_ty) in params.iter().zip(ty.value.params.iter()) {
+                    self.declare(&param_id, &param_ty);
+                }
+                self.check_against(expr, &ty.value.ret);
+                self.symbols.exit_scope();
+                Type::Unit
+            }
             Expr::Set { lhs, expr } => {
                 let lhs_type = self.type_of_lhs(lhs);
                 self.check_against(expr, &lhs_type);
@@ -107,7 +134,7 @@ impl TypeChecker {
                 self.check_against(expr, &Type::Bool);
                 Type::Bool
             }
-            Expr::FunCall { id, args } => {
+            Expr::FunCall { id, args, .. } => {
                 let Some(fun) = self.symbols.lookup(&id.value) else {
                     return Type::Any; // undeclared symbol, error already reported
                 };
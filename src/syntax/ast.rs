@@ -1,9 +1,11 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::ops::Range;
 use crate::utils::indent;
 
 pub type Span = Range<usize>;
 pub type Id = String;
+pub type TypeMap = HashMap<Span, Type>; // type of each expression by its span
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Spanned<T> {
@@ -42,6 +44,13 @@ pub enum Expr {
         ty: Spanned<Type>,
         expr: Box<Spanned<Expr>>
     },
+    // nested function, which captures the local variables it uses
+    LetFun {
+        id: Spanned<Id>,
+        params: Vec<Spanned<Id>>,
+        ty: Spanned<FunType>,
+        expr: Box<Spanned<Expr>>
+    },
     Set {
         lhs: Spanned<Lhs>,
         expr: Box<Spanned<Expr>>
@@ -65,7 +74,8 @@ pub enum Expr {
     },
     FunCall {
         id: Spanned<Id>,
-        args: Vec<Spanned<Expr>>
+        args: Vec<Spanned<Expr>>,
+        tail: bool // marked as a tail call
     },
     NewArray {
         ty: Spanned<Type>,
@@ -160,9 +170,18 @@ impl<T: fmt::Display> fmt::Display for Spanned<T> {
 
 impl Program {
     pub fn to_text(&self) -> String {
+        self.fmt_text(None)
+    }
+
+    // program with every expression annotated with its type
+    pub fn to_typed_text(&self, types: &TypeMap) -> String {
+        self.fmt_text(Some(types))
+    }
+
+    fn fmt_text(&self, types: Option<&TypeMap>) -> String {
         self.decls
             .iter()
-            .map(|decl| decl.value.to_text(0))
+            .map(|decl| decl.value.fmt_text(0, types))
             .collect::<Vec<_>>()
             .join("\n\n")
     }
@@ -170,6 +189,10 @@ impl Program {
 
 impl Decl {
     pub fn to_text(&self, level: usize) -> String {
+        self.fmt_text(level, None)
+    }
+
+    fn fmt_text(&self, level: usize, types: Option<&TypeMap>) -> String {
         match self {
             Decl::Fun { id, params, ty, expr } => {
                 format!(
@@ -182,7 +205,7 @@ impl Decl {
                         .join(", "),
                     ty.value.to_text(),
                     indent(level + 1),
-                    expr.value.to_text(level + 1)
+                    expr.to_typed_text(level + 1, types)
                 )
             }
             Decl::Var { id, ty, expr } => {
@@ -191,7 +214,7 @@ impl Decl {
                     id,
                     ty.value.to_text(),
                     indent(level + 1),
-                    expr.value.to_text(level + 1)
+                    expr.to_typed_text(level + 1, types)
                 )
             }
         }
@@ -200,13 +223,17 @@ impl Decl {
 
 impl Expr {
     pub fn to_text(&self, level: usize) -> String {
+        self.fmt_text(level, None)
+    }
+
+    fn fmt_text(&self, level: usize, types: Option<&TypeMap>) -> String {
         match self {
             Expr::Chain { lhs, rhs } => {
                 format!(
                     "{};\n{}{}",
-                    lhs.value.to_text(level),
+                    lhs.to_typed_text(level, types),
                     indent(level),
-                    rhs.value.to_text(level)
+                    rhs.to_typed_text(level, types)
                 )
             }
             Expr::Let { id, ty, expr } => {
@@ -214,63 +241,77 @@ impl Expr {
                     "let {} : {} = {}",
                     id,
                     ty.value.to_text(),
-                    expr.value.to_text(level)
+                    expr.to_typed_text(level, types)
+                )
+            }
+            Expr::LetFun { id, params, ty, expr } => {
+                format!(
+                    "let {} ({}) : {} = {}",
+                    id,
+                    params
+                        .iter()
+                        .map(|id| id.value.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    ty.value.to_text(),
+                    expr.to_typed_text(level, types)
                 )
             }
             Expr::Set { lhs, expr } => {
                 format!(
                     "set {} = {}",
-                    lhs.value.to_text(),
-                    expr.value.to_text(level)
+                    lhs.value.fmt_text(types),
+                    expr.to_typed_text(level, types)
                 )
             }
             Expr::NewArray { ty, size, init } => {
                 format!(
                     "new {}[{} | {}]",
                     ty.value.to_text(),
-                    size.value.to_text(level),
-                    init.value.to_text(level)
+                    size.to_typed_text(level, types),
+                    init.to_typed_text(level, types)
                 )
             }
             Expr::IfElse { cond, then, els } => {
                 format!(
                     "if {} then\n{}{}\n{}else\n{}{}",
-                    cond.value.to_text(level),
+                    cond.to_typed_text(level, types),
                     indent(level + 1),
-                    then.value.to_text(level + 1),
+                    then.to_typed_text(level + 1, types),
                     indent(level),
                     indent(level + 1),
-                    els.value.to_text(level + 1)
+                    els.to_typed_text(level + 1, types)
                 )
             }
             Expr::While { cond, expr } => {
                 format!(
                     "while {} do\n{}{}",
-                    cond.value.to_text(level),
+                    cond.to_typed_text(level, types),
                     indent(level + 1),
-                    expr.value.to_text(level + 1)
+                    expr.to_typed_text(level + 1, types)
                 )
             }
             Expr::BinOp { lhs, op, rhs } => {
                 format!(
                     "{} {} {}",
-                    lhs.value.to_text(level),
+                    lhs.to_typed_text(level, types),
                     op.to_text(),
-                    rhs.value.to_text(level)
+                    rhs.to_typed_text(level, types)
                 )
             }
-            Expr::Not { expr } => format!("!{}", expr.value.to_text(level)),
+            Expr::Not { expr } => format!("!{}", expr.to_typed_text(level, types)),
             Expr::Int(n) => n.to_string(),
             Expr::Bool(b) => format!("{}", b),
             Expr::Unit => "unit".to_string(),
             Expr::String(s) => format!("\"{}\"", s),
             Expr::Id(id) => id.to_string(),
-            Expr::FunCall { id, args } => {
+            Expr::FunCall { id, args, tail } => {
                 format!(
-                    "{}({})",
+                    "{}{}({})",
+                    if *tail { "tail " } else { "" },
                     id,
                     args.iter()
-                        .map(|arg| arg.value.to_text(level))
+                        .map(|arg| arg.to_typed_text(level, types))
                         .collect::<Vec<_>>()
                         .join(", ")
                 )
@@ -278,13 +319,24 @@ impl Expr {
             Expr::ArrayIndex { lhs, index } =>
                 format!(
                     "{}[{}]",
-                    lhs.value.to_text(),
-                    index.value.to_text(level)
+                    lhs.value.fmt_text(types),
+                    index.to_typed_text(level, types)
                 ),
         }
     }
 }
 
+impl Spanned<Expr> {
+    // annotates the expression with the type inferred by the type checker
+    fn to_typed_text(&self, level: usize, types: Option<&TypeMap>) -> String {
+        let text = self.value.fmt_text(level, types);
+        match types.and_then(|types| types.get(&self.span)) {
+            Some(ty) if !matches!(self.value, Expr::Chain { .. }) => format!("({} : {})", text, ty.to_text()),
+            _ => text,
+        }
+    }
+}
+
 impl Type {
     pub fn to_text(&self) -> String {
         match self {
@@ -297,13 +349,22 @@ impl Type {
             Type::Any => "Any".to_string(),
         }
     }
+
+    // arrays and function values are references to heap objects
+    pub fn is_reference(&self) -> bool {
+        matches!(self, Type::Array(_) | Type::Fun(_))
+    }
 }
 
 impl Lhs {
     pub fn to_text(&self) -> String {
+        self.fmt_text(None)
+    }
+
+    fn fmt_text(&self, types: Option<&TypeMap>) -> String {
         match self {
             Lhs::Var { id } => id.to_string(),
-            Lhs::Index { lhs, index } => format!("{}[{}]", lhs.value.to_text(), index.value.to_text(0)),
+            Lhs::Index { lhs, index } => format!("{}[{}]", lhs.value.fmt_text(types), index.to_typed_text(0, types)),
         }
     }
 }
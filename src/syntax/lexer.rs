@@ -3,6 +3,7 @@ use std::num::ParseIntError;
 use logos::Logos;
 use crate::diagnostics::errors::{LexicalError, LexicalErrorKind};
 use crate::syntax::ast::Spanned;
+use crate::utils::get_position_in_src;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(error=LexicalErrorKind)]
@@ -126,6 +127,9 @@ pub enum Token {
     #[token("new")]
     New,
 
+    #[token("tail")]
+    Tail,
+
     #[token("|")]
     Pipe,
 
@@ -165,10 +169,29 @@ impl<'a> Lexer<'a> {
                 }
             }
         }
+        // tail is only a keyword before the function of a call, so it can still name variables and functions,
+        // going backwards to read `tail tail(...)` as a tail call to a function named tail
+        for i in (0..tokens.len()).rev() {
+            if tokens[i].value == Token::Tail && !matches!(tokens.get(i + 1).map(|tok| &tok.value), Some(Token::Id(_))) {
+                tokens[i].value = Token::Id("tail".to_string());
+            }
+        }
         Ok(tokens)
     }
 }
 
+// one token per line, prefixed with its line and column in the source
+pub fn tokens_to_text(src: &str, tokens: &[Spanned<Token>]) -> String {
+    tokens
+        .iter()
+        .map(|tok| {
+            let (line, col) = get_position_in_src(src, tok.span.start);
+            format!("{}:{}\t{:?}", line, col, tok.value)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl From<ParseIntError> for LexicalErrorKind {
     fn from(err: ParseIntError) -> Self {
         use std::num::IntErrorKind::*;
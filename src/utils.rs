@@ -1,5 +1,8 @@
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use strsim::levenshtein;
 
 pub fn read_aguda_file(file: &str) -> Result<String, String> {
@@ -34,6 +37,21 @@ pub fn get_position_in_src(source: &str, index: usize) -> (usize, usize) {
     (line, col)
 }
 
+// inverse of get_position_in_src
+pub fn get_index_in_src(source: &str, line: usize, col: usize) -> usize {
+    let line_start = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(|l| l.len())
+        .sum::<usize>();
+    let offset = source[line_start..]
+        .char_indices()
+        .nth(col.saturating_sub(1))
+        .map(|(i, _)| i)
+        .unwrap_or(source.len() - line_start);
+    line_start + offset
+}
+
 pub fn indent(level: usize) -> String {
     "  ".repeat(level)
 }
@@ -44,4 +62,37 @@ pub fn get_similar(names: Vec<String>, name: &str) -> Option<String> {
         .map(|str| (str, levenshtein(name, str)))
         .min_by_key(|&(_, dist)| dist)
         .and_then(|(best, dist)| if dist <= 2 { Some(best.to_string()) } else { None })
+}
+
+// directory in the system temporary directory for the intermediate files of a build,
+// removed with its contents when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(prefix: &str) -> io::Result<Self> {
+        // unique within the process, which may build several programs at once,
+        // skipping the directories left by an earlier process with the same id
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("{}-{}-{}", prefix, process::id(), id));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
 }
\ No newline at end of file
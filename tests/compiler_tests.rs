@@ -1,7 +1,14 @@
 use std::fs;
 use std::path::Path;
-use aguda_rs::{compile_aguda_program, run_aguda_program};
-use aguda_rs::diagnostics::errors::AgudaError;
+use std::process::Command;
+use aguda_rs::{check_aguda_program, compile_aguda_program, interpret_aguda_program, jit_aguda_program, load_bytecode, run_aguda_program, run_bytecode, Backend, CompileOptions, Emit, Gc, OptLevel, Overflow};
+use aguda_rs::bytecode::format::encode;
+use aguda_rs::bytecode::instr::{Bytecode, Function, Instr};
+use aguda_rs::diagnostics::errors::{AgudaError, RuntimeErrorKind};
+use aguda_rs::diagnostics::warnings::Warning;
+use aguda_rs::ir::instr::{Block, Operand, Terminator};
+use aguda_rs::ir::lowering::lower;
+use aguda_rs::ir::verifier::verify;
 use aguda_rs::diagnostics::formatting::format_aguda_errors;
 
 #[test]
@@ -10,6 +17,7 @@ fn test_compiler() {
     let valid_dir = base_dir.join("valid");
     let invalid_syntax_dir = base_dir.join("invalid-syntax");
     let invalid_semantic_dir = base_dir.join("invalid-semantic");
+    let invalid_runtime_dir = base_dir.join("invalid-runtime");
 
     let (valid_passed, valid_failed) = test_agu_files_in_dir(&valid_dir, true);
     let valid_tests = valid_passed + valid_failed;
@@ -17,8 +25,10 @@ fn test_compiler() {
     let invalid_syntax_tests = invalid_syntax_passed + invalid_syntax_failed;
     let (invalid_semantic_passed, invalid_semantic_failed) = test_agu_files_in_dir(&invalid_semantic_dir, false);
     let invalid_semantic_tests = invalid_semantic_passed + invalid_semantic_failed;
-    let total_tests = valid_tests + invalid_syntax_tests + invalid_semantic_tests;
-    let failed_tests = valid_failed + invalid_syntax_passed + invalid_semantic_passed;
+    let (invalid_runtime_passed, invalid_runtime_failed) = test_runtime_errors_in_dir(&invalid_runtime_dir);
+    let invalid_runtime_tests = invalid_runtime_passed + invalid_runtime_failed;
+    let total_tests = valid_tests + invalid_syntax_tests + invalid_semantic_tests + invalid_runtime_tests;
+    let failed_tests = valid_failed + invalid_syntax_passed + invalid_semantic_passed + invalid_runtime_failed;
 
     println!("\n📊 Test Summary");
     println!("========================");
@@ -34,6 +44,10 @@ fn test_compiler() {
     println!("✅  Passed: {}", invalid_semantic_passed);
     println!("❌  Failed: {}", invalid_semantic_failed);
     println!("========================");
+    println!("Invalid runtime tests ({})", invalid_runtime_tests);
+    println!("✅  Passed: {}", invalid_runtime_passed);
+    println!("❌  Failed: {}", invalid_runtime_failed);
+    println!("========================");
     println!("📝 Total tests: {}", total_tests);
     println!("⚠️ Failures: {}", failed_tests);
     println!("========================");
@@ -41,6 +55,381 @@ fn test_compiler() {
     assert_eq!(valid_failed, 0, "Some valid tests failed");
     assert_eq!(invalid_syntax_passed, 0, "Some invalid syntax tests passed");
     assert_eq!(invalid_semantic_passed, 0, "Some invalid semantic tests passed");
+    assert_eq!(invalid_runtime_failed, 0, "Some invalid runtime tests did not fail at runtime");
+}
+
+// valid programs run in the jit, in the test process
+#[test]
+fn test_jit() {
+    let failed = for_each_valid_test(|src, agu_path| {
+        let options = CompileOptions { emit: Emit::Bitcode, ..CompileOptions::default() };
+        let (bitcode, _) = compile_aguda_program(src, &agu_path.to_string_lossy(), &options)
+            .map_err(|e| format!("failed to compile {:?}: {:?}", agu_path, e))?;
+        let (stdout, status) = jit_aguda_program(&bitcode)
+            .map_err(|e| format!("failed to run {:?}: {:?}", agu_path, e.kind))?;
+        Ok((stdout, Some(status)))
+    });
+    assert_eq!(failed, 0, "Some valid tests failed in the jit");
+}
+
+// valid programs built with the c backend, which needs a c compiler
+#[test]
+fn test_c_backend() {
+    let options = CompileOptions { backend: Backend::C, ..CompileOptions::default() };
+    let failed = for_each_valid_test(|src, agu_path| run_executable(src, agu_path, "aguda_c", &options));
+    assert_eq!(failed, 0, "Some valid tests failed with the c backend");
+}
+
+// valid programs, and programs that allocate more than fits in memory, built with reference counting
+#[test]
+fn test_rc_gc() {
+    let options = CompileOptions { gc: Gc::Rc, ..CompileOptions::default() };
+    let run = |src: &str, agu_path: &Path| run_executable(src, agu_path, "aguda_rc", &options);
+    let failed = for_each_valid_test(run) + for_each_test_in("./tests/gc", run);
+    assert_eq!(failed, 0, "Some tests failed with reference counting");
+}
+
+// valid programs, and programs that allocate more than fits in memory, built with the mark-sweep collector
+#[test]
+fn test_marksweep_gc() {
+    for (backend, prefix) in [(Backend::Llvm, "aguda_ms"), (Backend::C, "aguda_c_ms")] {
+        let options = CompileOptions { gc: Gc::MarkSweep, backend, ..CompileOptions::default() };
+        let run = |src: &str, agu_path: &Path| run_executable(src, agu_path, prefix, &options);
+        let failed = for_each_valid_test(run) + for_each_test_in("./tests/gc", run);
+        assert_eq!(failed, 0, "Some tests failed with the mark-sweep collector and the {:?} backend", backend);
+    }
+}
+
+// valid programs run in the interpreter, which must give the same output as the compiled programs
+#[test]
+fn test_interpreter() {
+    let failed = for_each_valid_test(|src, agu_path| {
+        let (ast, _) = check_aguda_program(src).map_err(|e| format!("failed to check {:?}: {:?}", agu_path, e))?;
+        let (stdout, status) = interpret_aguda_program(&ast, src, &CompileOptions::default())
+            .map_err(|e| format!("failed to interpret {:?}: {:?}", agu_path, e.kind))?;
+        Ok((stdout, Some(status)))
+    });
+    assert_eq!(failed, 0, "Some valid tests failed in the interpreter");
+}
+
+// valid programs compiled to bytecode, encoded and decoded, and run in the vm
+#[test]
+fn test_bytecode() {
+    let failed = for_each_valid_test(|src, agu_path| run_in_vm(src, agu_path, &CompileOptions::default()));
+    assert_eq!(failed, 0, "Some valid tests failed in the bytecode vm");
+}
+
+// valid programs rewritten by every ast optimization pass, run in the vm to leave llvm out
+#[test]
+fn test_ast_passes() {
+    let options = CompileOptions { opt: OptLevel::O3, ..CompileOptions::default() };
+    let failed = for_each_valid_test(|src, agu_path| run_in_vm(src, agu_path, &options));
+    assert_eq!(failed, 0, "Some valid tests failed after the ast optimization passes");
+}
+
+// output of each stage of the compiler for a program, compared with the expected file named after the stage
+#[test]
+fn test_emit() {
+    let agu_path = Path::new("./tests/emit/64371_double/double.agu");
+    let stages = [(Emit::Tokens, "tokens"), (Emit::Ast, "ast"), (Emit::TypedAst, "typed-ast"), (Emit::Ir, "ir")];
+    for (emit, stage) in stages {
+        let expected = fs::read_to_string(agu_path.with_extension(format!("{}.expect", stage)))
+            .unwrap_or_else(|e| panic!("failed to read expected {} of {:?}: {}", stage, agu_path, e));
+        let output = emit_text(agu_path, &CompileOptions { emit, ..CompileOptions::default() });
+        assert_eq!(output.trim(), expected.trim(), "wrong {} for {:?}", stage, agu_path);
+    }
+
+    // the llvm output depends on its version and on the host, so only its main parts are checked
+    let llvm_ir = emit_text(agu_path, &CompileOptions { emit: Emit::LlvmIr, ..CompileOptions::default() });
+    assert!(llvm_ir.contains("define tailcc i64 @aguda.double("), "no function double in the llvm ir:\n{}", llvm_ir);
+    assert!(llvm_ir.contains("call tailcc i64 @aguda.double("), "no call to double in the llvm ir:\n{}", llvm_ir);
+    let asm = emit_text(agu_path, &CompileOptions { emit: Emit::Asm, ..CompileOptions::default() });
+    assert!(asm.contains("\naguda.double:") && asm.contains("\nmain:"), "no functions in the assembly:\n{}", asm);
+    let obj = emit(agu_path, &CompileOptions { emit: Emit::Obj, ..CompileOptions::default() });
+    assert!(!obj.is_ascii(), "the object file is not binary");
+    assert!(obj.windows(b"double".len()).any(|name| name == b"double"), "no symbol double in the object file");
+}
+
+// debug metadata of the program built with -g, which describes its functions, variables and lines
+#[test]
+fn test_debug_info() {
+    let agu_path = Path::new("./tests/emit/64371_double/double.agu");
+    let llvm_ir = emit_text(agu_path, &CompileOptions { emit: Emit::LlvmIr, debug: true, ..CompileOptions::default() });
+    let metadata = |node: &str| {
+        llvm_ir.lines()
+            .find(|line| line.contains(node))
+            .unwrap_or_else(|| panic!("no metadata {} in the llvm ir:\n{}", node, llvm_ir))
+    };
+    metadata("!DICompileUnit(");
+    metadata("!DIFile(filename: \"double.agu\"");
+    assert!(metadata("!DISubprogram(name: \"double\"").contains("line: 5"));
+    assert!(metadata("!DISubprogram(name: \"main\"").contains("line: 9"));
+    assert!(metadata("!DILocalVariable(name: \"x\", arg: 1").contains("line: 5"));
+    assert!(metadata("!DILocalVariable(name: \"y\"").contains("line: 6"));
+    metadata("!DILocation(line: 6");
+
+    let llvm_ir = emit_text(agu_path, &CompileOptions { emit: Emit::LlvmIr, ..CompileOptions::default() });
+    assert!(!llvm_ir.contains("!DICompileUnit("), "debug metadata without -g");
+}
+
+// code generated for another architecture than the host, which is never run
+#[test]
+fn test_cross_target() {
+    let agu_path = Path::new("./tests/emit/64371_double/double.agu");
+    let target = Some("aarch64-unknown-linux-gnu".to_string());
+    let llvm_ir = emit_text(agu_path, &CompileOptions { emit: Emit::LlvmIr, target: target.clone(), ..CompileOptions::default() });
+    assert!(llvm_ir.contains("target triple = \"aarch64-unknown-linux-gnu\""), "wrong target in the llvm ir:\n{}", llvm_ir);
+    let asm = emit_text(agu_path, &CompileOptions { emit: Emit::Asm, target, ..CompileOptions::default() });
+    assert!(asm.contains("\naguda.double:") && asm.contains("\nmain:"), "no functions in the assembly:\n{}", asm);
+    // aarch64 calls with bl and saves the link register x30, which x86 does not have
+    assert!(asm.contains("\tbl\t") && asm.contains("x30"), "the assembly is not aarch64:\n{}", asm);
+}
+
+// program printed by --print-after when the pass runs, here squares lowered with --overflow=trap
+#[test]
+fn test_print_after() {
+    let agu_path = Path::new("./tests/emit/64371_square/square.agu");
+    let output = Command::new(env!("CARGO_BIN_EXE_aguda-rs"))
+        .args(["-f", &agu_path.to_string_lossy(), "-O", "1", "--overflow", "trap", "--emit", "ir", "--print-after", "lower-pow"])
+        .output()
+        .expect("failed to run the compiler");
+    let expected = fs::read_to_string(agu_path.with_extension("lower-pow.expect")).expect("failed to read expected file");
+    assert!(output.status.success(), "failed to compile {:?}", agu_path);
+    assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), expected.trim(), "wrong program after lower-pow");
+}
+
+// programs that compile with warnings, compared with the expected file, which holds each warning
+// followed by the source it points to
+#[test]
+fn test_warnings() {
+    let mut failed = 0;
+    for entry in fs::read_dir("./tests/warning").expect("failed to read base test directory") {
+        let path = entry.expect("invalid entry").path();
+        let agu_path = fs::read_dir(&path)
+            .expect("failed to read test directory")
+            .map(|entry| entry.expect("invalid entry").path())
+            .find(|p| p.extension().is_some_and(|ext| ext == "agu"))
+            .expect("no .agu file found");
+        let src = fs::read_to_string(&agu_path).expect("failed to read file");
+        let (_, warnings) = check_aguda_program(&src).unwrap_or_else(|e| panic!("failed to check {:?}: {:?}", agu_path, e));
+        let found = warnings
+            .iter()
+            .map(|warning| {
+                let (kind, span) = match warning {
+                    Warning::UnusedIdentifier(sym) => ("UnusedIdentifier", &sym.span),
+                    Warning::MisplacedTailCall(call) => ("MisplacedTailCall", &call.span),
+                    Warning::ConstantOverflow(op) => ("ConstantOverflow", &op.span),
+                    Warning::ConstantDivisionByZero(op) => ("ConstantDivisionByZero", &op.span),
+                };
+                format!("{}: {}", kind, &src[span.clone()])
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let expected = fs::read_to_string(agu_path.with_extension("expect")).expect("failed to read expected file");
+        if found != expected {
+            println!("❌  {:?}: expected\n{}\nfound\n{}", agu_path, expected, found);
+            failed += 1;
+        }
+    }
+    assert_eq!(failed, 0, "Some warning tests failed");
+}
+
+// control-flow graphs broken by hand, which the ir verifier must reject
+#[test]
+fn test_ir_verifier() {
+    let agu_path = Path::new("./tests/emit/64371_double/double.agu");
+    let src = fs::read_to_string(agu_path).expect("failed to read file");
+    let (ast, _) = check_aguda_program(&src).unwrap_or_else(|e| panic!("failed to check {:?}: {:?}", agu_path, e));
+    let ir = lower(&ast);
+    verify(&ir).unwrap_or_else(|e| panic!("the ir of {:?} is invalid: {}", agu_path, e));
+
+    // the entry block of double jumps to its start block, which defines the temporaries and returns
+    let double = ir.functions.iter().position(|fun| fun.name == "double").expect("no function double");
+    let breaks = [
+        ("no terminator", (|entry| entry.term = None) as fn(&mut Block)),
+        ("jump to an undefined block", |entry| entry.term.as_mut().unwrap().value = Terminator::Jump(99)),
+        ("temporary used before its definition", |entry| {
+            entry.term.as_mut().unwrap().value = Terminator::Return(Operand::Temp(3))
+        }),
+    ];
+    for (problem, break_entry) in breaks {
+        let mut broken = ir.clone();
+        break_entry(&mut broken.functions[double].blocks[0]);
+        assert!(verify(&broken).is_err(), "the ir verifier accepted a block with {}", problem);
+    }
+}
+
+// output of the compiler for the program, which must compile
+fn emit(agu_path: &Path, options: &CompileOptions) -> Vec<u8> {
+    let src = fs::read_to_string(agu_path).unwrap_or_else(|e| panic!("failed to read file {:?}: {}", agu_path, e));
+    let (output, _) = compile_aguda_program(&src, &agu_path.to_string_lossy(), options)
+        .unwrap_or_else(|e| panic!("failed to compile {:?}: {:?}", agu_path, e));
+    output
+}
+
+fn emit_text(agu_path: &Path, options: &CompileOptions) -> String {
+    String::from_utf8(emit(agu_path, options)).unwrap_or_else(|_| panic!("the output for {:?} is not text", agu_path))
+}
+
+// hand-written bytecode that the compiler never generates, which the vm must reject instead of panicking
+#[test]
+fn test_invalid_bytecode() {
+    let rejected = [
+        vec![Instr::Add, Instr::Return],
+        vec![Instr::Fun(0), Instr::Int(1), Instr::Call(2), Instr::Return],
+        vec![Instr::Bool(true), Instr::JumpIfFalse(3), Instr::Int(1), Instr::Return],
+    ];
+    for code in rejected {
+        let bytes = encode(&bytecode_with_main(code.clone()));
+        assert!(load_bytecode(&bytes).is_err(), "bytecode {:?} was loaded", code);
+    }
+
+    let failing = [
+        vec![Instr::Int(1), Instr::Bool(true), Instr::Add, Instr::Return],
+        vec![Instr::Unit, Instr::Length, Instr::Return],
+        vec![Instr::Int(1), Instr::Int(2), Instr::Call(1), Instr::Return],
+        vec![Instr::Fun(0), Instr::Int(1), Instr::Call(1), Instr::Return],
+    ];
+    for code in failing {
+        let bytes = encode(&bytecode_with_main(code.clone()));
+        let bytecode = load_bytecode(&bytes).unwrap_or_else(|e| panic!("bytecode {:?} was rejected: {:?}", code, e.kind));
+        match run_bytecode(&bytecode, &CompileOptions::default()) {
+            Err(e) => assert!(matches!(e.kind, RuntimeErrorKind::Unknown(_)), "bytecode {:?} failed with {:?}", code, e.kind),
+            Ok(_) => panic!("bytecode {:?} did not fail", code),
+        }
+    }
+}
+
+// program whose main runs the code, with an initializer that does nothing
+fn bytecode_with_main(code: Vec<Instr>) -> Bytecode {
+    let function = |name: &str, params, code: Vec<Instr>| Function {
+        name: name.to_string(),
+        params,
+        captures: 0,
+        locals: params,
+        pos: ((1, 1), (1, 2)),
+        positions: vec![((1, 1), (1, 2)); code.len()],
+        code,
+    };
+    Bytecode {
+        file: "test.agu".to_string(),
+        src: String::new(),
+        strings: vec![],
+        globals: vec![],
+        functions: vec![function("init", 0, vec![Instr::Unit, Instr::Return]), function("main", 1, code)],
+        init: 0,
+        main: 1,
+    }
+}
+
+// builds the program into an executable in the temporary directory, named after the test with the prefix, and runs it
+fn run_executable(src: &str, agu_path: &Path, prefix: &str, options: &CompileOptions) -> RunResult {
+    let dir = agu_path.parent().unwrap();
+    let exe_path = std::env::temp_dir().join(format!("{}_{}", prefix, dir.file_name().unwrap().to_string_lossy()));
+    let options = CompileOptions { output: Some(exe_path.to_string_lossy().to_string()), ..options.clone() };
+    compile_aguda_program(src, &agu_path.to_string_lossy(), &options)
+        .map_err(|e| format!("failed to compile {:?}: {:?}", agu_path, e))?;
+    let output = run_aguda_program(&exe_path);
+    let _ = fs::remove_file(&exe_path);
+    let output = output.map_err(|e| format!("failed to run {:?}: {:?}", agu_path, e.kind))?;
+    Ok((output.stdout, output.status.code()))
+}
+
+// compiles the program to bytecode, which is encoded and decoded before running in the vm
+fn run_in_vm(src: &str, agu_path: &Path, options: &CompileOptions) -> RunResult {
+    let options = CompileOptions { emit: Emit::Bytecode, ..options.clone() };
+    let (bytes, _) = compile_aguda_program(src, &agu_path.to_string_lossy(), &options)
+        .map_err(|e| format!("failed to compile {:?}: {:?}", agu_path, e))?;
+    let bytecode = load_bytecode(&bytes).map_err(|e| format!("failed to load {:?}: {:?}", agu_path, e.kind))?;
+    let (stdout, status) = run_bytecode(&bytecode, &options)
+        .map_err(|e| format!("failed to run {:?}: {:?}", agu_path, e.kind))?;
+    Ok((stdout, Some(status)))
+}
+
+// output and exit status of a program, or why it could not run
+type RunResult = Result<(Vec<u8>, Option<i32>), String>;
+
+// runs every valid program with the runner, given its source and path, and checks its output and exit status,
+// returning the number of failed tests
+fn for_each_valid_test(run: impl Fn(&str, &Path) -> RunResult) -> usize {
+    for_each_test_in("./tests/valid", run)
+}
+
+fn for_each_test_in(dir: &str, run: impl Fn(&str, &Path) -> RunResult) -> usize {
+    let mut failed = 0;
+    for entry in fs::read_dir(dir).expect("failed to read base test directory") {
+        let path = entry.expect("invalid entry").path();
+        if !path.is_dir() {
+            continue;
+        }
+        if let Err(err) = run_valid_test(&path, &run) {
+            println!("❌  {}", err);
+            failed += 1;
+        }
+    }
+    failed
+}
+
+fn run_valid_test(dir: &Path, run: &impl Fn(&str, &Path) -> RunResult) -> Result<(), String> {
+    let agu_path = fs::read_dir(dir)
+        .map_err(|e| format!("failed to read dir {:?}: {}", dir, e))?
+        .map(|entry| entry.expect("invalid entry").path())
+        .find(|p| p.extension().is_some_and(|ext| ext == "agu"))
+        .ok_or_else(|| format!("no .agu file found in {:?}", dir))?;
+    let src = fs::read_to_string(&agu_path).map_err(|e| format!("failed to read file {:?}: {}", agu_path, e))?;
+    let expected = fs::read_to_string(agu_path.with_extension("expect"))
+        .map_err(|e| format!("failed to read expected output of {:?}: {}", agu_path, e))?;
+    let (stdout, status) = run(&src, &agu_path)?;
+    check_output(&agu_path, &expected, &String::from_utf8_lossy(&stdout), status)
+}
+
+// programs that compile but must stop with a runtime error
+fn test_runtime_errors_in_dir(dir: &Path) -> (i32, i32) {
+    assert!(dir.exists(), "Test directory not found");
+    let mut passed = 0;
+    let mut failed = 0;
+    for entry in fs::read_dir(dir).expect("failed to read base test directory") {
+        let path = entry.expect("invalid entry").path();
+        if !path.is_dir() {
+            continue;
+        }
+        let agu_path = fs::read_dir(&path)
+            .expect("failed to read test directory")
+            .map(|entry| entry.expect("invalid entry").path())
+            .find(|p| p.extension().is_some_and(|ext| ext == "agu"))
+            .expect("no .agu file found");
+        let src = fs::read_to_string(&agu_path).expect("failed to read file");
+        let file = agu_path.to_string_lossy();
+        // integer overflow is only detected in trap mode
+        let options = CompileOptions { overflow: Overflow::Trap, ..exe_options(&path) };
+        let exe_path = options.exe_path(&file);
+        let result = compile_aguda_program(&src, &file, &options)
+            .map_err(|_| "failed to compile".to_string())
+            .and_then(|_| {
+                let output = run_aguda_program(&exe_path);
+                let _ = fs::remove_file(&exe_path);
+                // the expected file holds the kind of the error
+                let expected = fs::read_to_string(agu_path.with_extension("expect")).expect("failed to read expected file");
+                // the location printed by the program must cover the same expression as in the interpreter
+                let (ast, _) = check_aguda_program(&src).map_err(|_| "failed to check".to_string())?;
+                let interpreted = interpret_aguda_program(&ast, &src, &options).err().and_then(|e| e.pos);
+                match output {
+                    Ok(_) => Err("program did not fail".to_string()),
+                    Err(e) if e.pos.is_none() => Err(format!("runtime error without location: {:?}", e.kind)),
+                    Err(e) if format!("{:?}", e.kind) != expected => Err(format!("expected {}, found {:?}", expected, e.kind)),
+                    Err(e) if e.pos != interpreted => Err(format!("expected location {:?}, found {:?}", interpreted, e.pos)),
+                    Err(_) => Ok(()),
+                }
+            });
+        match result {
+            Ok(_) => passed += 1,
+            Err(err) => {
+                println!("❌  {:?}: {}", agu_path, err);
+                failed += 1;
+            }
+        }
+    }
+    (passed, failed)
 }
 
 fn test_agu_files_in_dir(dir: &Path, valid: bool) -> (i32, i32) {
@@ -79,7 +468,8 @@ fn test_agu_file_in_dir(dir: &Path) -> Result<(), String> {
     let src = fs::read_to_string(&agu_path)
         .map_err(|e| format!("failed to read file {:?}: {}", agu_path, e))?;
 
-    let result = std::panic::catch_unwind(|| compile_aguda_program(&src, &agu_path.to_string_lossy(), 0));
+    let options = exe_options(dir);
+    let result = std::panic::catch_unwind(|| compile_aguda_program(&src, &agu_path.to_string_lossy(), &options));
     if result.is_err() {
         return Err(format!("failed to compile: {:?}", agu_path));
     }
@@ -89,15 +479,12 @@ fn test_agu_file_in_dir(dir: &Path) -> Result<(), String> {
             let expected = fs::read_to_string(&expected_file)
                 .map_err(|e| format!("failed to read expected output file {:?}: {}", expected_file, e))?;
 
-            let output = run_aguda_program(&agu_path.to_str().unwrap());
+            let exe_path = options.exe_path(&agu_path.to_string_lossy());
+            let output = run_aguda_program(&exe_path);
+            let _ = fs::remove_file(&exe_path);
             match output {
                 Ok(output) => {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    if stdout.trim() == expected.trim() {
-                        Ok(())
-                    } else {
-                        Err(format!("wrong output for: {:?}:\nexpected:\n{}\ngot:\n{}", agu_path, expected, stdout))
-                    }
+                    check_output(&agu_path, &expected, &String::from_utf8_lossy(&output.stdout), output.status.code())
                 },
                 Err(e) => {
                     let errors = vec![AgudaError::from(e)];
@@ -110,4 +497,29 @@ fn test_agu_file_in_dir(dir: &Path) -> Result<(), String> {
             Err(format_aguda_errors(errors, 1, true, &agu_path.to_string_lossy(), &src))
         }
     }
+}
+
+// compares the output of the program with the expected one, and its exit status with the one
+// in the .status file of the test, or 0 if there is none
+fn check_output(agu_path: &Path, expected: &str, stdout: &str, status: Option<i32>) -> Result<(), String> {
+    if stdout.trim() != expected.trim() {
+        return Err(format!("wrong output for: {:?}:\nexpected:\n{}\ngot:\n{}", agu_path, expected, stdout));
+    }
+    let expected_status = match fs::read_to_string(agu_path.with_extension("status")) {
+        Ok(status) => status.trim().parse().map_err(|_| format!("invalid exit status of {:?}", agu_path))?,
+        Err(_) => 0,
+    };
+    if status != Some(expected_status) {
+        return Err(format!("wrong exit status for: {:?}: expected {}, got {:?}", agu_path, expected_status, status));
+    }
+    Ok(())
+}
+
+// executable of the test built in the temporary directory, so that tests never build the same path
+fn exe_options(dir: &Path) -> CompileOptions {
+    let exe_path = std::env::temp_dir().join(format!("aguda_{}", dir.file_name().unwrap().to_string_lossy()));
+    CompileOptions {
+        output: Some(exe_path.to_string_lossy().to_string()),
+        ..CompileOptions::default()
+    }
 }
\ No newline at end of file